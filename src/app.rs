@@ -1,110 +1,1545 @@
 use eframe::egui;
 use image::RgbImage;
 
+use error::AppError;
+
+pub mod calculator;
+pub mod chromaticity;
+pub mod clipboard;
+pub mod color_space;
+pub mod compare;
+pub mod error;
+pub mod export;
 pub mod hist;
+pub mod history;
+pub mod hsv_wheel;
+pub mod icc;
 pub mod image_op;
+pub mod inspector;
+pub mod jobs;
+pub mod metadata;
+pub mod notifications;
+pub mod point_cloud;
+pub mod recent;
+pub mod scopes;
+pub mod session;
+pub mod tone_map;
+pub mod viewer;
 
-#[derive(Default)]
+/// Имя приложения, под ним eframe хранит настройки
+pub const APP_NAME: &str = "ColorsApp";
+
+/// Ключ настроек в хранилище eframe
+const SETTINGS_KEY: &str = "settings";
+
+#[derive(Default, Clone, Copy, serde::Serialize, serde::Deserialize)]
+#[allow(clippy::upper_case_acronyms)]
 enum Task {
     #[default]
     Grayscale,
     RGBChannels,
     HSV,
+    ToneMapping,
+    ColorSpace,
+}
+
+/// Настройки, сохраняемые между запусками
+#[derive(serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+struct Settings {
+    theme: egui::ThemePreference,
+    /// Папка, из которой последний раз открывали картинку или куда сохраняли
+    last_dir: Option<std::path::PathBuf>,
+    task: Task,
+    scope: scopes::ScopeKind,
+    histogram: hist::HistogramOptions,
+    /// Открывать при запуске картинку и историю прошлого сеанса
+    restore_session: bool,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            theme: egui::ThemePreference::Light,
+            last_dir: None,
+            task: Task::default(),
+            scope: scopes::ScopeKind::default(),
+            histogram: hist::HistogramOptions::default(),
+            restore_session: false,
+        }
+    }
+}
+
+/// Настройки вывода разности картинок
+struct DiffSettings {
+    /// Вид разности для второй картинки
+    view: image_op::DiffView,
+    /// Усиление для усиленной разности и разности со знаком
+    gain: f32,
+    /// Растягивать наибольшую разность до 255 вместо ручного усиления
+    auto_stretch: bool,
+}
+
+impl Default for DiffSettings {
+    fn default() -> Self {
+        Self {
+            view: image_op::DiffView::Abs,
+            gain: 8.0,
+            auto_stretch: false,
+        }
+    }
 }
 
+/// Настройки перевода картинки в другое пространство
 #[derive(Default)]
-pub struct ColorsApp {
+struct ConvertSettings {
+    target: color_space::RgbSpace,
+}
+
+/// Приведение цветов, вышедших за охват, и их подсветка
+struct GamutSettings {
+    /// Способ приведения для HSV, перевода пространства и тональной компрессии
+    mapping: color_space::GamutMapping,
+    /// Подсвечивать пиксели, вышедшие за охват
+    show_overlay: bool,
+    overlay_color: [u8; 3],
+}
+
+impl Default for GamutSettings {
+    fn default() -> Self {
+        Self {
+            mapping: color_space::GamutMapping::default(),
+            show_overlay: true,
+            overlay_color: [255, 0, 255],
+        }
+    }
+}
+
+/// Что делать с прозрачностью загруженной картинки
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+enum AlphaMode {
+    /// Операции не трогают альфа-канал, результат сохраняет прозрачность оригинала
+    #[default]
+    Keep,
+    /// Наложить картинку на фон перед обработкой, результат непрозрачен
+    Flatten,
+}
+
+/// Настройки работы с альфа-каналом
+#[derive(Clone, Copy)]
+struct AlphaSettings {
+    mode: AlphaMode,
+    /// Фон для наложения
+    background: [u8; 3],
+}
+
+impl Default for AlphaSettings {
+    fn default() -> Self {
+        Self {
+            mode: AlphaMode::Keep,
+            background: [255, 255, 255],
+        }
+    }
+}
+
+/// Операция над загруженной картинкой, которую можно повторить на других открытых картинках
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Adjustment {
+    Original,
+    Grayscale1,
+    Grayscale2,
+    /// Один канал: 0 - красный, 1 - зелёный, 2 - синий
+    Channel(usize),
+    Hsv(i32, i16, i16),
+    ToneMap(tone_map::ToneSettings),
+    Convert(color_space::RgbSpace),
+}
+
+impl Adjustment {
+    /// Название для истории
+    fn name(self) -> String {
+        match self {
+            Adjustment::Original => "Original".to_string(),
+            Adjustment::Grayscale1 => "Grayscale1".to_string(),
+            Adjustment::Grayscale2 => "Grayscale2".to_string(),
+            Adjustment::Channel(0) => "Red channel".to_string(),
+            Adjustment::Channel(1) => "Green channel".to_string(),
+            Adjustment::Channel(_) => "Blue channel".to_string(),
+            Adjustment::Hsv(..) => "HSV".to_string(),
+            Adjustment::ToneMap(settings) => format!("Tone map {}", settings.operator.name()),
+            Adjustment::Convert(to) => format!("Convert to {}", to.name()),
+        }
+    }
+}
+
+/// Результат фоновой операции и состояние картинки, которое он описывает.
+/// Состояние меняется, только когда результат выведен.
+struct JobResult {
+    output: jobs::JobOutput,
+    /// Операция для повтора на других картинках, None - не повторяется
+    adjustment: Option<Adjustment>,
+    /// Приведение охвата, с которым посчитан результат
+    mapping: color_space::GamutMapping,
+    /// Линейный буфер и гистограмма, с которыми посчитана тональная компрессия
+    tone: Option<(std::sync::Arc<Vec<f32>>, tone_map::ToneMapper)>,
+    /// Метрики качества для выведенной разности картинок
+    metrics: Option<image_op::ImageMetrics>,
+}
+
+impl JobResult {
+    fn new(
+        output: jobs::JobOutput,
+        adjustment: Adjustment,
+        mapping: color_space::GamutMapping,
+    ) -> Self {
+        Self {
+            output,
+            adjustment: Some(adjustment),
+            mapping,
+            tone: None,
+            metrics: None,
+        }
+    }
+}
+
+/// Пиксели картинки, переведённые в рабочее пространство в фоне
+struct Prepared {
+    image: RgbImage,
+    precise: Option<Vec<f32>>,
+    /// Почему пиксели не удалось перевести
+    warning: Option<String>,
+}
+
+/// Настройки окна, с которыми обрабатывается каждая открытая картинка
+struct Processing {
+    /// Пространство, в котором работают операции
+    space: color_space::RgbSpace,
+    mapping: color_space::GamutMapping,
+    alpha: AlphaSettings,
+    /// Цвет подсветки пикселей вне охвата
+    overlay_color: [u8; 3],
+}
+
+/// Что изменилось в картинке после вывода результатов фоновых заданий
+#[derive(Default)]
+struct Polled {
+    /// Выведен новый результат
+    shown: bool,
+    /// Чем закончилась запись картинки в файл
+    saved: Option<Result<(std::path::PathBuf, Vec<String>), AppError>>,
+}
+
+/// Открытая картинка и состояние её обработки
+#[derive(Default)]
+struct ImageState {
+    /// Картинка как она записана в файле, без альфа-канала и перевода в sRGB
+    decoded_image: Option<image::RgbImage>,
+    /// Точный буфер как он записан в файле
+    decoded_precise: Option<Vec<f32>>,
+    /// ICC профиль загруженной картинки
+    color: icc::ColorManagement,
+    /// EXIF загруженной картинки
+    metadata: metadata::ImageMetadata,
+    /// Почему пиксели не удалось перевести в рабочее пространство
+    profile_warning: Option<String>,
+    /// Загруженная картинка без альфа-канала, с которой работают операции
     loaded_image: Option<image::RgbImage>,
+    /// Загруженная картинка в рабочем пространстве до наложения на фон
+    source_image: Option<image::RgbImage>,
+    /// Альфа-канал загруженной картинки, None - картинка непрозрачна
+    loaded_alpha: Option<Vec<u8>>,
+    /// Глубина цвета загруженной картинки
+    loaded_depth: image_op::SampleDepth,
+    /// RGB буфер загруженной картинки 16 бит или с плавающей точкой, [0, 1] для целых.
+    /// None для 8-битных картинок.
+    loaded_precise: Option<Vec<f32>>,
+    /// Точный буфер до наложения на фон
+    source_precise: Option<Vec<f32>>,
     cur_image: Option<Vec<u8>>,
     cur_image_size: Option<(usize, usize)>,
+    /// Альфа-канал выводимой картинки
+    cur_alpha: Option<Vec<u8>>,
+    /// Выводимая картинка с точностью исходника, если операция её сохранила
+    cur_precise: Option<Vec<f32>>,
+    /// Пространство, в котором записаны пиксели выводимой картинки
+    cur_space: color_space::RgbSpace,
     cur_image_texture: Option<egui::TextureHandle>,
+    /// Пиксели выводимой картинки, вышедшие за охват при операции
+    out_of_gamut: Option<Vec<bool>>,
+    gamut_overlay: Option<egui::TextureHandle>,
     image_path: Option<std::path::PathBuf>,
-    task: Task,
-    histogram: hist::RGBHistogram,
+    /// Результаты операций для сравнения между собой
+    history: history::History,
+    view: viewer::ImageView,
+    comparison: compare::Comparison,
+    inspector: inspector::Inspector,
+    /// Метрики качества для выведенной разности картинок
+    metrics: Option<image_op::ImageMetrics>,
+    /// Ошибка последней операции сравнения для вывода в UI
+    error: Option<AppError>,
+    tone_mapper: tone_map::ToneMapper,
+    /// Линейный RGB буфер загруженной картинки для тональной компрессии
+    tone_linear: Option<std::sync::Arc<Vec<f32>>>,
     // HSV
     hue: i32,
     saturation: i16,
     value: i16,
+    /// Параметры выведенного результата HSV, чтобы не считать его каждый кадр
+    hsv_shown: Option<(i32, i16, i16, color_space::GamutMapping)>,
+    /// Последняя операция, которую можно повторить на других картинках
+    adjustment: Option<Adjustment>,
+    /// Последняя запущенная операция и приведение охвата для неё.
+    /// Отменённая операция не запускается панелью заново с теми же параметрами.
+    requested: Option<(Adjustment, color_space::GamutMapping)>,
+    /// Операция, выполняющаяся в фоне
+    jobs: jobs::Jobs<Result<JobResult, AppError>>,
+    /// Перевод в рабочее пространство, выполняющийся в фоне
+    prepare: jobs::Jobs<Prepared>,
+    /// Запись выводимой картинки в файл, выполняющаяся в фоне
+    /// Результат - файл и метаданные, которые не удалось в него встроить
+    saving: jobs::Jobs<Result<(std::path::PathBuf, Vec<String>), AppError>>,
+    /// Сеанс, история которого восстанавливается, когда картинка переведена
+    session: Option<session::Session>,
 }
 
-impl ColorsApp {
-    pub fn new(cc: &eframe::CreationContext<'_>) -> Self {
-        cc.egui_ctx.set_theme(egui::Theme::Light);
-        Self::default()
+impl ImageState {
+    /// Разобрать открытую картинку: пиксели как в файле, прозрачность, профиль и EXIF
+    fn decode(&mut self, opened: OpenedImage) {
+        let OpenedImage {
+            image: img,
+            icc_profile,
+            exif,
+            orientation,
+        } = opened;
+        let rgb = if img.color().has_alpha() {
+            let (rgb, alpha) = image_op::split_alpha(img.to_rgba8().as_raw());
+            self.loaded_alpha = Some(alpha);
+            RgbImage::from_raw(img.width(), img.height(), rgb).unwrap()
+        } else {
+            self.loaded_alpha = None;
+            img.to_rgb8()
+        };
+        self.loaded_depth = sample_depth(img.color());
+        self.decoded_precise =
+            (self.loaded_depth != image_op::SampleDepth::U8).then(|| img.to_rgb32f().into_raw());
+        self.decoded_image = Some(rgb);
+        self.color.set_embedded(icc_profile.as_deref());
+        self.metadata.set(exif, orientation);
     }
 
-    /// Загрузить файл с картинкой из файловой системы
-    fn load_image(&mut self, ctx: &egui::Context) {
-        let path = rfd::FileDialog::new()
-            .add_filter("Images", &["png", "jpg", "jpeg", "bmp", "tga", "tiff"])
-            .pick_file();
+    /// Перевести пиксели из профиля картинки в рабочее пространство space в фоне.
+    /// Пока перевод идёт, операции над картинкой недоступны.
+    fn start_conversion(&mut self, ctx: &egui::Context, space: color_space::RgbSpace) {
+        let Some(job) = self.conversion_job(space) else {
+            return;
+        };
+        // результат операций над старыми пикселями уже не нужен
+        self.jobs.cancel();
+        self.source_image = None;
+        self.source_precise = None;
+        self.loaded_image = None;
+        self.loaded_precise = None;
+        self.prepare
+            .spawn(ctx, format!("Convert to {}", space.name()), job);
+    }
+
+    /// Задание перевода пикселей в рабочее пространство space; None - картинки нет
+    fn conversion_job(
+        &self,
+        space: color_space::RgbSpace,
+    ) -> Option<impl FnOnce(&jobs::Progress) -> Option<Prepared> + Send + 'static> {
+        let mut image = self.decoded_image.clone()?;
+        let mut precise = self.decoded_precise.clone();
+        let hdr = self.loaded_depth == image_op::SampleDepth::F32;
+        let has_profile = self.color.label().is_some();
+        let conversion = self.color.conversion(space);
+
+        Some(move |progress: &jobs::Progress| {
+            let mut warning = None;
+            let done = if hdr {
+                // линейные HDR данные не кодируются кривой профиля, их основные цвета - sRGB
+                if has_profile {
+                    warning = Some("Профиль не применяется к HDR картинкам".to_string());
+                }
+                let from = color_space::RgbSpace::Srgb;
+                let clip = color_space::GamutMapping::Clip;
+                jobs::for_chunks(&mut image, 3, progress, (0.0, 0.5), |chunk| {
+                    color_space::convert_buffer(chunk, from, space, clip);
+                }) && precise.as_mut().is_none_or(|precise| {
+                    jobs::for_chunks(precise, 3, progress, (0.5, 1.0), |chunk| {
+                        color_space::convert_f32_buffer(chunk, from, space, clip, true);
+                    })
+                })
+            } else {
+                let applied = conversion.and_then(|conversion| match conversion {
+                    Some(conversion) => {
+                        conversion.apply(&mut image, precise.as_deref_mut(), progress)
+                    }
+                    None => Ok(true),
+                });
+                applied.unwrap_or_else(|e| {
+                    warning = Some(e);
+                    true
+                })
+            };
+            done.then_some(Prepared {
+                image,
+                precise,
+                warning,
+            })
+        })
+    }
+
+    /// Запомнить переведённые в рабочее пространство пиксели
+    fn set_prepared(&mut self, prepared: Prepared) {
+        self.source_image = Some(prepared.image);
+        self.source_precise = prepared.precise;
+        self.profile_warning = prepared.warning;
+    }
+
+    /// Подготовить картинку для операций: наложить на фон или оставить как есть
+    fn apply_alpha(&mut self, settings: &AlphaSettings) {
+        let mut image = self.source_image.clone();
+        let mut precise = self.source_precise.clone();
+        if settings.mode == AlphaMode::Flatten
+            && let Some(alpha) = &self.loaded_alpha
+        {
+            if let Some(image) = &mut image {
+                image_op::flatten_alpha(image, alpha, settings.background);
+            }
+            if let Some(precise) = &mut precise {
+                image_op::flatten_alpha_f32(precise, alpha, settings.background);
+            }
+        }
+        self.loaded_image = image;
+        self.loaded_precise = precise;
+        self.tone_linear = None;
+        self.hsv_shown = None;
+        self.requested = None;
+    }
 
-        if let Some(path) = path {
-            if let Ok(img) = image::open(&path) {
-                self.loaded_image = Some(img.to_rgb8());
-                self.cur_image = Some(img.to_rgb8().into_raw());
-                self.cur_image_size = Some((img.width() as usize, img.height() as usize));
-                self.image_path = Some(path);
-                self.histogram.update_data(self.cur_image.clone().unwrap());
-                self.update_texture(ctx);
+    /// Вывести результаты завершившихся фоновых заданий картинки
+    fn poll(
+        &mut self,
+        ctx: &egui::Context,
+        processing: &Processing,
+        notifications: &mut notifications::Notifications,
+    ) -> Polled {
+        let mut polled = Polled::default();
+        if let Some(prepared) = self.prepare.poll() {
+            self.show_prepared(ctx, prepared, processing, notifications);
+            polled.shown = true;
+        }
+        if let Some(result) = self.jobs.poll() {
+            match result {
+                Ok(result) => self.show_job_output(ctx, result, processing),
+                Err(e) => self.set_error(e, notifications),
+            }
+            polled.shown = true;
+        }
+        if let Some(result) = self.saving.poll() {
+            // записанный файл становится файлом картинки
+            if let Ok((path, _)) = &result {
+                self.image_path = Some(path.clone());
             }
+            polled.saved = Some(result);
+        }
+        polled
+    }
+
+    /// Подготовить переведённую картинку для операций и начать историю с неё
+    fn show_prepared(
+        &mut self,
+        ctx: &egui::Context,
+        prepared: Prepared,
+        processing: &Processing,
+        notifications: &mut notifications::Notifications,
+    ) {
+        self.set_prepared(prepared);
+        if let Some(warning) = &self.profile_warning {
+            notifications.warning(warning.clone());
+        }
+        self.apply_alpha(&processing.alpha);
+        self.restart_from_loaded(ctx, processing);
+        if let Some(session) = self.session.take() {
+            self.restore_session(ctx, &session, processing, notifications);
         }
     }
 
-    /// Обновить выводимую картинку и гистограмму
+    /// Вернуть историю операций прошлого сеанса
+    fn restore_session(
+        &mut self,
+        ctx: &egui::Context,
+        session: &session::Session,
+        processing: &Processing,
+        notifications: &mut notifications::Notifications,
+    ) {
+        let Some(size) = self.cur_image_size else {
+            return;
+        };
+        if let Err(e) = session.restore_into(&mut self.history, size) {
+            notifications.warning(format!("История прошлого сеанса не восстановлена: {e}"));
+            self.restart_from_loaded(ctx, processing);
+            return;
+        }
+        // выводится последний результат прошлого сеанса
+        if self.history.len() > 1
+            && let Some(last) = self.history.get(self.history.len() - 1)
+        {
+            self.cur_image = Some(last.image.clone());
+            self.cur_alpha = last.alpha.clone();
+            self.cur_space = last.space;
+            self.cur_precise = None;
+            self.cur_image_size = Some(last.size);
+            self.update_texture(ctx);
+        }
+    }
+
+    /// Вывести заново подготовленную картинку и начать историю с неё
+    fn restart_from_loaded(&mut self, ctx: &egui::Context, processing: &Processing) {
+        if let Some(image) = &self.loaded_image {
+            let size = image_size(image);
+            self.show_original(ctx, processing);
+            self.history.reset(
+                self.cur_image.clone().unwrap(),
+                self.cur_alpha.clone(),
+                size,
+                self.cur_space,
+            );
+            self.comparison.reset_selection();
+        }
+    }
+
+    /// Вывести загруженную картинку без изменений, отменив операцию в фоне
+    fn show_original(&mut self, ctx: &egui::Context, processing: &Processing) {
+        self.jobs.cancel();
+        let Some(orig_image) = &self.loaded_image else {
+            return;
+        };
+        let size = image_size(orig_image);
+        let (buf, precise) = match &self.loaded_precise {
+            Some(precise) => (image_op::f32_buffer_to_u8(precise), Some(precise.clone())),
+            None => (orig_image.as_raw().clone(), None),
+        };
+        let name = Adjustment::Original.name();
+        let output = jobs::JobOutput {
+            name,
+            buf,
+            precise,
+            size,
+            space: processing.space,
+            out_of_gamut: None,
+        };
+        self.show_result_precise(ctx, output, &processing.alpha);
+    }
+
+    /// Вывести результат фоновой операции и запомнить состояние, которое он описывает
+    fn show_job_output(&mut self, ctx: &egui::Context, result: JobResult, processing: &Processing) {
+        let mut output = result.output;
+        let mask = output.out_of_gamut.take();
+        self.show_result_precise(ctx, output, &processing.alpha);
+        if let Some(mask) = mask {
+            self.set_out_of_gamut(ctx, mask, processing.overlay_color);
+        }
+        self.adjustment = result.adjustment;
+        if let Some(Adjustment::Hsv(h, s, v)) = result.adjustment {
+            self.hue = h;
+            self.saturation = s;
+            self.value = v;
+            self.hsv_shown = Some((h, s, v, result.mapping));
+        }
+        if let Some((linear, mapper)) = result.tone {
+            self.tone_linear = Some(linear);
+            self.tone_mapper = mapper;
+        }
+        // метрики выставляются после вывода, который их сбрасывает
+        if let Some(metrics) = result.metrics {
+            self.metrics = Some(metrics);
+            self.error = None;
+        }
+    }
+
+    /// Вывести результат операции вместе с его точным буфером и запомнить его в истории
+    fn show_result_precise(
+        &mut self,
+        ctx: &egui::Context,
+        output: jobs::JobOutput,
+        alpha: &AlphaSettings,
+    ) {
+        let jobs::JobOutput {
+            name,
+            buf,
+            precise,
+            size,
+            space,
+            ..
+        } = output;
+        self.cur_precise = precise;
+        self.cur_alpha = self.result_alpha(size, alpha);
+        self.cur_space = space;
+        self.out_of_gamut = None;
+        self.gamut_overlay = None;
+        self.history
+            .push(&name, buf.clone(), self.cur_alpha.clone(), size, space);
+        self.cur_image_size = Some(size);
+        self.cur_image = Some(buf);
+        self.update_texture(ctx);
+    }
+
+    /// Альфа-канал для результата операции размера size.
+    /// Прозрачность оригинала сохраняется, если размер не изменился.
+    fn result_alpha(&self, size: (usize, usize), alpha: &AlphaSettings) -> Option<Vec<u8>> {
+        if alpha.mode != AlphaMode::Keep {
+            return None;
+        }
+        let image = self.loaded_image.as_ref()?;
+        (image_size(image) == size)
+            .then(|| self.loaded_alpha.clone())
+            .flatten()
+    }
+
+    /// Обновить текстуру выводимой картинки.
+    /// Метрики сбрасываются, их выставляет только вывод разности.
     fn update_texture(&mut self, ctx: &egui::Context) {
+        self.metrics = None;
         if let Some(raw_data) = &self.cur_image {
-            let size = [
-                self.cur_image_size.unwrap().0,
-                self.cur_image_size.unwrap().1,
-            ];
+            let size = self.cur_image_size.unwrap();
             let texture = ctx.load_texture(
                 "cur_image",
-                egui::ColorImage::from_rgb(size, raw_data),
-                Default::default(),
+                viewer::color_image(raw_data, self.cur_alpha.as_deref(), size, self.cur_space),
+                viewer::TEXTURE_OPTIONS,
             );
             self.cur_image_texture = Some(texture);
+        }
+    }
+
+    /// Запомнить пиксели выведенной картинки, вышедшие за охват, и подсветку для них
+    fn set_out_of_gamut(&mut self, ctx: &egui::Context, mask: Vec<bool>, color: [u8; 3]) {
+        self.out_of_gamut = mask.contains(&true).then_some(mask);
+        self.update_gamut_overlay(ctx, color);
+    }
+
+    /// Подсветка пикселей вне охвата цветом color
+    fn update_gamut_overlay(&mut self, ctx: &egui::Context, [r, g, b]: [u8; 3]) {
+        self.gamut_overlay = match (&self.out_of_gamut, self.cur_image_size) {
+            (Some(mask), Some(size)) => {
+                let color = egui::Color32::from_rgb(r, g, b);
+                Some(ctx.load_texture(
+                    "gamut_overlay",
+                    viewer::mask_overlay(mask, size, color),
+                    viewer::TEXTURE_OPTIONS,
+                ))
+            }
+            _ => None,
+        };
+    }
+
+    /// Запомнить ошибку операции сравнения и сообщить о ней
+    fn set_error(&mut self, error: AppError, notifications: &mut notifications::Notifications) {
+        notifications.error(&error);
+        self.error = Some(error);
+    }
+
+    /// Применить операцию к картинке; true, если результат выведен сразу.
+    /// Операция запоминается для остальных картинок, когда выведен её результат.
+    fn apply_adjustment(
+        &mut self,
+        ctx: &egui::Context,
+        adjustment: Adjustment,
+        processing: &Processing,
+    ) -> bool {
+        // картинка ещё переводится в рабочее пространство
+        if self.loaded_image.is_none() {
+            return false;
+        }
+        self.requested = Some((adjustment, processing.mapping));
+        match adjustment {
+            Adjustment::Original => {
+                self.show_original(ctx, processing);
+                self.adjustment = Some(adjustment);
+                return true;
+            }
+            Adjustment::Grayscale1 => self.show_operation(
+                ctx,
+                adjustment,
+                image_op::rgb_buffer_to_grayscale1,
+                image_op::rgb_f32_buffer_to_grayscale1,
+                processing,
+            ),
+            Adjustment::Grayscale2 => self.show_operation(
+                ctx,
+                adjustment,
+                image_op::rgb_buffer_to_grayscale2,
+                image_op::rgb_f32_buffer_to_grayscale2,
+                processing,
+            ),
+            Adjustment::Channel(channel) => {
+                let op: fn(&mut [u8]) = match channel {
+                    0 => image_op::rgb_buffer_to_red_channel,
+                    1 => image_op::rgb_buffer_to_green_channel,
+                    _ => image_op::rgb_buffer_to_blue_channel,
+                };
+                let precise_op =
+                    move |buf: &mut [f32]| image_op::rgb_f32_buffer_to_channel(buf, channel);
+                self.show_operation(ctx, adjustment, op, precise_op, processing);
+            }
+            Adjustment::Hsv(h, s, v) => self.show_hsv(ctx, h, s, v, processing),
+            Adjustment::ToneMap(settings) => self.show_tone_mapped(ctx, settings, processing),
+            Adjustment::Convert(to) => self.show_converted(ctx, to, processing),
+        }
+        false
+    }
+
+    /// Применить операцию к загруженной картинке.
+    /// Для 16-битных и HDR картинок операция выполняется над точным буфером.
+    /// Операция попиксельная и выполняется в фоне, результат выводится, когда готов.
+    fn show_operation(
+        &mut self,
+        ctx: &egui::Context,
+        adjustment: Adjustment,
+        op: impl Fn(&mut [u8]) + Send + 'static,
+        precise_op: impl Fn(&mut [f32]) + Send + 'static,
+        processing: &Processing,
+    ) {
+        let Some(orig_image) = &self.loaded_image else {
+            return;
+        };
+        let size = image_size(orig_image);
+        let (space, mapping) = (processing.space, processing.mapping);
+        let name = adjustment.name();
+        match &self.loaded_precise {
+            Some(precise) => {
+                let mut precise = precise.clone();
+                self.jobs.spawn(ctx, name.clone(), move |progress| {
+                    if !jobs::for_chunks(&mut precise, 3, progress, (0.0, 1.0), precise_op) {
+                        return None;
+                    }
+                    let output = jobs::JobOutput {
+                        name,
+                        buf: image_op::f32_buffer_to_u8(&precise),
+                        precise: Some(precise),
+                        size,
+                        space,
+                        out_of_gamut: None,
+                    };
+                    Some(Ok(JobResult::new(output, adjustment, mapping)))
+                });
+            }
+            None => {
+                let mut buf = orig_image.clone().into_raw();
+                self.jobs.spawn(ctx, name.clone(), move |progress| {
+                    if !jobs::for_chunks(&mut buf, 3, progress, (0.0, 1.0), op) {
+                        return None;
+                    }
+                    let output = jobs::JobOutput {
+                        name,
+                        buf,
+                        precise: None,
+                        size,
+                        space,
+                        out_of_gamut: None,
+                    };
+                    Some(Ok(JobResult::new(output, adjustment, mapping)))
+                });
+            }
+        }
+    }
+
+    /// Сдвиг HSV с приведением вышедших за охват цветов.
+    /// HDR картинки не ограничены сверху, в охват приводится только их цветность.
+    fn show_hsv(&mut self, ctx: &egui::Context, h: i32, s: i16, v: i16, processing: &Processing) {
+        let Some(orig_image) = &self.loaded_image else {
+            return;
+        };
+        let adjustment = Adjustment::Hsv(h, s, v);
+        let size = image_size(orig_image);
+        let mut buf: Vec<f32> = match &self.loaded_precise {
+            Some(precise) => precise.clone(),
+            None => orig_image.iter().map(|&c| c as f32 / 255.0).collect(),
+        };
+        let keep_precise = self.loaded_precise.is_some();
+        let hdr = self.loaded_depth == image_op::SampleDepth::F32;
+        let (space, mapping) = (processing.space, processing.mapping);
+        let name = adjustment.name();
+        self.jobs.spawn(ctx, name.clone(), move |progress| {
+            let mut mask = Vec::with_capacity(buf.len() / 3);
+            let done = jobs::for_chunks(&mut buf, 3, progress, (0.0, 1.0), |chunk| {
+                image_op::add_hsv_unbounded(chunk, h, s, v);
+                // линейные HDR данные приводятся в охват без обрезки яркости
+                mask.extend(if hdr {
+                    color_space::map_hdr_gamut(chunk, space, mapping)
+                } else {
+                    color_space::map_gamut(chunk, space, mapping)
+                });
+            });
+            if !done {
+                return None;
+            }
+            let output = jobs::JobOutput {
+                name,
+                buf: image_op::f32_buffer_to_u8(&buf),
+                precise: keep_precise.then_some(buf),
+                size,
+                space,
+                out_of_gamut: Some(mask),
+            };
+            Some(Ok(JobResult::new(output, adjustment, mapping)))
+        });
+    }
+
+    /// Тональная компрессия с параметрами settings.
+    /// Линейный буфер и гистограмма яркости считаются в том же задании один раз на картинку.
+    fn show_tone_mapped(
+        &mut self,
+        ctx: &egui::Context,
+        settings: tone_map::ToneSettings,
+        processing: &Processing,
+    ) {
+        let Some(orig_image) = &self.loaded_image else {
+            return;
+        };
+        let size = image_size(orig_image);
+        let (space, mapping) = (processing.space, processing.mapping);
+        let linear = self.tone_linear.clone();
+        // исходник нужен, только если линейный буфер ещё не посчитан
+        let source: Vec<f32> = match (&linear, &self.loaded_precise) {
+            (Some(_), _) => Vec::new(),
+            (None, Some(precise)) => precise.clone(),
+            (None, None) => orig_image.iter().map(|&c| c as f32 / 255.0).collect(),
+        };
+        let already_linear = self.loaded_depth == image_op::SampleDepth::F32;
+        let mut mapper = self.tone_mapper.clone();
+        mapper.settings = settings;
+        let adjustment = Adjustment::ToneMap(settings);
+        let name = adjustment.name();
+        self.jobs.spawn(ctx, name.clone(), move |progress| {
+            let linear = match linear {
+                Some(linear) => linear,
+                None => {
+                    let linear = tone_map::linear_buffer(&source, already_linear, space);
+                    mapper.space = space;
+                    mapper.set_image(&linear);
+                    std::sync::Arc::new(linear)
+                }
+            };
+            if progress.is_cancelled() {
+                return None;
+            }
+            progress.set(0.1);
+            let adaptation = mapper.adaptation(&linear, size);
+            if progress.is_cancelled() {
+                return None;
+            }
+            progress.set(0.2);
+
+            let mut buf = linear.to_vec();
+            let mut mask = Vec::with_capacity(size.0 * size.1);
+            let done = jobs::for_chunks(&mut buf, 3, progress, (0.2, 1.0), |chunk| {
+                let first = mask.len();
+                mask.extend(mapper.map_chunk(chunk, first, &adaptation, mapping));
+            });
+            if !done {
+                return None;
+            }
+            let output = jobs::JobOutput {
+                name,
+                buf: image_op::f32_buffer_to_u8(&buf),
+                precise: None,
+                size,
+                space,
+                out_of_gamut: Some(mask),
+            };
+            let mut result = JobResult::new(output, adjustment, mapping);
+            result.tone = Some((linear, mapper));
+            Some(Ok(result))
+        });
+    }
+
+    /// Перевод загруженной картинки из рабочего пространства в to
+    fn show_converted(
+        &mut self,
+        ctx: &egui::Context,
+        to: color_space::RgbSpace,
+        processing: &Processing,
+    ) {
+        let Some(orig_image) = &self.loaded_image else {
+            return;
+        };
+        let (from, mapping) = (processing.space, processing.mapping);
+        let size = image_size(orig_image);
+        let linear = self.loaded_depth == image_op::SampleDepth::F32;
+        let precise = self.loaded_precise.clone();
+        // 8-битный буфер переводится, только если нет точного
+        let mut buf = match precise {
+            Some(_) => Vec::new(),
+            None => orig_image.clone().into_raw(),
+        };
+        let adjustment = Adjustment::Convert(to);
+        let name = adjustment.name();
+        self.jobs.spawn(ctx, name.clone(), move |progress| {
+            let mut mask = Vec::with_capacity(size.0 * size.1);
+            let precise = match precise {
+                Some(mut precise) => {
+                    let done = jobs::for_chunks(&mut precise, 3, progress, (0.0, 1.0), |chunk| {
+                        mask.extend(color_space::convert_f32_buffer(
+                            chunk, from, to, mapping, linear,
+                        ));
+                    });
+                    if !done {
+                        return None;
+                    }
+                    buf = image_op::f32_buffer_to_u8(&precise);
+                    Some(precise)
+                }
+                None => {
+                    let done = jobs::for_chunks(&mut buf, 3, progress, (0.0, 1.0), |chunk| {
+                        mask.extend(color_space::convert_buffer(chunk, from, to, mapping));
+                    });
+                    if !done {
+                        return None;
+                    }
+                    None
+                }
+            };
+            let output = jobs::JobOutput {
+                name,
+                buf,
+                precise,
+                size,
+                space: to,
+                out_of_gamut: Some(mask),
+            };
+            Some(Ok(JobResult::new(output, adjustment, mapping)))
+        });
+    }
+
+    /// Имя вкладки: имя файла или откуда взята картинка
+    fn title(&self) -> String {
+        match &self.image_path {
+            Some(path) => path.file_name().map_or_else(
+                || path.display().to_string(),
+                |n| n.to_string_lossy().into(),
+            ),
+            None if self.decoded_image.is_some() => "Clipboard".to_string(),
+            None => "Empty".to_string(),
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct ColorsApp {
+    /// Текущая картинка и её обработка
+    image: ImageState,
+    /// Остальные открытые картинки по порядку вкладок, без текущей
+    tabs: Vec<ImageState>,
+    /// Номер вкладки текущей картинки
+    active: usize,
+    show_metadata: bool,
+    export: export::ExportDialog,
+    /// Пространство, в котором работают операции и XYZ/Lab
+    working_space: color_space::RgbSpace,
+    convert: ConvertSettings,
+    gamut: GamutSettings,
+    alpha: AlphaSettings,
+    recent: recent::RecentFiles,
+    clipboard: clipboard::Clipboard,
+    theme: egui::ThemePreference,
+    /// Папка для диалогов открытия и сохранения
+    last_dir: Option<std::path::PathBuf>,
+    restore_session: bool,
+    /// Какая картинка и какое изменение истории уже записаны в сеанс
+    session_saved: Option<(std::path::PathBuf, u64)>,
+    task: Task,
+    histogram: hist::RGBHistogram,
+    /// Что выводится в нижней панели
+    scope: scopes::ScopeKind,
+    scopes: scopes::Scopes,
+    show_inspector: bool,
+    calculator: calculator::ColorCalculator,
+    show_calculator: bool,
+    hsv_wheel: hsv_wheel::HsvWheel,
+    show_hsv_wheel: bool,
+    point_cloud: point_cloud::PointCloud,
+    show_point_cloud: bool,
+    chromaticity: chromaticity::Chromaticity,
+    show_chromaticity: bool,
+    /// Всплывающие сообщения и журнал ошибок
+    notifications: notifications::Notifications,
+    show_log: bool,
+    /// Вторая картинка для сравнения, готовится так же, как загруженная
+    second: ImageState,
+    size_match: image_op::SizeMatch,
+    diff: DiffSettings,
+}
+
+impl ColorsApp {
+    /// Окно приложения; files открываются во вкладках после картинки прошлого сеанса
+    pub fn new(cc: &eframe::CreationContext<'_>, files: Vec<std::path::PathBuf>) -> Self {
+        let settings: Settings = cc
+            .storage
+            .and_then(|s| eframe::get_value(s, SETTINGS_KEY))
+            .unwrap_or_default();
+        cc.egui_ctx.set_theme(settings.theme);
+        let mut app = Self {
+            recent: recent::RecentFiles::load(cc.storage),
+            ..Self::default()
+        };
+        app.apply_settings(settings);
+        if app.restore_session
+            && let Some(session) = session::Session::load(cc.storage)
+        {
+            app.reopen_session(&cc.egui_ctx, session);
+        }
+        for path in files {
+            app.open_path(&cc.egui_ctx, path);
+        }
+        app
+    }
+
+    /// Настройки для записи в хранилище
+    fn settings(&self) -> Settings {
+        Settings {
+            theme: self.theme,
+            last_dir: self.last_dir.clone(),
+            task: self.task,
+            scope: self.scope,
+            histogram: self.histogram.options(),
+            restore_session: self.restore_session,
+        }
+    }
+
+    fn apply_settings(&mut self, settings: Settings) {
+        self.theme = settings.theme;
+        self.last_dir = settings.last_dir;
+        self.task = settings.task;
+        self.scope = settings.scope;
+        self.histogram.set_options(settings.histogram);
+        self.restore_session = settings.restore_session;
+    }
 
-            self.histogram.update_data(self.cur_image.clone().unwrap());
+    /// Открыть картинку прошлого сеанса.
+    /// Её история возвращается, когда картинка переведена в рабочее пространство.
+    fn reopen_session(&mut self, ctx: &egui::Context, session: session::Session) {
+        self.open_path(ctx, session.image.clone());
+        if self.image.image_path.as_ref() == Some(&session.image) {
+            self.image.session = Some(session);
         }
     }
 
-    /// Сохранить текущую выводимую картинку в файл
-    fn save_image(&mut self) {
-        if self.cur_image == None {
+    /// Записать картинку и историю для восстановления при следующем запуске
+    fn save_session(&mut self, storage: &mut dyn eframe::Storage) {
+        // история ещё не начата, записанный сеанс остаётся прежним
+        if self.image.prepare.is_running() {
             return;
         }
+        let Some(path) = self
+            .image
+            .image_path
+            .clone()
+            .filter(|_| self.restore_session)
+        else {
+            session::Session::store(None, storage);
+            self.session_saved = None;
+            return;
+        };
+        let state = (path, self.image.history.revision());
+        if self.session_saved.as_ref() == Some(&state) {
+            return;
+        }
+        match session::Session::capture(&state.0, &self.image.history) {
+            Ok(session) => {
+                session::Session::store(session.as_ref(), storage);
+                self.session_saved = Some(state);
+            }
+            Err(e) => self.report(e),
+        }
+    }
+
+    /// Выбрать файл с картинкой в диалоге и загрузить его
+    fn load_image(&mut self, ctx: &egui::Context) {
+        let paths = image_file_dialog(self.last_dir.as_deref()).pick_files();
+        for path in paths.into_iter().flatten() {
+            self.open_path(ctx, path);
+        }
+    }
+
+    /// Загрузить картинку из файла в новую вкладку.
+    /// Уже открытый файл просто становится текущим.
+    fn open_path(&mut self, ctx: &egui::Context, path: std::path::PathBuf) {
+        if let Some(index) =
+            (0..self.tab_count()).find(|&i| self.tab(i).image_path.as_ref() == Some(&path))
+        {
+            self.select_tab(index);
+            return;
+        }
+        match open_image(&path) {
+            Ok(opened) => {
+                self.recent.add(&path);
+                self.last_dir = path.parent().map(|dir| dir.to_path_buf());
+                self.new_tab();
+                self.set_image(ctx, opened, Some(path));
+            }
+            Err(e) => {
+                // пропавший файл больше не предлагается в недавних
+                if !path.exists() {
+                    self.recent.remove(&path);
+                }
+                self.report(AppError::open(&path, e));
+            }
+        }
+    }
+
+    /// Вставить картинку из буфера обмена.
+    /// Вставленный текст с путём к файлу открывает файл вместе с профилем и EXIF.
+    fn paste_image(&mut self, ctx: &egui::Context, text: Option<&str>) {
+        if let Some(path) = text.and_then(clipboard::file_path) {
+            self.open_path(ctx, path);
+            return;
+        }
+        match self.clipboard.paste_image() {
+            Ok(image) => {
+                let opened = OpenedImage {
+                    image: image.into(),
+                    icc_profile: None,
+                    exif: None,
+                    orientation: image::metadata::Orientation::NoTransforms,
+                };
+                self.new_tab();
+                self.set_image(ctx, opened, None);
+            }
+            Err(e) => self.report(e),
+        }
+    }
+
+    /// Скопировать выводимую картинку в буфер обмена, в sRGB
+    fn copy_result(&mut self) {
+        let (Some(buf), Some(size)) = (&self.image.cur_image, self.image.cur_image_size) else {
+            return;
+        };
+        let mut rgb = buf.clone();
+        if self.image.cur_space != color_space::RgbSpace::Srgb {
+            color_space::convert_buffer(
+                &mut rgb,
+                self.image.cur_space,
+                color_space::RgbSpace::Srgb,
+                self.gamut.mapping,
+            );
+        }
+        let alpha = self
+            .image
+            .cur_alpha
+            .clone()
+            .unwrap_or_else(|| vec![255; size.0 * size.1]);
+        let result = image_op::merge_alpha(&rgb, &alpha)
+            .and_then(|rgba| self.clipboard.copy_image(&rgba, size));
+        match result {
+            Ok(()) => self
+                .notifications
+                .info("Картинка скопирована в буфер обмена"),
+            Err(e) => self.report(e),
+        }
+    }
+
+    /// Сделать картинку загруженной, path - None для вставленной из буфера обмена.
+    /// Картинка выводится, когда переведена в рабочее пространство.
+    fn set_image(
+        &mut self,
+        ctx: &egui::Context,
+        opened: OpenedImage,
+        path: Option<std::path::PathBuf>,
+    ) {
+        self.image.decode(opened);
+        self.image.image_path = path;
+        self.image.view.fit();
+        self.apply_color_profile(ctx);
+    }
+
+    /// Перевести пиксели загруженной картинки из её профиля в рабочее пространство
+    fn apply_color_profile(&mut self, ctx: &egui::Context) {
+        self.image.start_conversion(ctx, self.working_space);
+    }
+
+    /// Перевести вторую картинку в рабочее пространство, как загруженную
+    fn prepare_second_image(&mut self, ctx: &egui::Context) {
+        self.second.start_conversion(ctx, self.working_space);
+    }
+
+    /// Подготовить переведённую вторую картинку так же, как загруженную
+    fn show_second_prepared(&mut self, prepared: Prepared) {
+        self.second.set_prepared(prepared);
+        if let Some(warning) = &self.second.profile_warning {
+            self.notifications.warning(warning.clone());
+        }
+        self.second.apply_alpha(&self.alpha);
+    }
+
+    /// Меню работы с альфа-каналом
+    fn alpha_menu_ui(&mut self, ctx: &egui::Context, ui: &mut egui::Ui) {
+        if self.image.loaded_alpha.is_none() {
+            ui.label("Картинка непрозрачна");
+        }
+        let mut changed = ui
+            .radio_value(&mut self.alpha.mode, AlphaMode::Keep, "Keep transparency")
+            .changed();
+        changed |= ui
+            .radio_value(
+                &mut self.alpha.mode,
+                AlphaMode::Flatten,
+                "Flatten onto background",
+            )
+            .changed();
+        ui.horizontal(|ui| {
+            ui.label("Background");
+            changed |= ui
+                .color_edit_button_srgb(&mut self.alpha.background)
+                .changed();
+        });
+
+        if changed {
+            let processing = self.processing();
+            for image in self.images_mut() {
+                if image.source_image.is_some() {
+                    image.apply_alpha(&processing.alpha);
+                    image.restart_from_loaded(ctx, &processing);
+                }
+            }
+            self.refresh_views();
+            self.second.apply_alpha(&self.alpha);
+        }
+    }
+
+    /// Меню ICC профиля загруженной картинки
+    fn color_profile_menu_ui(&mut self, ctx: &egui::Context, ui: &mut egui::Ui) {
+        if self.image.color.menu_ui(ui) {
+            self.apply_color_profile(ctx);
+        }
+    }
+
+    /// Меню рабочего пространства: пиксели переводятся в него из профиля картинки
+    fn working_space_menu_ui(&mut self, ctx: &egui::Context, ui: &mut egui::Ui) {
+        let mut changed = false;
+        for space in color_space::RgbSpace::ALL {
+            changed |= ui
+                .radio_value(&mut self.working_space, space, space.name())
+                .changed();
+        }
+        if changed {
+            let space = self.working_space;
+            for image in self.images_mut() {
+                image.start_conversion(ctx, space);
+            }
+            self.prepare_second_image(ctx);
+        }
+    }
+
+    /// Вывести результаты завершившихся фоновых операций.
+    /// Операции других вкладок запускает "Apply to all" и перевод в рабочее пространство,
+    /// гистограммы и мониторы обновляются только для текущей картинки.
+    fn poll_jobs(&mut self, ctx: &egui::Context) {
+        let processing = self.processing();
+        let mut saved = Vec::new();
+        let polled = self.image.poll(ctx, &processing, &mut self.notifications);
+        if polled.shown {
+            self.refresh_views();
+        }
+        saved.extend(polled.saved);
+        for tab in &mut self.tabs {
+            saved.extend(tab.poll(ctx, &processing, &mut self.notifications).saved);
+        }
+        for result in saved {
+            self.show_saved(result);
+        }
+        if let Some(prepared) = self.second.prepare.poll() {
+            self.show_second_prepared(prepared);
+        }
+    }
+
+    /// Выбор способа приведения цветов вне охвата; true, если он изменился
+    fn gamut_mapping_ui(&mut self, ui: &mut egui::Ui) -> bool {
+        let before = self.gamut.mapping;
+        egui::ComboBox::from_label("gamut")
+            .selected_text(self.gamut.mapping.name())
+            .show_ui(ui, |ui| {
+                for mapping in color_space::GamutMapping::ALL {
+                    ui.selectable_value(&mut self.gamut.mapping, mapping, mapping.name());
+                }
+            });
+        self.gamut.mapping != before
+    }
+
+    /// Обновить пиксель под курсором, закрепить пиксель по щелчку
+    /// и взять значения закреплённых пикселей из текущих буферов
+    fn update_inspector(&mut self) {
+        let original = self
+            .image
+            .loaded_image
+            .as_ref()
+            .map(|img| (img.as_raw().as_slice(), image_size(img)));
+        let processed = self
+            .image
+            .cur_image
+            .as_deref()
+            .zip(self.image.cur_image_size);
+        let sample = |(x, y)| inspector::PixelSample::new(x, y, original, processed);
+
+        self.image.inspector.hovered = self.image.view.hovered_pixel().map(sample);
+        if let Some(pixel) = self.image.view.clicked_pixel() {
+            self.image.inspector.pin(pixel);
+        }
+        self.image.inspector.resample(sample);
+    }
+
+    /// Загрузить вторую картинку, с которой сравнивается загруженная.
+    /// Она поворачивается по EXIF и переводится в рабочее пространство, как первая.
+    fn load_second_image(&mut self, ctx: &egui::Context) {
+        let Some(path) = image_file_dialog(self.last_dir.as_deref()).pick_file() else {
+            return;
+        };
+
+        match open_image(&path) {
+            Ok(opened) => {
+                self.last_dir = path.parent().map(|dir| dir.to_path_buf());
+                self.second = ImageState::default();
+                self.second.decode(opened);
+                self.second.image_path = Some(path);
+                self.prepare_second_image(ctx);
+                self.image.error = None;
+            }
+            Err(e) => self.set_error(AppError::open(&path, e)),
+        }
+    }
+
+    /// Сообщить об ошибке всплывающим сообщением и записать её в журнал
+    fn report(&mut self, error: AppError) {
+        self.notifications.error(&error);
+    }
+
+    /// Сообщить об ошибке сравнения и показать её рядом с метриками
+    fn set_error(&mut self, error: AppError) {
+        self.image.set_error(error, &mut self.notifications);
+    }
+
+    /// Настройки окна для обработки картинок
+    fn processing(&self) -> Processing {
+        Processing {
+            space: self.working_space,
+            mapping: self.gamut.mapping,
+            alpha: self.alpha,
+            overlay_color: self.gamut.overlay_color,
+        }
+    }
+
+    /// Применить операцию к текущей картинке
+    fn apply_adjustment(&mut self, ctx: &egui::Context, adjustment: Adjustment) {
+        let processing = self.processing();
+        if self.image.apply_adjustment(ctx, adjustment, &processing) {
+            self.refresh_views();
+        }
+    }
+
+    /// Вывести разность двух картинок одного размера вместе с метриками.
+    /// Разность и метрики считаются в фоне.
+    fn show_difference(
+        &mut self,
+        ctx: &egui::Context,
+        buf_1: Vec<u8>,
+        buf_2: Vec<u8>,
+        size: (usize, usize),
+        view: image_op::DiffView,
+    ) {
+        if self.image.loaded_image.is_none() {
+            return;
+        }
+        let gain = (!self.diff.auto_stretch).then_some(self.diff.gain);
+        let (space, mapping) = (self.working_space, self.gamut.mapping);
+        let name = format!("diff {}", diff_view_name(view));
+        self.image.requested = None;
+        self.image.jobs.spawn(ctx, name.clone(), move |progress| {
+            let result = difference_with_metrics(&buf_1, &buf_2, size, view, gain, progress);
+            let (buf, metrics) = match result {
+                Ok(Some(done)) => done,
+                Ok(None) => return None,
+                Err(e) => return Some(Err(e)),
+            };
+            let output = jobs::JobOutput {
+                name,
+                buf,
+                precise: None,
+                size,
+                space,
+                out_of_gamut: None,
+            };
+            // разность не повторяется на других картинках
+            Some(Ok(JobResult {
+                output,
+                adjustment: None,
+                mapping,
+                tone: None,
+                metrics: Some(metrics),
+            }))
+        });
+    }
+
+    /// Вывести разность загруженной и второй картинок
+    fn show_difference_with_second(&mut self, ctx: &egui::Context) {
+        let (Some(first), Some(second)) = (&self.image.loaded_image, &self.second.loaded_image)
+        else {
+            self.set_error(AppError::SecondImageMissing);
+            return;
+        };
+
+        match image_op::align_images(
+            first.as_raw(),
+            image_size(first),
+            second.as_raw(),
+            image_size(second),
+            self.size_match,
+        ) {
+            Ok((buf_1, buf_2, size)) => {
+                self.show_difference(ctx, buf_1, buf_2, size, self.diff.view)
+            }
+            Err(e) => self.set_error(e),
+        }
+    }
+
+    /// Пересчитать гистограммы и мониторы для выводимой картинки
+    fn update_scopes(&mut self) {
+        match (&self.image.cur_image, self.image.cur_image_size) {
+            (Some(raw_data), Some(size)) => {
+                if let Err(e) = self.histogram.update_data(raw_data.clone()) {
+                    self.notifications.error(&e);
+                }
+                self.histogram.set_precise(self.image.cur_precise.clone());
+                self.histogram.set_alpha(self.image.cur_alpha.clone());
+                self.scopes.update_data(raw_data.clone(), size);
+                self.point_cloud.set_image(raw_data);
+                self.chromaticity.set_image(raw_data);
+            }
+            _ => {
+                self.histogram.clear();
+                self.scopes.update_data(Vec::new(), (0, 0));
+                self.point_cloud.set_image(&[]);
+                self.chromaticity.set_image(&[]);
+            }
+        }
+    }
+
+    /// Выводимая картинка с точностью и прозрачностью для экспорта
+    fn export_image(&self) -> Option<(image::DynamicImage, export::SourceInfo)> {
+        let buf = self.image.cur_image.as_ref()?;
+        let (w, h) = self.image.cur_image_size?;
+        let alpha = self.image.cur_alpha.as_deref();
+        let image = match (&self.image.cur_precise, alpha) {
+            (Some(precise), _) => precise_image(precise, alpha, (w, h)),
+            (None, Some(alpha)) => {
+                let rgba = image_op::merge_alpha(buf, alpha).ok()?;
+                image::RgbaImage::from_raw(w as u32, h as u32, rgba)?.into()
+            }
+            (None, None) => RgbImage::from_raw(w as u32, h as u32, buf.clone())?.into(),
+        };
+        Some((image, self.export_source_info()?))
+    }
+
+    /// Глубина, прозрачность и серость выводимой картинки
+    fn export_source_info(&self) -> Option<export::SourceInfo> {
+        let buf = self.image.cur_image.as_ref()?;
+        Some(export::SourceInfo {
+            depth: match self.image.cur_precise {
+                Some(_) => self.image.loaded_depth,
+                None => image_op::SampleDepth::U8,
+            },
+            has_alpha: self.image.cur_alpha.is_some(),
+            is_gray: buf.chunks_exact(3).all(|p| p[0] == p[1] && p[1] == p[2]),
+        })
+    }
+
+    /// ICC профиль пространства картинки и EXIF исходника для записи в файл
+    fn file_metadata(&self) -> export::FileMetadata {
+        export::FileMetadata {
+            icc_profile: self
+                .image
+                .color
+                .embed_on_save
+                .then(|| icc::space_icc(self.image.cur_space))
+                .flatten(),
+            exif: self.image.metadata.exif_for_save(self.image.cur_space),
+        }
+    }
+
+    /// Посчитать в фоне размер файла для настроек окна экспорта
+    fn estimate_export_size(&mut self, ctx: &egui::Context) {
+        if let Some((image, source)) = self.export_image() {
+            let metadata = self.file_metadata();
+            self.export.start_estimate(ctx, image, source, metadata);
+        }
+    }
+
+    /// Сохранить текущую выводимую картинку в файл с настройками окна экспорта.
+    /// Картинка кодируется и записывается в фоне.
+    fn save_image(&mut self, ctx: &egui::Context) {
+        let Some((image, source)) = self.export_image() else {
+            return;
+        };
+        let format = self.export.settings.format;
+        let extension = format.extensions()[0];
 
         // File dialog
-        let mut dialog = rfd::FileDialog::new()
-            .add_filter("PNG Image", &["png"])
-            .add_filter("JPEG Image", &["jpg", "jpeg"])
-            .add_filter("BMP Image", &["bmp"])
-            .add_filter("All Files", &["*"]);
+        let mut dialog = rfd::FileDialog::new().add_filter(format.name(), format.extensions());
 
         // Set default path
-        if let Some(path) = &self.image_path {
+        if let Some(dir) = &self.last_dir {
+            dialog = dialog.set_directory(dir);
+        }
+        if let Some(path) = &self.image.image_path {
             if let Some(parent) = path.parent() {
                 dialog = dialog.set_directory(parent);
             }
-            if let Some(file_name) = path.file_name() {
-                dialog = dialog.set_file_name(file_name.to_string_lossy().as_ref());
+            if let Some(stem) = path.file_stem() {
+                dialog = dialog.set_file_name(format!("{}.{extension}", stem.to_string_lossy()));
             }
         }
 
         // Show save dialog
-        let size = self.cur_image_size.unwrap();
-        let buf = self.cur_image.clone().unwrap();
-        let image = RgbImage::from_raw(size.0 as u32, size.1 as u32, buf).unwrap();
-        if let Some(new_path) = dialog.save_file() {
-            match image.save(&new_path) {
-                Ok(_) => {
-                    self.image_path = Some(new_path);
+        if let Some(mut new_path) = dialog.save_file() {
+            // расширение должно соответствовать формату
+            let matches_format = new_path
+                .extension()
+                .and_then(|e| e.to_str())
+                .is_some_and(|e| format.extensions().contains(&e.to_lowercase().as_str()));
+            if !matches_format {
+                new_path.set_extension(extension);
+            }
+            let settings = self.export.settings.clone();
+            let metadata = self.file_metadata();
+            self.image.saving.spawn(ctx, "Saving", move |progress| {
+                let encoded = match settings.encode(&image, &source, &metadata) {
+                    Ok(encoded) => encoded,
+                    Err(e) => return Some(Err(e)),
+                };
+                // отменённое сохранение не записывает файл
+                if progress.is_cancelled() {
+                    return None;
                 }
-                Err(e) => {
-                    eprintln!("Failed to save image: {}", e);
+                let result = std::fs::write(&new_path, encoded.bytes)
+                    .map_err(|e| AppError::write(&new_path, e));
+                progress.set(1.0);
+                Some(result.map(|()| (new_path, encoded.warnings)))
+            });
+        }
+    }
+
+    /// Сообщить о записанном файле и невстроенных в него метаданных
+    fn show_saved(&mut self, result: Result<(std::path::PathBuf, Vec<String>), AppError>) {
+        match result {
+            Ok((path, warnings)) => {
+                self.notifications
+                    .info(format!("Сохранено в {}", path.display()));
+                for warning in warnings {
+                    self.notifications.warning(warning);
                 }
+                self.last_dir = path.parent().map(|dir| dir.to_path_buf());
             }
+            Err(e) => self.report(e),
         }
     }
 
@@ -113,102 +1548,156 @@ impl ColorsApp {
         ui.vertical(|ui| {
             // Вывести оригинальную картинку
             if ui.button("Original").clicked() {
-                if let Some(orig_image) = &self.loaded_image {
-                    self.cur_image = Some(orig_image.clone().into_raw());
-                    self.update_texture(ctx);
-                }
+                self.apply_adjustment(ctx, Adjustment::Original);
             }
 
             // Оттенки серого 1-ым методом
             if ui.button("Grayscale1").clicked() {
-                if let Some(orig_image) = &self.loaded_image {
-                    // картинка
-                    let mut buf = orig_image.clone().into_raw();
-                    image_op::rgb_buffer_to_grayscale1(&mut buf);
-                    self.cur_image = Some(buf);
-                    self.update_texture(ctx);
-                }
+                self.apply_adjustment(ctx, Adjustment::Grayscale1);
             }
 
             // Оттенки серого 2-ым методом
             if ui.button("Grayscale2").clicked() {
-                if let Some(orig_image) = &self.loaded_image {
-                    let mut buf = orig_image.clone().into_raw();
-                    image_op::rgb_buffer_to_grayscale2(&mut buf);
-                    self.cur_image = Some(buf);
-                    self.update_texture(ctx);
-                }
+                self.apply_adjustment(ctx, Adjustment::Grayscale2);
             }
 
             // Разница между 2-мя методами
-            if ui.button("diff").clicked() {
-                if let Some(orig_image) = &self.loaded_image {
-                    let mut buf_1 = orig_image.clone().into_raw();
-                    image_op::rgb_buffer_to_grayscale1(&mut buf_1);
-                    let mut buf_2 = orig_image.clone().into_raw();
-                    image_op::rgb_buffer_to_grayscale2(&mut buf_2);
-
-                    let buf = image_op::compute_difference(&buf_1, &buf_2);
-                    self.cur_image = Some(buf);
-                    self.update_texture(ctx);
-                }
+            if ui.button("diff").clicked()
+                && let Some(orig_image) = &self.image.loaded_image
+            {
+                let (buf_1, buf_2) = grayscale_pair(orig_image);
+                let size = image_size(orig_image);
+                self.show_difference(ctx, buf_1, buf_2, size, image_op::DiffView::Abs);
             }
 
             // Разница между 2-мя методами в негативе
-            if ui.button("diff neg").clicked() {
-                if let Some(orig_image) = &self.loaded_image {
-                    let mut buf_1 = orig_image.clone().into_raw();
-                    image_op::rgb_buffer_to_grayscale1(&mut buf_1);
-                    let mut buf_2 = orig_image.clone().into_raw();
-                    image_op::rgb_buffer_to_grayscale2(&mut buf_2);
-
-                    let buf = image_op::compute_difference_neg(&buf_1, &buf_2);
-                    self.cur_image = Some(buf);
-                    self.update_texture(ctx);
-                }
+            if ui.button("diff neg").clicked()
+                && let Some(orig_image) = &self.image.loaded_image
+            {
+                let (buf_1, buf_2) = grayscale_pair(orig_image);
+                let size = image_size(orig_image);
+                self.show_difference(ctx, buf_1, buf_2, size, image_op::DiffView::Neg);
+            }
+
+            // Усиленная разница и разница со знаком
+            if ui.button("diff amp").clicked()
+                && let Some(orig_image) = &self.image.loaded_image
+            {
+                let (buf_1, buf_2) = grayscale_pair(orig_image);
+                let size = image_size(orig_image);
+                self.show_difference(ctx, buf_1, buf_2, size, image_op::DiffView::Amplified);
+            }
+            if ui.button("diff signed").clicked()
+                && let Some(orig_image) = &self.image.loaded_image
+            {
+                let (buf_1, buf_2) = grayscale_pair(orig_image);
+                let size = image_size(orig_image);
+                self.show_difference(ctx, buf_1, buf_2, size, image_op::DiffView::Signed);
+            }
+            self.diff_gain_ui(ui);
+
+            ui.separator();
+            self.second_image_ui(ctx, ui);
+
+            if let Some(metrics) = &self.image.metrics {
+                ui.separator();
+                show_metrics(ui, metrics);
+            }
+            if let Some(error) = &self.image.error {
+                ui.colored_label(ui.visuals().error_fg_color, error.to_string());
             }
         });
     }
 
+    /// UI сравнения загруженной картинки со второй
+    fn second_image_ui(&mut self, ctx: &egui::Context, ui: &mut egui::Ui) {
+        if ui.button("Load second image").clicked() {
+            self.load_second_image(ctx);
+        }
+        self.second.prepare.progress_ui(ui);
+        if let Some(path) = &self.second.image_path
+            && let Some(name) = path.file_name()
+        {
+            ui.label(name.to_string_lossy());
+        }
+
+        // Способ приведения размеров
+        let mut offset = match self.size_match {
+            image_op::SizeMatch::Offset(dx, dy) => (dx, dy),
+            _ => (0, 0),
+        };
+        egui::ComboBox::from_label("size")
+            .selected_text(match self.size_match {
+                image_op::SizeMatch::Crop => "Crop",
+                image_op::SizeMatch::Resize => "Resize",
+                image_op::SizeMatch::Offset(..) => "Offset",
+            })
+            .show_ui(ui, |ui| {
+                ui.selectable_value(&mut self.size_match, image_op::SizeMatch::Crop, "Crop");
+                ui.selectable_value(&mut self.size_match, image_op::SizeMatch::Resize, "Resize");
+                ui.selectable_value(
+                    &mut self.size_match,
+                    image_op::SizeMatch::Offset(offset.0, offset.1),
+                    "Offset",
+                );
+            });
+        if let image_op::SizeMatch::Offset(..) = self.size_match {
+            ui.horizontal(|ui| {
+                ui.add(egui::DragValue::new(&mut offset.0).prefix("dx "));
+                ui.add(egui::DragValue::new(&mut offset.1).prefix("dy "));
+            });
+            self.size_match = image_op::SizeMatch::Offset(offset.0, offset.1);
+        }
+
+        egui::ComboBox::from_label("view")
+            .selected_text(diff_view_name(self.diff.view))
+            .show_ui(ui, |ui| {
+                for view in [
+                    image_op::DiffView::Abs,
+                    image_op::DiffView::Neg,
+                    image_op::DiffView::Amplified,
+                    image_op::DiffView::Signed,
+                ] {
+                    ui.selectable_value(&mut self.diff.view, view, diff_view_name(view));
+                }
+            });
+        if ui.button("diff second").clicked() {
+            self.show_difference_with_second(ctx);
+        }
+    }
+
+    /// UI усиления разности
+    fn diff_gain_ui(&mut self, ui: &mut egui::Ui) {
+        ui.checkbox(&mut self.diff.auto_stretch, "auto stretch");
+        ui.add_enabled(
+            !self.diff.auto_stretch,
+            egui::Slider::new(&mut self.diff.gain, 1.0..=64.0)
+                .logarithmic(true)
+                .text("gain"),
+        );
+    }
+
     /// UI левой панели для задания с каналами RGB
     fn left_buttons_rgb_channels(&mut self, ctx: &egui::Context, ui: &mut egui::Ui) {
         ui.vertical(|ui| {
             // Вывести оригинальную картинку
             if ui.button("Original").clicked() {
-                if let Some(orig_image) = &self.loaded_image {
-                    self.cur_image = Some(orig_image.clone().into_raw());
-                    self.update_texture(ctx);
-                }
+                self.apply_adjustment(ctx, Adjustment::Original);
             }
 
             // Вывести красный канал
             if ui.button("Red channel").clicked() {
-                if let Some(orig_image) = &self.loaded_image {
-                    let mut buf = orig_image.clone().into_raw();
-                    image_op::rgb_buffer_to_red_channel(&mut buf);
-                    self.cur_image = Some(buf);
-                    self.update_texture(ctx);
-                }
+                self.apply_adjustment(ctx, Adjustment::Channel(0));
             }
 
             // Вывести зелёный канал
             if ui.button("Green channel").clicked() {
-                if let Some(orig_image) = &self.loaded_image {
-                    let mut buf = orig_image.clone().into_raw();
-                    image_op::rgb_buffer_to_green_channel(&mut buf);
-                    self.cur_image = Some(buf);
-                    self.update_texture(ctx);
-                }
+                self.apply_adjustment(ctx, Adjustment::Channel(1));
             }
 
             // Вывести синий канал
             if ui.button("Blue channel").clicked() {
-                if let Some(orig_image) = &self.loaded_image {
-                    let mut buf = orig_image.clone().into_raw();
-                    image_op::rgb_buffer_to_blue_channel(&mut buf);
-                    self.cur_image = Some(buf);
-                    self.update_texture(ctx);
-                }
+                self.apply_adjustment(ctx, Adjustment::Channel(2));
             }
         });
     }
@@ -218,45 +1707,441 @@ impl ColorsApp {
         ui.vertical(|ui| {
             // Вывести оригинальную картинку
             if ui.button("Original").clicked() {
-                if let Some(orig_image) = &self.loaded_image {
-                    self.cur_image = Some(orig_image.clone().into_raw());
-                    self.update_texture(ctx);
-                }
-                self.hue = 0;
-                self.saturation = 0;
-                self.value = 0;
+                self.apply_adjustment(ctx, Adjustment::Original);
+                self.image.hue = 0;
+                self.image.saturation = 0;
+                self.image.value = 0;
+                self.image.hsv_shown = Some((0, 0, 0, self.gamut.mapping));
             }
 
             // HSV sliders
-            ui.add(egui::Slider::new(&mut self.hue, 0..=360 as i32).text("hue"));
-            ui.add(egui::Slider::new(&mut self.saturation, -100..=100 as i16).text("saturation"));
-            ui.add(egui::Slider::new(&mut self.value, -100..=100 as i16).text("value"));
+            ui.add(egui::Slider::new(&mut self.image.hue, 0..=360).text("hue"));
+            ui.add(egui::Slider::new(&mut self.image.saturation, -100..=100).text("saturation"));
+            ui.add(egui::Slider::new(&mut self.image.value, -100..=100).text("value"));
 
-            if let Some(orig_image) = &self.loaded_image {
-                let mut buf = orig_image.clone().into_raw();
-                // Test rgb -> hsv -> rgb
-                // for i in (0..buf.len()).step_by(3) {
-                //     let mut r = buf[i];
-                //     let mut g = buf[i + 1];
-                //     let mut b = buf[i + 2];
-
-                //     let (h, s, v) = image_op::rgb_to_hsv(r, g, b);
-                //     (r, g, b) = image_op::hsv_to_rgb(h, s, v);
-
-                //     buf[i] = r;
-                //     buf[i + 1] = g;
-                //     buf[i + 2] = b;
-                // }
-                image_op::add_hsv_to_buffer(&mut buf, self.hue, self.saturation, self.value);
-                self.cur_image = Some(buf);
-                self.update_texture(ctx);
+            self.gamut_mapping_ui(ui);
+
+            let (h, s, v) = (self.image.hue, self.image.saturation, self.image.value);
+            let mapping = self.gamut.mapping;
+            if self.image.loaded_image.is_some()
+                && self.image.hsv_shown != Some((h, s, v, mapping))
+                && self.image.requested != Some((Adjustment::Hsv(h, s, v), mapping))
+            {
+                self.apply_adjustment(ctx, Adjustment::Hsv(h, s, v));
             }
         });
     }
+
+    /// UI левой панели для тональной компрессии HDR
+    fn left_buttons_tone_mapping(&mut self, ctx: &egui::Context, ui: &mut egui::Ui) {
+        ui.vertical(|ui| {
+            if self.image.loaded_image.is_none() {
+                ui.label("Необходимо загрузить картинку.");
+                return;
+            }
+
+            // гистограмма яркости считается вместе с первой компрессией картинки
+            let requested = matches!(self.image.requested, Some((Adjustment::ToneMap(_), _)));
+            let mut apply = self.image.tone_linear.is_none() && !requested;
+            apply |= self.image.tone_mapper.ui(ui);
+            apply |= self.gamut_mapping_ui(ui);
+            apply |= ui.button("Apply").clicked();
+            if apply {
+                let settings = self.image.tone_mapper.settings;
+                self.apply_adjustment(ctx, Adjustment::ToneMap(settings));
+            }
+        });
+    }
+
+    /// UI левой панели для перевода картинки в другое RGB пространство
+    fn left_buttons_color_space(&mut self, ctx: &egui::Context, ui: &mut egui::Ui) {
+        ui.vertical(|ui| {
+            ui.label(format!("Working space: {}", self.working_space.name()));
+            egui::ComboBox::from_label("to")
+                .selected_text(self.convert.target.name())
+                .show_ui(ui, |ui| {
+                    for space in color_space::RgbSpace::ALL {
+                        ui.selectable_value(&mut self.convert.target, space, space.name());
+                    }
+                });
+            self.gamut_mapping_ui(ui);
+
+            if ui.button("Convert").clicked() {
+                self.apply_adjustment(ctx, Adjustment::Convert(self.convert.target));
+            }
+            ui.label("Результат выводится и сохраняется с профилем нового пространства");
+        });
+    }
+
+    /// Число открытых вкладок вместе с текущей
+    fn tab_count(&self) -> usize {
+        self.tabs.len() + 1
+    }
+
+    /// Картинка вкладки index
+    fn tab(&self, index: usize) -> &ImageState {
+        match index.cmp(&self.active) {
+            std::cmp::Ordering::Less => &self.tabs[index],
+            std::cmp::Ordering::Equal => &self.image,
+            std::cmp::Ordering::Greater => &self.tabs[index - 1],
+        }
+    }
+
+    /// Переключиться на вкладку index
+    fn select_tab(&mut self, index: usize) {
+        if index == self.active || index >= self.tab_count() {
+            return;
+        }
+        let current = std::mem::take(&mut self.image);
+        self.tabs.insert(self.active, current);
+        self.image = self.tabs.remove(index);
+        self.active = index;
+        self.refresh_views();
+    }
+
+    /// Завести вкладку в конце для новой картинки, если текущая уже занята
+    fn new_tab(&mut self) {
+        if self.image.decoded_image.is_none() {
+            return;
+        }
+        let current = std::mem::take(&mut self.image);
+        self.tabs.insert(self.active, current);
+        self.active = self.tabs.len();
+    }
+
+    /// Закрыть вкладку index; после текущей становится текущей соседняя
+    fn close_tab(&mut self, index: usize) {
+        if index < self.active {
+            self.tabs.remove(index);
+            self.active -= 1;
+        } else if index > self.active {
+            self.tabs.remove(index - 1);
+        } else {
+            self.image = if self.tabs.is_empty() {
+                ImageState::default()
+            } else {
+                self.active = self.active.min(self.tabs.len() - 1);
+                self.tabs.remove(self.active)
+            };
+            self.refresh_views();
+        }
+    }
+
+    /// Гистограммы, мониторы, оценка экспорта и круг HSV для текущей картинки
+    fn refresh_views(&mut self) {
+        self.export.invalidate();
+        self.update_scopes();
+        let original = self
+            .image
+            .history
+            .get(0)
+            .map(|entry| entry.image.as_slice());
+        self.hsv_wheel.set_image(original.unwrap_or_default());
+    }
+
+    /// Все открытые картинки, начиная с текущей
+    fn images_mut(&mut self) -> impl Iterator<Item = &mut ImageState> {
+        std::iter::once(&mut self.image).chain(&mut self.tabs)
+    }
+
+    /// Повторить последнюю операцию текущей картинки на всех открытых картинках
+    fn apply_to_all(&mut self, ctx: &egui::Context) {
+        let Some(adjustment) = self.image.adjustment else {
+            return;
+        };
+        let processing = self.processing();
+        let mut shown = false;
+        for image in self.images_mut() {
+            if image.adjustment != Some(adjustment) {
+                shown |= image.apply_adjustment(ctx, adjustment, &processing);
+            }
+        }
+        if shown {
+            self.refresh_views();
+        }
+    }
+
+    /// Вкладки открытых картинок и повтор операции на всех
+    fn tabs_ui(&mut self, ctx: &egui::Context, ui: &mut egui::Ui) {
+        let mut selected = None;
+        let mut closed = None;
+        ui.horizontal_wrapped(|ui| {
+            for index in 0..self.tab_count() {
+                let tab = self.tab(index);
+                let path = tab.image_path.as_ref().map(|p| p.display().to_string());
+                let response = ui.selectable_label(index == self.active, tab.title());
+                let response = match path {
+                    Some(path) => response.on_hover_text(path),
+                    None => response,
+                };
+                if response.clicked() {
+                    selected = Some(index);
+                }
+                if ui.small_button("×").on_hover_text("Close").clicked() {
+                    closed = Some(index);
+                }
+                ui.separator();
+            }
+
+            let adjustment = self.image.adjustment;
+            let button = ui.add_enabled(
+                adjustment.is_some() && self.tab_count() > 1,
+                egui::Button::new("Apply to all"),
+            );
+            if let Some(adjustment) = adjustment {
+                let hint = format!("Apply \"{}\" to all open images", adjustment.name());
+                if button.on_hover_text(hint).clicked() {
+                    self.apply_to_all(ctx);
+                }
+            }
+        });
+        if let Some(index) = selected {
+            self.select_tab(index);
+        }
+        if let Some(index) = closed {
+            self.close_tab(index);
+        }
+    }
+
+    /// Перетащенные в окно файлы и копирование с вставкой с клавиатуры
+    fn handle_file_input(&mut self, ctx: &egui::Context) {
+        let dropped: Vec<_> = ctx.input(|i| {
+            i.raw
+                .dropped_files
+                .iter()
+                .filter_map(|f| f.path.clone())
+                .collect()
+        });
+        for path in dropped {
+            self.open_path(ctx, path);
+        }
+
+        // Ctrl+C и Ctrl+V в текстовых полях остаются за полями
+        if !ctx.wants_keyboard_input() {
+            let (copy, paste) = ctx.input(|i| {
+                let copy = i.events.iter().any(|e| matches!(e, egui::Event::Copy));
+                let paste = i.events.iter().find_map(|e| match e {
+                    egui::Event::Paste(text) => Some(text.clone()),
+                    _ => None,
+                });
+                (copy, paste)
+            });
+            if copy {
+                self.copy_result();
+            }
+            if let Some(text) = paste {
+                self.paste_image(ctx, Some(&text));
+            }
+        }
+
+        // Подсказка, пока файл тащат над окном
+        if ctx.input(|i| !i.raw.hovered_files.is_empty()) {
+            let painter = ctx.layer_painter(egui::LayerId::new(
+                egui::Order::Foreground,
+                egui::Id::new("file_drop"),
+            ));
+            let rect = ctx.screen_rect();
+            painter.rect_filled(rect, 0.0, egui::Color32::from_black_alpha(160));
+            painter.text(
+                rect.center(),
+                egui::Align2::CENTER_CENTER,
+                "Drop image to open",
+                egui::FontId::proportional(24.0),
+                egui::Color32::WHITE,
+            );
+        }
+    }
+}
+
+/// Разность выровненных буферов в виде view и метрики качества для фонового задания.
+/// gain None - растянуть разность на весь диапазон. Ok(None) - задание отменили.
+fn difference_with_metrics(
+    buf_1: &[u8],
+    buf_2: &[u8],
+    size: (usize, usize),
+    view: image_op::DiffView,
+    gain: Option<f32>,
+    progress: &jobs::Progress,
+) -> Result<Option<(Vec<u8>, image_op::ImageMetrics)>, AppError> {
+    image_op::check_same_size(buf_1, buf_2)?;
+    let gain = match gain {
+        Some(gain) => gain,
+        None => image_op::stretch_gain(buf_1, buf_2, view)?,
+    };
+    progress.set(0.1);
+
+    let mut diff = buf_1.to_vec();
+    let mut offset = 0;
+    let done = jobs::for_chunks(&mut diff, 3, progress, (0.1, 0.3), |chunk| {
+        let other = &buf_2[offset..offset + chunk.len()];
+        offset += chunk.len();
+        // куски одного размера, разность всегда посчитается
+        if let Ok(part) = image_op::compute_difference_view(chunk, other, view, gain) {
+            chunk.copy_from_slice(&part);
+        }
+    });
+    if !done {
+        return Ok(None);
+    }
+
+    let metrics = image_op::compute_metrics_with_progress(buf_1, buf_2, size.0, size.1, |done| {
+        progress.set(0.3 + 0.7 * done);
+        !progress.is_cancelled()
+    })?;
+    Ok(metrics.map(|metrics| (diff, metrics)))
+}
+
+/// Диалог открытия картинок, начинающийся с папки dir
+fn image_file_dialog(dir: Option<&std::path::Path>) -> rfd::FileDialog {
+    // расширения всех форматов, которые умеет читать image, в том числе exr, hdr, webp, qoi и avif
+    let extensions: Vec<&str> = image::ImageFormat::all()
+        .filter(|format| format.reading_enabled())
+        .flat_map(|format| format.extensions_str().iter().copied())
+        .collect();
+    let dialog = rfd::FileDialog::new().add_filter("Images", &extensions);
+    match dir {
+        Some(dir) => dialog.set_directory(dir),
+        None => dialog,
+    }
+}
+
+/// Картинка из файла вместе с метаданными
+struct OpenedImage {
+    /// Пиксели, уже повёрнутые по EXIF
+    image: image::DynamicImage,
+    icc_profile: Option<Vec<u8>>,
+    exif: Option<Vec<u8>>,
+    orientation: image::metadata::Orientation,
+}
+
+/// Открыть картинку вместе со встроенными ICC профилем и EXIF
+fn open_image(path: &std::path::Path) -> image::ImageResult<OpenedImage> {
+    use image::ImageDecoder;
+    use image::metadata::Orientation;
+    let mut decoder = image::ImageReader::open(path)?
+        .with_guessed_format()?
+        .into_decoder()?;
+    // картинка без читаемого профиля считается sRGB
+    let icc_profile = decoder.icc_profile().ok().flatten();
+    let mut exif = decoder.exif_metadata().ok().flatten();
+    let orientation = decoder.orientation().unwrap_or(Orientation::NoTransforms);
+    let mut image = image::DynamicImage::from_decoder(decoder)?;
+    metadata::apply_orientation(&mut image, exif.as_deref_mut(), orientation);
+    Ok(OpenedImage {
+        image,
+        icc_profile,
+        exif,
+        orientation,
+    })
+}
+
+/// Открыть картинку так же, как окно: повернуть по EXIF, перевести из профиля
+/// в рабочее пространство space и наложить на фон background, если он задан.
+/// Возвращает картинку и предупреждение о профиле.
+pub fn open_working_image(
+    path: &std::path::Path,
+    space: color_space::RgbSpace,
+    background: Option<[u8; 3]>,
+) -> Result<(RgbImage, Option<String>), AppError> {
+    let opened = open_image(path).map_err(|e| AppError::open(path, e))?;
+    let mut state = ImageState::default();
+    state.decode(opened);
+    let job = state.conversion_job(space).expect("картинка разобрана");
+    let prepared = job(&jobs::Progress::default()).expect("перевод не отменялся");
+    state.set_prepared(prepared);
+    let alpha = match background {
+        Some(background) => AlphaSettings {
+            mode: AlphaMode::Flatten,
+            background,
+        },
+        None => AlphaSettings::default(),
+    };
+    state.apply_alpha(&alpha);
+    let image = state.loaded_image.take().expect("картинка подготовлена");
+    Ok((image, state.profile_warning))
+}
+
+/// Картинка в оттенках серого 1-ым и 2-ым методами
+fn grayscale_pair(image: &RgbImage) -> (Vec<u8>, Vec<u8>) {
+    let mut buf_1 = image.clone().into_raw();
+    image_op::rgb_buffer_to_grayscale1(&mut buf_1);
+    let mut buf_2 = image.clone().into_raw();
+    image_op::rgb_buffer_to_grayscale2(&mut buf_2);
+    (buf_1, buf_2)
+}
+
+/// Глубина цвета по типу пикселей картинки
+fn sample_depth(color: image::ColorType) -> image_op::SampleDepth {
+    use image::ColorType;
+    match color {
+        ColorType::L16 | ColorType::La16 | ColorType::Rgb16 | ColorType::Rgba16 => {
+            image_op::SampleDepth::U16
+        }
+        ColorType::Rgb32F | ColorType::Rgba32F => image_op::SampleDepth::F32,
+        _ => image_op::SampleDepth::U8,
+    }
+}
+
+/// Картинка с плавающей точкой из точного буфера и альфа-канала
+fn precise_image(
+    precise: &[f32],
+    alpha: Option<&[u8]>,
+    size: (usize, usize),
+) -> image::DynamicImage {
+    let (w, h) = (size.0 as u32, size.1 as u32);
+    match alpha {
+        Some(alpha) => {
+            let rgba = precise
+                .chunks_exact(3)
+                .zip(alpha)
+                .flat_map(|(p, &a)| [p[0], p[1], p[2], a as f32 / 255.0])
+                .collect();
+            image::Rgba32FImage::from_raw(w, h, rgba).unwrap().into()
+        }
+        None => image::Rgb32FImage::from_raw(w, h, precise.to_vec())
+            .unwrap()
+            .into(),
+    }
+}
+
+/// Размер картинки (ширина, высота)
+fn image_size(image: &RgbImage) -> (usize, usize) {
+    (image.width() as usize, image.height() as usize)
+}
+
+/// Название вида разности
+fn diff_view_name(view: image_op::DiffView) -> &'static str {
+    match view {
+        image_op::DiffView::Abs => "Abs",
+        image_op::DiffView::Neg => "Neg",
+        image_op::DiffView::Amplified => "Amplified",
+        image_op::DiffView::Signed => "Signed",
+    }
+}
+
+/// Вывести метрики качества в UI
+fn show_metrics(ui: &mut egui::Ui, metrics: &image_op::ImageMetrics) {
+    ui.label(format!("MSE: {:.3}", metrics.mse));
+    if metrics.psnr.is_finite() {
+        ui.label(format!("PSNR: {:.2} dB", metrics.psnr));
+    } else {
+        ui.label("PSNR: ∞ dB");
+    }
+    ui.label(format!("SSIM: {:.4}", metrics.ssim));
+    ui.label(format!("MS-SSIM: {:.4}", metrics.ms_ssim));
 }
 
 impl eframe::App for ColorsApp {
+    fn save(&mut self, storage: &mut dyn eframe::Storage) {
+        self.recent.save(storage);
+        eframe::set_value(storage, SETTINGS_KEY, &self.settings());
+        self.save_session(storage);
+    }
+
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        self.handle_file_input(ctx);
+        self.poll_jobs(ctx);
+
         // Top menu buttons
         egui::TopBottomPanel::top("top_panel").show(ctx, |ui| {
             egui::MenuBar::default().ui(ui, |ui| {
@@ -267,9 +2152,40 @@ impl eframe::App for ColorsApp {
                         self.load_image(ctx);
                     }
 
-                    // Save Image
-                    if ui.button("Save Image").clicked() {
-                        self.save_image();
+                    // Recently opened files
+                    ui.menu_button("Recent files", |ui| {
+                        if let Some(path) = self.recent.ui(ui) {
+                            self.open_path(ctx, path);
+                        }
+                    });
+                    ui.checkbox(&mut self.restore_session, "Restore session on start");
+
+                    // Clipboard
+                    let paste = egui::KeyboardShortcut::new(egui::Modifiers::COMMAND, egui::Key::V);
+                    if ui
+                        .add(
+                            egui::Button::new("Paste image")
+                                .shortcut_text(ctx.format_shortcut(&paste)),
+                        )
+                        .clicked()
+                    {
+                        self.paste_image(ctx, None);
+                    }
+                    let copy = egui::KeyboardShortcut::new(egui::Modifiers::COMMAND, egui::Key::C);
+                    if ui
+                        .add_enabled(
+                            self.image.cur_image.is_some(),
+                            egui::Button::new("Copy result")
+                                .shortcut_text(ctx.format_shortcut(&copy)),
+                        )
+                        .clicked()
+                    {
+                        self.copy_result();
+                    }
+
+                    // Export options and save
+                    if ui.button("Export…").clicked() {
+                        self.export.open = true;
                     }
 
                     // Close app
@@ -278,6 +2194,36 @@ impl eframe::App for ColorsApp {
                     }
                 });
 
+                // View options
+                ui.menu_button("View", |ui| {
+                    ui.checkbox(&mut self.show_inspector, "Pixel inspector");
+                    ui.checkbox(&mut self.show_calculator, "Color calculator");
+                    ui.checkbox(&mut self.show_hsv_wheel, "HSV wheel");
+                    ui.checkbox(&mut self.show_point_cloud, "Point cloud");
+                    ui.checkbox(&mut self.show_chromaticity, "Chromaticity diagram");
+                    ui.checkbox(&mut self.show_metadata, "Metadata");
+                    let log_label = match self.notifications.unread_errors {
+                        0 => "Log".to_string(),
+                        n => format!("Log ({n})"),
+                    };
+                    ui.checkbox(&mut self.show_log, log_label);
+                    ui.separator();
+                    ui.menu_button("Theme", |ui| {
+                        for (theme, name) in [
+                            (egui::ThemePreference::Light, "Light"),
+                            (egui::ThemePreference::Dark, "Dark"),
+                            (egui::ThemePreference::System, "System"),
+                        ] {
+                            if ui.radio_value(&mut self.theme, theme, name).changed() {
+                                ctx.set_theme(self.theme);
+                            }
+                        }
+                    });
+                    ui.menu_button("Alpha", |ui| self.alpha_menu_ui(ctx, ui));
+                    ui.menu_button("Color profile", |ui| self.color_profile_menu_ui(ctx, ui));
+                    ui.menu_button("Working space", |ui| self.working_space_menu_ui(ctx, ui));
+                });
+
                 // Task dialog
                 ui.menu_button("Task", |ui| {
                     // Grayscale
@@ -294,19 +2240,133 @@ impl eframe::App for ColorsApp {
                     if ui.button("HSV").clicked() {
                         self.task = Task::HSV;
                     }
+
+                    // Tone mapping
+                    if ui.button("Tone mapping").clicked() {
+                        self.task = Task::ToneMapping;
+                    }
+
+                    // Color space conversion
+                    if ui.button("Color space").clicked() {
+                        self.task = Task::ColorSpace;
+                    }
                 });
             });
         });
 
+        // Open images
+        if self.image.decoded_image.is_some() || !self.tabs.is_empty() {
+            egui::TopBottomPanel::top("tabs_panel").show(ctx, |ui| self.tabs_ui(ctx, ui));
+        }
+
+        // XYZ/Lab views follow the working space and the space of the shown image
+        self.calculator.space = self.working_space;
+        self.histogram.space = self.image.cur_space;
+        self.point_cloud.rgb_space = self.image.cur_space;
+        self.chromaticity.space = self.image.cur_space;
+        self.image.inspector.spaces = (self.working_space, self.image.cur_space);
+
+        // Color calculator
+        egui::Window::new("Color calculator")
+            .open(&mut self.show_calculator)
+            .resizable(false)
+            .show(ctx, |ui| self.calculator.ui(ui));
+
+        // HSV wheel
+        egui::Window::new("HSV wheel")
+            .open(&mut self.show_hsv_wheel)
+            .default_width(560.0)
+            .show(ctx, |ui| {
+                self.hsv_wheel.ui(
+                    ui,
+                    self.image.hue,
+                    self.image.saturation,
+                    self.image.value,
+                    self.working_space,
+                    self.gamut.mapping,
+                )
+            });
+
+        // 3D point cloud of the current image
+        egui::Window::new("Point cloud")
+            .open(&mut self.show_point_cloud)
+            .default_size([520.0, 520.0])
+            .show(ctx, |ui| self.point_cloud.ui(ui));
+
+        // CIE xy diagram of the current image
+        egui::Window::new("Chromaticity diagram")
+            .open(&mut self.show_chromaticity)
+            .default_size([480.0, 520.0])
+            .show(ctx, |ui| self.chromaticity.ui(ui));
+
+        // Export options with file size estimate
+        if self.export.open && self.export.source.is_none() {
+            self.export.source = self.export_source_info();
+        }
+        let mut export_open = self.export.open;
+        let mut export_action = export::ExportAction::None;
+        egui::Window::new("Export")
+            .open(&mut export_open)
+            .resizable(false)
+            .show(ctx, |ui| export_action = self.export.ui(ui));
+        self.export.open = export_open;
+        match export_action {
+            export::ExportAction::Estimate => self.estimate_export_size(ctx),
+            export::ExportAction::Export => self.save_image(ctx),
+            export::ExportAction::None => {}
+        }
+
+        // Errors and warnings of the session
+        egui::Window::new("Log")
+            .open(&mut self.show_log)
+            .default_size([420.0, 260.0])
+            .show(ctx, |ui| self.notifications.log_ui(ui));
+        self.notifications.show_toasts(ctx);
+
+        // EXIF of the loaded image
+        egui::Window::new("Metadata")
+            .open(&mut self.show_metadata)
+            .resizable(false)
+            .show(ctx, |ui| self.image.metadata.ui(ui));
+
         // Image view
         egui::CentralPanel::default().show(ctx, |ui| {
-            // Bottom histogram
+            // Bottom histogram and scopes
             egui::TopBottomPanel::bottom("bottom_panel")
                 .resizable(true)
                 .default_height(500.0)
                 .height_range(200.0..=500.0)
                 .show_inside(ui, |ui| {
+                    ui.horizontal(|ui| {
+                        for kind in scopes::ScopeKind::ALL {
+                            ui.selectable_value(&mut self.scope, kind, kind.name());
+                        }
+                        if self.image.cur_alpha.is_some()
+                            && self.scope == scopes::ScopeKind::Histogram
+                        {
+                            ui.separator();
+                            ui.checkbox(&mut self.histogram.show_alpha, "alpha");
+                        }
+                    });
+
+                    match self.scope {
+                        scopes::ScopeKind::Histogram => {}
+                        scopes::ScopeKind::Joint => {
+                            self.histogram.show_joint(ui);
+                            return;
+                        }
+                        kind => {
+                            self.scopes.show(ui, kind);
+                            return;
+                        }
+                    }
                     let size = egui::vec2(ui.available_width(), ui.available_height());
+                    if self.show_inspector {
+                        self.histogram
+                            .set_highlighted(self.image.inspector.highlighted());
+                    } else {
+                        self.histogram.set_highlighted(Vec::new());
+                    }
                     self.histogram.show(ui, Some(size));
                 });
 
@@ -318,14 +2378,87 @@ impl eframe::App for ColorsApp {
                     Task::Grayscale => self.left_buttons_grayscale(ctx, ui),
                     Task::RGBChannels => self.left_buttons_rgb_channels(ctx, ui),
                     Task::HSV => self.left_buttons_hsv(ctx, ui),
+                    Task::ToneMapping => self.left_buttons_tone_mapping(ctx, ui),
+                    Task::ColorSpace => self.left_buttons_color_space(ctx, ui),
                 });
 
+            // Pixel inspector
+            if self.show_inspector {
+                egui::SidePanel::right("inspector_panel")
+                    .default_width(260.0)
+                    .show_inside(ui, |ui| self.image.inspector.ui(ui));
+            }
+
             // Image display
-            if let Some(texture) = &self.cur_image_texture {
-                ui.add(egui::Image::new(texture));
+            if let Some(texture) = self.image.cur_image_texture.clone() {
+                ui.horizontal(|ui| {
+                    self.image.view.toolbar_ui(ui);
+                    ui.separator();
+                    self.image.prepare.progress_ui(ui);
+                    self.image.jobs.ui(ui);
+                    self.image.saving.ui(ui);
+                    self.image.comparison.controls_ui(ui, &self.image.history);
+                    if self.image.loaded_depth != image_op::SampleDepth::U8 {
+                        ui.separator();
+                        ui.label(self.image.loaded_depth.name()).on_hover_text(
+                            "Операции Original, Grayscale, каналы и HSV сохраняют точность",
+                        );
+                    }
+                    if let Some(label) = self.image.color.label() {
+                        ui.separator();
+                        ui.label(label).on_hover_text(
+                            "Пиксели переведены из этого профиля в рабочее пространство",
+                        );
+                    }
+                    if self.image.cur_space != color_space::RgbSpace::Srgb {
+                        ui.separator();
+                        ui.label(self.image.cur_space.name())
+                            .on_hover_text("Пространство пикселей, для вывода переводится в sRGB");
+                    }
+                    if let Some(warning) = &self.image.profile_warning {
+                        ui.separator();
+                        ui.colored_label(ui.visuals().warn_fg_color, warning);
+                    }
+                    if let Some(mask) = &self.image.out_of_gamut {
+                        let share = mask.iter().filter(|&&m| m).count() as f32 / mask.len() as f32;
+                        ui.separator();
+                        ui.checkbox(
+                            &mut self.gamut.show_overlay,
+                            format!("out of gamut {:.1}%", share * 100.0),
+                        )
+                        .on_hover_text(format!(
+                            "Пиксели, приведённые в охват способом {}",
+                            self.gamut.mapping.name()
+                        ));
+                        if ui
+                            .color_edit_button_srgb(&mut self.gamut.overlay_color)
+                            .changed()
+                        {
+                            self.image
+                                .update_gamut_overlay(ctx, self.gamut.overlay_color);
+                        }
+                    }
+                });
+                let overlay = self
+                    .image
+                    .gamut_overlay
+                    .as_ref()
+                    .filter(|_| self.gamut.show_overlay);
+                if self.image.comparison.mode == compare::CompareMode::Off {
+                    self.image.view.show(ui, &texture, overlay);
+                } else {
+                    self.image
+                        .comparison
+                        .show(ui, &mut self.image.view, &mut self.image.history);
+                }
+            } else if self.image.prepare.is_running() {
+                self.image.prepare.progress_ui(ui);
             } else {
                 ui.label("Необходимо загрузить картинку.");
             }
+            if self.show_inspector {
+                self.update_inspector();
+            }
         });
     }
 }
@@ -0,0 +1,351 @@
+use egui::*;
+
+use super::color_space::{self, GamutMapping, RgbSpace};
+use super::image_op;
+
+/// Запись цвета в одном из поддерживаемых пространств
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Notation {
+    Rgb,
+    Hex,
+    Hsv,
+    Hsl,
+    Xyz,
+    Lab,
+    Cmyk,
+}
+
+impl Notation {
+    pub const ALL: [Notation; 7] = [
+        Notation::Rgb,
+        Notation::Hex,
+        Notation::Hsv,
+        Notation::Hsl,
+        Notation::Xyz,
+        Notation::Lab,
+        Notation::Cmyk,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Notation::Rgb => "RGB",
+            Notation::Hex => "HEX",
+            Notation::Hsv => "HSV",
+            Notation::Hsl => "HSL",
+            Notation::Xyz => "XYZ",
+            Notation::Lab => "Lab",
+            Notation::Cmyk => "CMYK",
+        }
+    }
+
+    /// Подсказка о формате ввода
+    fn hint(self) -> &'static str {
+        match self {
+            Notation::Rgb => "r, g, b  [0, 255]",
+            Notation::Hex => "#RRGGBB или #RGB",
+            Notation::Hsv => "h°, s%, v%",
+            Notation::Hsl => "h°, s%, l%",
+            Notation::Xyz => "X, Y, Z  (D65, Y [0, 100])",
+            Notation::Lab => "L, a, b  (D65)",
+            Notation::Cmyk => "c%, m%, y%, k%",
+        }
+    }
+
+    /// Записать цвет в этом пространстве; space - пространство RGB составляющих
+    pub fn format(self, [r, g, b]: [u8; 3], space: RgbSpace) -> String {
+        match self {
+            Notation::Rgb => format!("{r}, {g}, {b}"),
+            Notation::Hex => format!("#{r:02X}{g:02X}{b:02X}"),
+            Notation::Hsv => {
+                let [r, g, b] = [r, g, b].map(|c| c as f32 / 255.0);
+                let (h, s, v) = image_op::rgb_f32_to_hsv(r, g, b);
+                format!("{h:.1}°, {s:.1}%, {v:.1}%")
+            }
+            Notation::Hsl => {
+                let (h, s, l) = image_op::rgb_to_hsl(r, g, b);
+                format!("{h:.1}°, {s:.1}%, {l:.1}%")
+            }
+            Notation::Xyz => {
+                let (x, y, z) = image_op::rgb_to_xyz(r, g, b, space);
+                format!("{x:.2}, {y:.2}, {z:.2}")
+            }
+            Notation::Lab => {
+                let (l, a, b) = image_op::rgb_to_lab(r, g, b, space);
+                format!("{l:.2}, {a:.2}, {b:.2}")
+            }
+            Notation::Cmyk => {
+                let (c, m, y, k) = image_op::rgb_to_cmyk(r, g, b);
+                format!("{c:.1}%, {m:.1}%, {y:.1}%, {k:.1}%")
+            }
+        }
+    }
+
+    /// Разобрать цвет, записанный в этом пространстве
+    pub fn parse(self, text: &str, space: RgbSpace) -> Result<[u8; 3], String> {
+        let (r, g, b) = match self {
+            Notation::Hex => return parse_hex(text),
+            Notation::Rgb => {
+                let [r, g, b] = parse_numbers(text)?;
+                for v in [r, g, b] {
+                    if !(0.0..=255.0).contains(&v) {
+                        return Err("RGB должен быть в [0, 255]".to_string());
+                    }
+                }
+                (r.round() as u8, g.round() as u8, b.round() as u8)
+            }
+            Notation::Hsv => {
+                let [h, s, v] = parse_numbers(text)?;
+                let (r, g, b) =
+                    image_op::hsv_to_rgb_f32(h, s.clamp(0.0, 100.0), v.clamp(0.0, 100.0));
+                let [r, g, b] = [r, g, b].map(|c| (c * 255.0).round() as u8);
+                (r, g, b)
+            }
+            Notation::Hsl => {
+                let [h, s, l] = parse_numbers(text)?;
+                image_op::hsl_to_rgb(h, s, l)
+            }
+            Notation::Xyz => {
+                let [x, y, z] = parse_numbers(text)?;
+                image_op::xyz_to_rgb(x, y, z, space)
+            }
+            Notation::Lab => {
+                let [l, a, b] = parse_numbers(text)?;
+                image_op::lab_to_rgb(l, a, b, space)
+            }
+            Notation::Cmyk => {
+                let [c, m, y, k] = parse_numbers(text)?;
+                image_op::cmyk_to_rgb(c, m, y, k)
+            }
+        };
+        Ok([r, g, b])
+    }
+}
+
+/// Калькулятор: цвет, введённый в одном пространстве, во всех остальных
+pub struct ColorCalculator {
+    /// Пространство RGB составляющих, от него зависят XYZ и Lab
+    pub space: RgbSpace,
+    /// Пространство, в котором записаны поля
+    formatted_for: RgbSpace,
+    rgb: [u8; 3],
+    /// Текст в полях ввода, по одному на каждое пространство
+    texts: Vec<String>,
+    /// Ошибка разбора и пространство, в котором она возникла
+    error: Option<(Notation, String)>,
+}
+
+impl Default for ColorCalculator {
+    fn default() -> Self {
+        let mut calculator = Self {
+            space: RgbSpace::default(),
+            formatted_for: RgbSpace::default(),
+            rgb: [255, 128, 0],
+            texts: vec![String::new(); Notation::ALL.len()],
+            error: None,
+        };
+        calculator.set_rgb(calculator.rgb, None);
+        calculator
+    }
+}
+
+impl ColorCalculator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Выставить цвет и переписать все поля, кроме редактируемого
+    pub fn set_rgb(&mut self, rgb: [u8; 3], editing: Option<Notation>) {
+        self.rgb = rgb;
+        self.formatted_for = self.space;
+        for (text, notation) in self.texts.iter_mut().zip(Notation::ALL) {
+            if Some(notation) != editing {
+                *text = notation.format(rgb, self.space);
+            }
+        }
+    }
+
+    /// Окно калькулятора
+    pub fn ui(&mut self, ui: &mut Ui) {
+        if self.formatted_for != self.space {
+            self.set_rgb(self.rgb, None);
+        }
+        ui.label(format!("RGB: {}", self.space.name()));
+
+        // образец цвета и выбор цвета мышью, на экран цвет выводится в sRGB, как картинка
+        ui.horizontal(|ui| {
+            let mut shown = self.rgb;
+            color_space::convert_buffer(&mut shown, self.space, RgbSpace::Srgb, GamutMapping::Clip);
+            let (rect, _) = ui.allocate_exact_size(vec2(64.0, 40.0), Sense::hover());
+            ui.painter()
+                .rect_filled(rect, 4.0, Color32::from_rgb(shown[0], shown[1], shown[2]));
+
+            if ui.color_edit_button_srgb(&mut shown).changed() {
+                color_space::convert_buffer(
+                    &mut shown,
+                    RgbSpace::Srgb,
+                    self.space,
+                    GamutMapping::Clip,
+                );
+                self.error = None;
+                self.set_rgb(shown, None);
+            }
+        });
+
+        let mut edited = None;
+        Grid::new("color_calculator").num_columns(2).show(ui, |ui| {
+            for (i, notation) in Notation::ALL.into_iter().enumerate() {
+                ui.label(notation.name());
+                let response = ui.add(
+                    TextEdit::singleline(&mut self.texts[i])
+                        .hint_text(notation.hint())
+                        .desired_width(220.0),
+                );
+                if response.changed() {
+                    edited = Some(notation);
+                }
+                // после редактирования поле приводится к общему виду
+                if response.lost_focus() && self.error.is_none() {
+                    self.texts[i] = notation.format(self.rgb, self.space);
+                }
+                ui.end_row();
+            }
+        });
+
+        if let Some(notation) = edited {
+            let index = Notation::ALL.iter().position(|&n| n == notation).unwrap();
+            match notation.parse(&self.texts[index], self.space) {
+                Ok(rgb) => {
+                    self.error = None;
+                    self.set_rgb(rgb, Some(notation));
+                }
+                Err(e) => self.error = Some((notation, e)),
+            }
+        }
+
+        if let Some((notation, error)) = &self.error {
+            ui.colored_label(
+                ui.visuals().error_fg_color,
+                format!("{}: {}", notation.name(), error),
+            );
+        }
+    }
+}
+
+/// Разобрать N чисел, разделённых запятыми или пробелами; знаки ° и % игнорируются
+fn parse_numbers<const N: usize>(text: &str) -> Result<[f32; N], String> {
+    let numbers = text
+        .split(|c: char| c == ',' || c == ';' || c.is_whitespace())
+        .map(|part| part.trim_matches(|c| c == '°' || c == '%'))
+        .filter(|part| !part.is_empty())
+        .map(|part| part.parse::<f32>().map_err(|_| format!("не число: {part}")))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    numbers
+        .try_into()
+        .map_err(|v: Vec<f32>| format!("нужно {} числа, а не {}", N, v.len()))
+}
+
+/// Разобрать #RRGGBB или #RGB
+fn parse_hex(text: &str) -> Result<[u8; 3], String> {
+    let hex = text.trim().trim_start_matches('#');
+    let digits: Vec<u8> = hex
+        .chars()
+        .map(|c| c.to_digit(16).map(|d| d as u8))
+        .collect::<Option<_>>()
+        .ok_or("недопустимый символ")?;
+
+    match digits.as_slice() {
+        [r, g, b] => Ok([r * 17, g * 17, b * 17]),
+        [r1, r2, g1, g2, b1, b2] => Ok([r1 * 16 + r2, g1 * 16 + g2, b1 * 16 + b2]),
+        _ => Err("нужно 3 или 6 шестнадцатеричных цифр".to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Цвета для проверки перевода туда и обратно: серые, основные и произвольные
+    const COLORS: [[u8; 3]; 10] = [
+        [0, 0, 0],
+        [255, 255, 255],
+        [128, 128, 128],
+        [255, 0, 0],
+        [0, 255, 0],
+        [0, 0, 255],
+        [255, 128, 0],
+        [12, 200, 180],
+        [90, 30, 160],
+        [201, 187, 23],
+    ];
+
+    #[test]
+    fn hex_short_and_long() {
+        let space = RgbSpace::Srgb;
+        assert_eq!(Notation::Hex.parse("#abc", space), Ok([0xaa, 0xbb, 0xcc]));
+        assert_eq!(
+            Notation::Hex.parse("#aabbcc", space),
+            Ok([0xaa, 0xbb, 0xcc])
+        );
+        assert_eq!(
+            Notation::Hex.parse(" AABBCC ", space),
+            Ok([0xaa, 0xbb, 0xcc])
+        );
+        assert!(Notation::Hex.parse("#abcd", space).is_err());
+        assert!(Notation::Hex.parse("#gg0000", space).is_err());
+        assert!(Notation::Hex.parse("", space).is_err());
+    }
+
+    #[test]
+    fn bad_input_is_rejected() {
+        let space = RgbSpace::Srgb;
+        assert!(Notation::Rgb.parse("1, 2", space).is_err());
+        assert!(Notation::Rgb.parse("1, 2, 3, 4", space).is_err());
+        assert!(Notation::Rgb.parse("1, x, 3", space).is_err());
+        assert!(Notation::Cmyk.parse("10%, 20%, 30%", space).is_err());
+    }
+
+    #[test]
+    fn out_of_range_values() {
+        let space = RgbSpace::Srgb;
+        // RGB за пределами [0, 255] - ошибка
+        assert!(Notation::Rgb.parse("256, 0, 0", space).is_err());
+        assert!(Notation::Rgb.parse("-1, 0, 0", space).is_err());
+        // оттенок берётся по кругу, насыщенность и яркость обрезаются
+        assert_eq!(
+            Notation::Hsv.parse("360°, 100%, 100%", space),
+            Notation::Hsv.parse("0°, 100%, 100%", space)
+        );
+        assert_eq!(
+            Notation::Hsv.parse("-120°, 150%, 200%", space),
+            Ok([0, 0, 255])
+        );
+    }
+
+    #[test]
+    fn round_trips_within_one() {
+        let space = RgbSpace::Srgb;
+        for notation in [
+            Notation::Rgb,
+            Notation::Hex,
+            Notation::Hsv,
+            Notation::Hsl,
+            Notation::Lab,
+            Notation::Cmyk,
+        ] {
+            for rgb in COLORS {
+                let text = notation.format(rgb, space);
+                let back = notation
+                    .parse(&text, space)
+                    .unwrap_or_else(|e| panic!("{} {text}: {e}", notation.name()));
+                for (a, b) in rgb.into_iter().zip(back) {
+                    assert!(
+                        a.abs_diff(b) <= 1,
+                        "{}: {rgb:?} -> {text} -> {back:?}",
+                        notation.name()
+                    );
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,272 @@
+use egui::*;
+use egui_plot::{Legend, Line, LineStyle, Plot, PlotImage, PlotPoint, Points, Polygon, Text};
+
+use super::color_space::RgbSpace;
+use super::image_op;
+
+/// Наибольшее число пикселей для карты плотности
+const MAX_SAMPLES: usize = 200_000;
+
+/// Область диаграммы, покрытая картой плотности
+const X_RANGE: f32 = 0.8;
+const Y_RANGE: f32 = 0.9;
+
+/// Ячеек карты плотности на единицу x и y
+const BINS_PER_UNIT: f32 = 300.0;
+
+/// Белая точка D65
+const WHITE_POINT_D65: (f64, f64) = (0.3127, 0.3290);
+
+/// Спектральный локус CIE 1931 2°: координаты xy для 380..=700 нм с шагом 5 нм
+const SPECTRAL_LOCUS: [(f64, f64); 65] = [
+    (0.1741, 0.0050),
+    (0.1740, 0.0050),
+    (0.1738, 0.0049),
+    (0.1736, 0.0049),
+    (0.1733, 0.0048),
+    (0.1730, 0.0048),
+    (0.1726, 0.0048),
+    (0.1721, 0.0048),
+    (0.1714, 0.0051),
+    (0.1703, 0.0058),
+    (0.1689, 0.0069),
+    (0.1669, 0.0086),
+    (0.1644, 0.0109),
+    (0.1611, 0.0138),
+    (0.1566, 0.0177),
+    (0.1510, 0.0227),
+    (0.1440, 0.0297),
+    (0.1355, 0.0399),
+    (0.1241, 0.0578),
+    (0.1096, 0.0868),
+    (0.0913, 0.1327),
+    (0.0687, 0.2007),
+    (0.0454, 0.2950),
+    (0.0235, 0.4127),
+    (0.0082, 0.5384),
+    (0.0039, 0.6548),
+    (0.0139, 0.7502),
+    (0.0389, 0.8120),
+    (0.0743, 0.8338),
+    (0.1142, 0.8262),
+    (0.1547, 0.8059),
+    (0.1929, 0.7816),
+    (0.2296, 0.7543),
+    (0.2658, 0.7243),
+    (0.3016, 0.6923),
+    (0.3373, 0.6589),
+    (0.3731, 0.6245),
+    (0.4087, 0.5896),
+    (0.4441, 0.5547),
+    (0.4788, 0.5202),
+    (0.5125, 0.4866),
+    (0.5448, 0.4544),
+    (0.5752, 0.4242),
+    (0.6029, 0.3965),
+    (0.6270, 0.3725),
+    (0.6482, 0.3514),
+    (0.6658, 0.3340),
+    (0.6801, 0.3197),
+    (0.6915, 0.3083),
+    (0.7006, 0.2993),
+    (0.7079, 0.2920),
+    (0.7140, 0.2859),
+    (0.7190, 0.2809),
+    (0.7230, 0.2770),
+    (0.7260, 0.2740),
+    (0.7283, 0.2717),
+    (0.7300, 0.2700),
+    (0.7311, 0.2689),
+    (0.7320, 0.2680),
+    (0.7327, 0.2673),
+    (0.7334, 0.2666),
+    (0.7340, 0.2660),
+    (0.7344, 0.2656),
+    (0.7346, 0.2654),
+    (0.7347, 0.2653),
+];
+
+/// Длины волн, подписанные на локусе
+const LABELED_WAVELENGTHS: [u32; 9] = [460, 480, 490, 500, 520, 540, 560, 580, 600];
+
+/// Координаты цветности основных цветов R, G, B
+type Primaries = [(f64, f64); 3];
+
+/// Треугольники охвата: название, цвет и основные цвета
+const GAMUTS: [(&str, Color32, Primaries); 3] = [
+    (
+        "sRGB",
+        Color32::from_rgb(20, 150, 40),
+        [(0.640, 0.330), (0.300, 0.600), (0.150, 0.060)],
+    ),
+    (
+        "Display P3",
+        Color32::from_rgb(200, 40, 40),
+        [(0.680, 0.320), (0.265, 0.690), (0.150, 0.060)],
+    ),
+    (
+        "Rec.2020",
+        Color32::from_rgb(40, 80, 220),
+        [(0.708, 0.292), (0.170, 0.797), (0.131, 0.046)],
+    ),
+];
+
+/// Диаграмма цветности CIE 1931 xy с картой плотности цветностей пикселей
+#[derive(Default)]
+pub struct Chromaticity {
+    /// Рабочее пространство пикселей
+    pub space: RgbSpace,
+    /// Подвыборка пикселей картинки, RGB буфер
+    samples: Vec<u8>,
+    density: Option<TextureHandle>,
+    /// Пространство, для которого построена карта плотности
+    built_for: Option<RgbSpace>,
+    /// Доля пикселей, не имеющих цветности (чёрных)
+    black_fraction: f32,
+}
+
+impl Chromaticity {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Взять пиксели новой картинки; карта перестраивается, только если они изменились
+    pub fn set_image(&mut self, buf: &[u8]) {
+        let step = (buf.len() / 3).div_ceil(MAX_SAMPLES).max(1);
+        let samples: Vec<u8> = buf
+            .chunks_exact(3)
+            .step_by(step)
+            .flatten()
+            .copied()
+            .collect();
+        if samples != self.samples {
+            self.samples = samples;
+            self.density = None;
+        }
+    }
+
+    /// Окно диаграммы
+    pub fn ui(&mut self, ui: &mut Ui) {
+        if self.samples.is_empty() {
+            ui.label("Необходимо загрузить картинку.");
+            return;
+        }
+        if self.density.is_none() || self.built_for != Some(self.space) {
+            self.build(ui.ctx());
+        }
+        if self.black_fraction > 0.0 {
+            ui.label(format!(
+                "Чёрные пиксели без цветности: {:.1}%",
+                self.black_fraction * 100.0
+            ));
+        }
+
+        let plot = Plot::new("cie_xy")
+            .data_aspect(1.0)
+            .include_x(0.0)
+            .include_x(X_RANGE)
+            .include_y(0.0)
+            .include_y(Y_RANGE)
+            .x_axis_label("x")
+            .y_axis_label("y")
+            .legend(Legend::default());
+
+        plot.show(ui, |plot_ui| {
+            if let Some(density) = &self.density {
+                plot_ui.image(PlotImage::new(
+                    "pixels",
+                    density.id(),
+                    PlotPoint::new(X_RANGE / 2.0, Y_RANGE / 2.0),
+                    vec2(X_RANGE, Y_RANGE),
+                ));
+            }
+
+            // локус, замкнутый линией пурпурных
+            let locus: Vec<[f64; 2]> = SPECTRAL_LOCUS
+                .iter()
+                .chain(&SPECTRAL_LOCUS[..1])
+                .map(|&(x, y)| [x, y])
+                .collect();
+            plot_ui.line(
+                Line::new("spectral locus", locus)
+                    .color(Color32::from_gray(60))
+                    .width(1.5),
+            );
+            for nm in LABELED_WAVELENGTHS {
+                let (x, y) = SPECTRAL_LOCUS[(nm as usize - 380) / 5];
+                plot_ui.text(
+                    Text::new("wavelengths", PlotPoint::new(x, y), format!("{nm}"))
+                        .color(Color32::from_gray(90))
+                        .anchor(label_anchor(x, y)),
+                );
+            }
+
+            for (name, color, primaries) in GAMUTS {
+                let points: Vec<[f64; 2]> = primaries.iter().map(|&(x, y)| [x, y]).collect();
+                plot_ui.polygon(
+                    Polygon::new(name, points)
+                        .stroke(Stroke::new(1.5, color))
+                        .fill_color(Color32::TRANSPARENT)
+                        .style(LineStyle::Solid),
+                );
+            }
+
+            let (x, y) = WHITE_POINT_D65;
+            plot_ui.points(
+                Points::new("D65", vec![[x, y]])
+                    .radius(4.0)
+                    .color(Color32::from_rgb(230, 160, 0)),
+            );
+        });
+    }
+
+    /// Построить карту плотности цветностей пикселей
+    fn build(&mut self, ctx: &Context) {
+        let cols = (X_RANGE * BINS_PER_UNIT) as usize;
+        let rows = (Y_RANGE * BINS_PER_UNIT) as usize;
+        let mut bins = vec![0u32; cols * rows];
+        let mut black = 0;
+
+        for p in self.samples.chunks_exact(3) {
+            let Some((x, y)) = image_op::rgb_to_xy(p[0], p[1], p[2], self.space) else {
+                black += 1;
+                continue;
+            };
+            let col = ((x / X_RANGE * cols as f32) as usize).min(cols - 1);
+            // первая строка текстуры - верх диаграммы
+            let row = (((1.0 - y / Y_RANGE) * rows as f32) as usize).min(rows - 1);
+            bins[row * cols + col] += 1;
+        }
+        self.black_fraction = black as f32 / (self.samples.len() / 3) as f32;
+
+        let max = bins.iter().copied().max().unwrap_or(0).max(1) as f32;
+        let pixels = bins
+            .iter()
+            .map(|&count| {
+                if count == 0 {
+                    return Color32::TRANSPARENT;
+                }
+                let t = (1.0 + count as f32).ln() / (1.0 + max).ln();
+                let alpha = (80.0 + 175.0 * t) as u8;
+                Color32::from_rgba_unmultiplied(20, 20, 20, alpha)
+            })
+            .collect();
+        self.density = Some(ctx.load_texture(
+            "cie_xy_density",
+            ColorImage::new([cols, rows], pixels),
+            TextureOptions::NEAREST,
+        ));
+        self.built_for = Some(self.space);
+    }
+}
+
+/// Подпись длины волны снаружи локуса
+fn label_anchor(x: f64, y: f64) -> Align2 {
+    let (wx, wy) = WHITE_POINT_D65;
+    match (x < wx, y < wy) {
+        (true, true) => Align2::RIGHT_TOP,
+        (true, false) => Align2::RIGHT_BOTTOM,
+        (false, true) => Align2::LEFT_TOP,
+        (false, false) => Align2::LEFT_BOTTOM,
+    }
+}
@@ -0,0 +1,52 @@
+use std::borrow::Cow;
+
+use super::error::AppError;
+
+/// Системный буфер обмена для картинок.
+/// Соединение держится всё время работы: на Linux скопированная картинка
+/// доступна другим программам, только пока оно открыто.
+#[derive(Default)]
+pub struct Clipboard {
+    inner: Option<arboard::Clipboard>,
+}
+
+impl Clipboard {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn get(&mut self) -> Result<&mut arboard::Clipboard, AppError> {
+        if self.inner.is_none() {
+            self.inner = Some(arboard::Clipboard::new().map_err(AppError::clipboard)?);
+        }
+        Ok(self.inner.as_mut().unwrap())
+    }
+
+    /// Картинка из буфера обмена
+    pub fn paste_image(&mut self) -> Result<image::RgbaImage, AppError> {
+        let data = self.get()?.get_image().map_err(AppError::clipboard)?;
+        image::RgbaImage::from_raw(
+            data.width as u32,
+            data.height as u32,
+            data.bytes.into_owned(),
+        )
+        .ok_or(AppError::BufferSize)
+    }
+
+    /// Положить RGBA картинку в буфер обмена
+    pub fn copy_image(&mut self, rgba: &[u8], size: (usize, usize)) -> Result<(), AppError> {
+        let data = arboard::ImageData {
+            width: size.0,
+            height: size.1,
+            bytes: Cow::Borrowed(rgba),
+        };
+        self.get()?.set_image(data).map_err(AppError::clipboard)
+    }
+}
+
+/// Путь к существующему файлу из вставленного текста, например скопированного в файловом менеджере
+pub fn file_path(text: &str) -> Option<std::path::PathBuf> {
+    let text = text.lines().next()?.trim();
+    let path = std::path::PathBuf::from(text.strip_prefix("file://").unwrap_or(text));
+    path.is_file().then_some(path)
+}
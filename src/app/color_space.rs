@@ -0,0 +1,550 @@
+use super::image_op;
+
+/// Матрица 3x3 по строкам
+type Matrix = [[f32; 3]; 3];
+
+/// Линейный RGB -> XYZ (D65, Y [0, 1]) и обратно для каждого пространства.
+/// ProPhoto определён для D50 и приведён к D65 преобразованием Брэдфорда.
+const SRGB_TO_XYZ: Matrix = [
+    [0.4124564, 0.3575761, 0.1804375],
+    [0.2126729, 0.7151522, 0.072175],
+    [0.0193339, 0.119192, 0.9503041],
+];
+const XYZ_TO_SRGB: Matrix = [
+    [3.2404542, -1.5371385, -0.4985314],
+    [-0.969266, 1.8760108, 0.041556],
+    [0.0556434, -0.2040259, 1.0572252],
+];
+const DISPLAY_P3_TO_XYZ: Matrix = [
+    [0.4865709, 0.2656677, 0.1982173],
+    [0.2289746, 0.6917385, 0.0792869],
+    [0.0, 0.0451134, 1.0439444],
+];
+const XYZ_TO_DISPLAY_P3: Matrix = [
+    [2.493497, -0.9313836, -0.4027108],
+    [-0.829489, 1.7626641, 0.0236247],
+    [0.0358458, -0.0761724, 0.9568845],
+];
+const ADOBE_RGB_TO_XYZ: Matrix = [
+    [0.576669, 0.1855582, 0.1882286],
+    [0.297345, 0.6273636, 0.0752915],
+    [0.0270314, 0.0706889, 0.9913375],
+];
+const XYZ_TO_ADOBE_RGB: Matrix = [
+    [2.0415879, -0.565007, -0.3447314],
+    [-0.9692436, 1.8759675, 0.0415551],
+    [0.0134443, -0.1183624, 1.015175],
+];
+const REC2020_TO_XYZ: Matrix = [
+    [0.636958, 0.1446169, 0.168881],
+    [0.2627002, 0.6779981, 0.0593017],
+    [0.0, 0.0280727, 1.0609851],
+];
+const XYZ_TO_REC2020: Matrix = [
+    [1.7166512, -0.3556708, -0.2533663],
+    [-0.6666844, 1.6164812, 0.0157685],
+    [0.0176399, -0.0427706, 0.9421031],
+];
+const PROPHOTO_TO_XYZ: Matrix = [
+    [0.7555849, 0.112724, 0.082147],
+    [0.2683183, 0.7151232, 0.0165585],
+    [0.003916, -0.0129336, 1.0980753],
+];
+const XYZ_TO_PROPHOTO: Matrix = [
+    [1.4032029, -0.2230229, -0.1016105],
+    [-0.5262303, 1.4816174, 0.0170251],
+    [-0.0112023, 0.0182464, 0.9112472],
+];
+
+/// Рабочее RGB пространство: основные цвета, белая точка и передаточная функция
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum RgbSpace {
+    #[default]
+    Srgb,
+    DisplayP3,
+    AdobeRgb,
+    Rec2020,
+    ProPhoto,
+}
+
+impl RgbSpace {
+    pub const ALL: [RgbSpace; 5] = [
+        RgbSpace::Srgb,
+        RgbSpace::DisplayP3,
+        RgbSpace::AdobeRgb,
+        RgbSpace::Rec2020,
+        RgbSpace::ProPhoto,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            RgbSpace::Srgb => "sRGB",
+            RgbSpace::DisplayP3 => "Display P3",
+            RgbSpace::AdobeRgb => "Adobe RGB (1998)",
+            RgbSpace::Rec2020 => "Rec.2020",
+            RgbSpace::ProPhoto => "ProPhoto RGB",
+        }
+    }
+
+    fn to_xyz_matrix(self) -> &'static Matrix {
+        match self {
+            RgbSpace::Srgb => &SRGB_TO_XYZ,
+            RgbSpace::DisplayP3 => &DISPLAY_P3_TO_XYZ,
+            RgbSpace::AdobeRgb => &ADOBE_RGB_TO_XYZ,
+            RgbSpace::Rec2020 => &REC2020_TO_XYZ,
+            RgbSpace::ProPhoto => &PROPHOTO_TO_XYZ,
+        }
+    }
+
+    fn xyz_to_rgb_matrix(self) -> &'static Matrix {
+        match self {
+            RgbSpace::Srgb => &XYZ_TO_SRGB,
+            RgbSpace::DisplayP3 => &XYZ_TO_DISPLAY_P3,
+            RgbSpace::AdobeRgb => &XYZ_TO_ADOBE_RGB,
+            RgbSpace::Rec2020 => &XYZ_TO_REC2020,
+            RgbSpace::ProPhoto => &XYZ_TO_PROPHOTO,
+        }
+    }
+
+    /// Гамма-кодированная составляющая [0, 1] в линейную.
+    /// Отрицательные значения отражаются, чтобы не терять цвета вне охвата.
+    pub fn to_linear(self, c: f32) -> f32 {
+        let v = c.abs();
+        let linear = match self {
+            RgbSpace::Srgb | RgbSpace::DisplayP3 => image_op::srgb_to_linear(v),
+            RgbSpace::AdobeRgb => v.powf(563.0 / 256.0),
+            RgbSpace::Rec2020 => {
+                if v < 0.081 {
+                    v / 4.5
+                } else {
+                    ((v + 0.099) / 1.099).powf(1.0 / 0.45)
+                }
+            }
+            RgbSpace::ProPhoto => v.powf(1.8),
+        };
+        linear.copysign(c)
+    }
+
+    /// Линейная составляющая в гамма-кодированную
+    pub fn from_linear(self, c: f32) -> f32 {
+        let v = c.abs();
+        let encoded = match self {
+            RgbSpace::Srgb | RgbSpace::DisplayP3 => image_op::linear_to_srgb(v),
+            RgbSpace::AdobeRgb => v.powf(256.0 / 563.0),
+            RgbSpace::Rec2020 => {
+                if v < 0.018 {
+                    v * 4.5
+                } else {
+                    1.099 * v.powf(0.45) - 0.099
+                }
+            }
+            RgbSpace::ProPhoto => v.powf(1.0 / 1.8),
+        };
+        encoded.copysign(c)
+    }
+
+    /// Линейный RGB в XYZ (D65), Y [0, 1]
+    pub fn linear_to_xyz(self, rgb: [f32; 3]) -> [f32; 3] {
+        multiply(self.to_xyz_matrix(), rgb)
+    }
+
+    /// XYZ (D65) в линейный RGB, без обрезки
+    pub fn xyz_to_linear(self, xyz: [f32; 3]) -> [f32; 3] {
+        multiply(self.xyz_to_rgb_matrix(), xyz)
+    }
+
+    /// Яркость Y линейного RGB
+    pub fn luminance(self, [r, g, b]: [f32; 3]) -> f32 {
+        let [wr, wg, wb] = self.to_xyz_matrix()[1];
+        wr * r + wg * g + wb * b
+    }
+}
+
+/// XYZ (D65) -> LMS и LMS' -> OKLab и обратно
+const XYZ_TO_LMS: Matrix = [
+    [0.818933, 0.3618667, -0.1288597],
+    [0.0329845, 0.9293119, 0.0361456],
+    [0.0482003, 0.2643663, 0.6338517],
+];
+const LMS_TO_XYZ: Matrix = [
+    [1.227014, -0.5578, 0.2812561],
+    [-0.0405802, 1.1122569, -0.0716767],
+    [-0.0763813, -0.421482, 1.5861632],
+];
+const LMS_TO_OKLAB: Matrix = [
+    [0.2104543, 0.7936178, -0.004072],
+    [1.9779985, -2.4285922, 0.4505937],
+    [0.025904, 0.7827718, -0.8086758],
+];
+const OKLAB_TO_LMS: Matrix = [
+    [1.0, 0.3963378, 0.2158038],
+    [1.0, -0.1055613, -0.0638542],
+    [1.0, -0.0894842, -1.2914855],
+];
+
+/// Допуск, в пределах которого линейный цвет считается попавшим в охват.
+/// Больше погрешности матриц перевода (белый уходит на 2.5e-4), меньше шага 8 бит.
+const GAMUT_EPSILON: f32 = 1e-3;
+
+/// Колено сжатия насыщенности: доля предельной насыщенности,
+/// до которой цвета не меняются
+const COMPRESS_KNEE: f32 = 0.8;
+
+/// Линейный цвет в пределах [0, 1]
+pub fn in_gamut(rgb: [f32; 3]) -> bool {
+    rgb.iter()
+        .all(|&c| (-GAMUT_EPSILON..=1.0 + GAMUT_EPSILON).contains(&c))
+}
+
+fn linear_to_oklab(rgb: [f32; 3], space: RgbSpace) -> [f32; 3] {
+    let lms = multiply(&XYZ_TO_LMS, space.linear_to_xyz(rgb));
+    multiply(&LMS_TO_OKLAB, lms.map(f32::cbrt))
+}
+
+fn oklab_to_linear(lab: [f32; 3], space: RgbSpace) -> [f32; 3] {
+    let lms = multiply(&OKLAB_TO_LMS, lab).map(|c| c * c * c);
+    space.xyz_to_linear(multiply(&LMS_TO_XYZ, lms))
+}
+
+/// Линейный цвет с той же светлотой и тоном OKLCh, но насыщенностью chroma
+fn with_chroma(l: f32, [a, b]: [f32; 2], chroma: f32, space: RgbSpace) -> [f32; 3] {
+    oklab_to_linear([l, a * chroma, b * chroma], space)
+}
+
+/// Наибольшая насыщенность OKLCh в пределах [0, limit], при которой цвет
+/// светлоты l и тона hue (единичный вектор) ещё в охвате
+fn max_chroma(l: f32, hue: [f32; 2], limit: f32, space: RgbSpace) -> f32 {
+    let (mut lo, mut hi) = (0.0, limit);
+    for _ in 0..16 {
+        let mid = (lo + hi) / 2.0;
+        if in_gamut(with_chroma(l, hue, mid, space)) {
+            lo = mid;
+        } else {
+            hi = mid;
+        }
+    }
+    lo
+}
+
+/// Способ приведения цветов, не попадающих в охват пространства
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum GamutMapping {
+    /// Обрезать каждую составляющую отдельно
+    #[default]
+    Clip,
+    /// Уменьшить составляющие пропорционально, сохранив их отношения
+    Scale,
+    /// Снизить насыщенность OKLCh до границы охвата, сохранив светлоту и тон
+    Desaturate,
+    /// Плавно сжать насыщенность OKLCh у границы охвата, не трогая спокойные цвета
+    Compress,
+}
+
+impl GamutMapping {
+    pub const ALL: [GamutMapping; 4] = [
+        GamutMapping::Clip,
+        GamutMapping::Scale,
+        GamutMapping::Desaturate,
+        GamutMapping::Compress,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            GamutMapping::Clip => "Clip",
+            GamutMapping::Scale => "Scale to fit",
+            GamutMapping::Desaturate => "Desaturate (OKLCh)",
+            GamutMapping::Compress => "Compress chroma",
+        }
+    }
+
+    /// Привести линейный цвет пространства space в [0, 1]
+    pub fn apply(self, rgb: [f32; 3], space: RgbSpace) -> [f32; 3] {
+        match self {
+            GamutMapping::Clip => clip(rgb),
+            GamutMapping::Scale => {
+                let [r, g, b] = rgb.map(|c| c.max(0.0));
+                let max = r.max(g).max(b).max(1.0);
+                [r / max, g / max, b / max]
+            }
+            GamutMapping::Desaturate | GamutMapping::Compress => {
+                let [l, a, b] = linear_to_oklab(rgb, space);
+                let chroma = a.hypot(b);
+                if chroma < 1e-6 {
+                    return clip(rgb);
+                }
+                let hue = [a / chroma, b / chroma];
+                let mapped = if self == GamutMapping::Desaturate {
+                    if in_gamut(rgb) {
+                        return clip(rgb);
+                    }
+                    max_chroma(l, hue, chroma, space)
+                } else {
+                    let limit = max_chroma(l, hue, chroma.max(0.5), space);
+                    compress(chroma, limit)
+                };
+                // светлота вне [0, 1] не достижима и без насыщенности
+                clip(with_chroma(l, hue, mapped, space))
+            }
+        }
+    }
+}
+
+fn clip(rgb: [f32; 3]) -> [f32; 3] {
+    rgb.map(|c| c.clamp(0.0, 1.0))
+}
+
+/// Сжатие насыщенности chroma к пределу limit: до колена без изменений,
+/// выше асимптотически приближается к пределу
+fn compress(chroma: f32, limit: f32) -> f32 {
+    if limit <= 0.0 {
+        return 0.0;
+    }
+    let knee = COMPRESS_KNEE * limit;
+    if chroma <= knee {
+        return chroma;
+    }
+    let range = limit - knee;
+    knee + range * ((chroma - knee) / range).tanh()
+}
+
+/// Привести линейный цвет в охват; true, если он был вне охвата.
+/// Сжатие насыщенности затрагивает и цвета у границы охвата.
+fn map_pixel(rgb: [f32; 3], space: RgbSpace, mapping: GamutMapping) -> ([f32; 3], bool) {
+    let outside = !in_gamut(rgb);
+    if outside || mapping == GamutMapping::Compress {
+        (mapping.apply(rgb, space), outside)
+    } else {
+        (clip(rgb), false)
+    }
+}
+
+/// Привести линейный HDR цвет в охват по цветности; true, если он был вне охвата.
+/// Яркость выше 1 сохраняется: цвет приводится в охват, поделённый на наибольшую
+/// составляющую, и умножается на неё обратно.
+fn map_hdr_pixel(rgb: [f32; 3], space: RgbSpace, mapping: GamutMapping) -> ([f32; 3], bool) {
+    let scale = rgb.iter().fold(1.0f32, |max, &c| max.max(c));
+    let (mapped, outside) = map_pixel(rgb.map(|c| c / scale), space, mapping);
+    (mapped.map(|c| c * scale), outside)
+}
+
+/// Привести гамма-кодированный буфер пространства space, составляющие которого
+/// могут выходить за [0, 1], в охват. Возвращает маску пикселей вне охвата.
+pub fn map_gamut(buf: &mut [f32], space: RgbSpace, mapping: GamutMapping) -> Vec<bool> {
+    buf.chunks_exact_mut(3)
+        .map(|p| {
+            let linear = [0, 1, 2].map(|c| space.to_linear(p[c]));
+            let (rgb, outside) = map_pixel(linear, space, mapping);
+            for c in 0..3 {
+                p[c] = space.from_linear(rgb[c]);
+            }
+            outside
+        })
+        .collect()
+}
+
+/// Привести линейный буфер пространства space в охват.
+/// Возвращает маску пикселей вне охвата.
+pub fn map_linear_gamut(buf: &mut [f32], space: RgbSpace, mapping: GamutMapping) -> Vec<bool> {
+    buf.chunks_exact_mut(3)
+        .map(|p| {
+            let (rgb, outside) = map_pixel([p[0], p[1], p[2]], space, mapping);
+            p.copy_from_slice(&rgb);
+            outside
+        })
+        .collect()
+}
+
+/// Привести линейный HDR буфер пространства space в охват, не обрезая яркость сверху.
+/// Возвращает маску пикселей вне охвата.
+pub fn map_hdr_gamut(buf: &mut [f32], space: RgbSpace, mapping: GamutMapping) -> Vec<bool> {
+    buf.chunks_exact_mut(3)
+        .map(|p| {
+            let (rgb, outside) = map_hdr_pixel([p[0], p[1], p[2]], space, mapping);
+            p.copy_from_slice(&rgb);
+            outside
+        })
+        .collect()
+}
+
+/// Матрица перевода линейного RGB из пространства from в to
+fn conversion_matrix(from: RgbSpace, to: RgbSpace) -> Matrix {
+    let (a, b) = (to.xyz_to_rgb_matrix(), from.to_xyz_matrix());
+    std::array::from_fn(|i| std::array::from_fn(|j| (0..3).map(|k| a[i][k] * b[k][j]).sum()))
+}
+
+fn multiply(m: &Matrix, v: [f32; 3]) -> [f32; 3] {
+    std::array::from_fn(|i| m[i][0] * v[0] + m[i][1] * v[1] + m[i][2] * v[2])
+}
+
+/// Перевести 8-битный RGB буфер из пространства from в to.
+/// Возвращает маску пикселей, не попавших в охват to.
+pub fn convert_buffer(
+    buf: &mut [u8],
+    from: RgbSpace,
+    to: RgbSpace,
+    mapping: GamutMapping,
+) -> Vec<bool> {
+    if from == to {
+        return vec![false; buf.len() / 3];
+    }
+    let matrix = conversion_matrix(from, to);
+    let to_linear: Vec<f32> = (0..=255u8)
+        .map(|c| from.to_linear(c as f32 / 255.0))
+        .collect();
+    buf.chunks_exact_mut(3)
+        .map(|p| {
+            let linear = multiply(&matrix, [0, 1, 2].map(|c| to_linear[p[c] as usize]));
+            let (rgb, outside) = map_pixel(linear, to, mapping);
+            for c in 0..3 {
+                p[c] = (to.from_linear(rgb[c]) * 255.0).round() as u8;
+            }
+            outside
+        })
+        .collect()
+}
+
+/// Перевести точный RGB буфер из пространства from в to.
+/// Линейные HDR данные (linear) не кодируются и не обрезаются сверху,
+/// в охват приводится только их цветность.
+/// Возвращает маску пикселей, не попавших в охват to.
+pub fn convert_f32_buffer(
+    buf: &mut [f32],
+    from: RgbSpace,
+    to: RgbSpace,
+    mapping: GamutMapping,
+    linear: bool,
+) -> Vec<bool> {
+    if from == to {
+        return vec![false; buf.len() / 3];
+    }
+    let matrix = conversion_matrix(from, to);
+    if linear {
+        return buf
+            .chunks_exact_mut(3)
+            .map(|p| {
+                let rgb = multiply(&matrix, [p[0], p[1], p[2]]);
+                let (rgb, outside) = map_hdr_pixel(rgb, to, mapping);
+                p.copy_from_slice(&rgb);
+                outside
+            })
+            .collect();
+    }
+    for p in buf.chunks_exact_mut(3) {
+        let rgb = multiply(&matrix, [0, 1, 2].map(|c| from.to_linear(p[c])));
+        let rgb = rgb.map(|c| to.from_linear(c));
+        p.copy_from_slice(&rgb);
+    }
+    map_gamut(buf, to, mapping)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Насыщенные и спокойные цвета sRGB
+    const SAMPLES: [[u8; 3]; 6] = [
+        [255, 0, 0],
+        [0, 255, 0],
+        [0, 0, 255],
+        [128, 128, 128],
+        [200, 150, 100],
+        [255, 255, 255],
+    ];
+
+    #[test]
+    fn in_gamut_round_trip_is_unchanged() {
+        let original: Vec<f32> = SAMPLES.concat().iter().map(|&c| c as f32 / 255.0).collect();
+        let mut buf = original.clone();
+        for (from, to) in [
+            (RgbSpace::Srgb, RgbSpace::DisplayP3),
+            (RgbSpace::DisplayP3, RgbSpace::Srgb),
+        ] {
+            let mask = convert_f32_buffer(&mut buf, from, to, GamutMapping::Clip, false);
+            assert!(!mask.contains(&true), "{from:?} -> {to:?}");
+        }
+        for (a, b) in original.iter().zip(&buf) {
+            assert!((a - b).abs() < 1e-3, "{original:?} -> {buf:?}");
+        }
+
+        // цвет в охвате не меняется ни одним способом, кроме сжатия у границы
+        let rgb = [0.2, 0.5, 0.7];
+        for mapping in [
+            GamutMapping::Clip,
+            GamutMapping::Scale,
+            GamutMapping::Desaturate,
+        ] {
+            assert_eq!(map_pixel(rgb, RgbSpace::Srgb, mapping), (rgb, false));
+        }
+    }
+
+    #[test]
+    fn p3_primaries_are_out_of_srgb_gamut() {
+        let mut buf = vec![255, 0, 0, 0, 255, 0, 0, 0, 255, 255, 255, 255];
+        let mask = convert_buffer(
+            &mut buf,
+            RgbSpace::DisplayP3,
+            RgbSpace::Srgb,
+            GamutMapping::Clip,
+        );
+        assert_eq!(mask, [true, true, true, false]);
+    }
+
+    #[test]
+    fn desaturate_and_compress_end_in_gamut() {
+        let outside = [
+            [1.2, -0.1, 0.3],
+            [-0.2, 0.9, 0.1],
+            [0.1, 0.2, 1.5],
+            [1.0, 0.0, -0.3],
+        ];
+        for mapping in [GamutMapping::Desaturate, GamutMapping::Compress] {
+            for rgb in outside {
+                let (mapped, flagged) = map_pixel(rgb, RgbSpace::Srgb, mapping);
+                assert!(flagged);
+                assert!(
+                    mapped.iter().all(|c| (0.0..=1.0).contains(c)),
+                    "{mapping:?} {mapped:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn max_chroma_reaches_gamut_boundary() {
+        let [l, a, b] = linear_to_oklab([1.0, 0.0, 0.0], RgbSpace::DisplayP3);
+        let chroma = a.hypot(b);
+        let hue = [a / chroma, b / chroma];
+        let limit = max_chroma(l, hue, chroma, RgbSpace::Srgb);
+        assert!(limit > 0.0 && limit < chroma);
+        assert!(in_gamut(with_chroma(l, hue, limit, RgbSpace::Srgb)));
+        assert!(!in_gamut(with_chroma(l, hue, limit * 1.01, RgbSpace::Srgb)));
+    }
+
+    #[test]
+    fn linear_hdr_keeps_highlights_and_flags_chroma() {
+        // серый ярче белого остаётся в охвате и не обрезается
+        let mut gray = vec![4.0, 4.0, 4.0];
+        let mask = convert_f32_buffer(
+            &mut gray,
+            RgbSpace::Srgb,
+            RgbSpace::DisplayP3,
+            GamutMapping::Clip,
+            true,
+        );
+        assert_eq!(mask, [false]);
+        assert!(gray.iter().all(|c| (c - 4.0).abs() < 1e-3), "{gray:?}");
+
+        // яркий красный P3 вне sRGB: цветность приводится, яркость выше 1 остаётся
+        let mut red = vec![2.0, 0.0, 0.0];
+        let mask = convert_f32_buffer(
+            &mut red,
+            RgbSpace::DisplayP3,
+            RgbSpace::Srgb,
+            GamutMapping::Desaturate,
+            true,
+        );
+        assert_eq!(mask, [true]);
+        assert!(red.iter().all(|&c| c >= 0.0), "{red:?}");
+        assert!(red[0] > 1.0, "{red:?}");
+    }
+}
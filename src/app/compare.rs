@@ -0,0 +1,230 @@
+use egui::*;
+
+use super::history::History;
+use super::viewer::ImageView;
+
+/// Режим сравнения двух картинок
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompareMode {
+    /// Только текущий результат
+    #[default]
+    Off,
+    /// Две картинки рядом
+    SideBySide,
+    /// Шторка: слева первая картинка, справа вторая
+    Swipe,
+    /// Картинки сменяют друг друга
+    Flicker,
+}
+
+impl CompareMode {
+    pub fn name(self) -> &'static str {
+        match self {
+            CompareMode::Off => "Off",
+            CompareMode::SideBySide => "Side by side",
+            CompareMode::Swipe => "Swipe",
+            CompareMode::Flicker => "Flicker",
+        }
+    }
+}
+
+/// Сравнение двух записей истории
+pub struct Comparison {
+    pub mode: CompareMode,
+    /// Номер первой записи истории, None - оригинал
+    first: Option<u64>,
+    /// Номер второй записи истории, None - текущий результат
+    second: Option<u64>,
+    /// Положение шторки [0, 1]
+    split: f32,
+    /// Период смены картинок в секундах
+    flicker_period: f32,
+}
+
+impl Default for Comparison {
+    fn default() -> Self {
+        Self {
+            mode: CompareMode::Off,
+            first: None,
+            second: None,
+            split: 0.5,
+            flicker_period: 0.5,
+        }
+    }
+}
+
+impl Comparison {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Сбросить выбор картинок (например, после загрузки новой)
+    pub fn reset_selection(&mut self) {
+        self.first = None;
+        self.second = None;
+    }
+
+    /// Индексы сравниваемых записей истории.
+    /// Вытесненная из истории запись заменяется записью по умолчанию.
+    fn indices(&self, history: &History) -> (usize, usize) {
+        let last = history.len().saturating_sub(1);
+        let find = |id: Option<u64>| id.and_then(|id| history.index_of(id));
+        (
+            find(self.first).unwrap_or(0),
+            find(self.second).unwrap_or(last),
+        )
+    }
+
+    /// Панель выбора режима и сравниваемых картинок
+    pub fn controls_ui(&mut self, ui: &mut Ui, history: &History) {
+        ui.horizontal(|ui| {
+            ComboBox::from_label("compare")
+                .selected_text(self.mode.name())
+                .show_ui(ui, |ui| {
+                    for mode in [
+                        CompareMode::Off,
+                        CompareMode::SideBySide,
+                        CompareMode::Swipe,
+                        CompareMode::Flicker,
+                    ] {
+                        ui.selectable_value(&mut self.mode, mode, mode.name());
+                    }
+                });
+
+            if self.mode == CompareMode::Off || history.is_empty() {
+                return;
+            }
+
+            let (first, second) = self.indices(history);
+            history_combo(ui, "first", &mut self.first, first, history, "Original");
+            history_combo(ui, "second", &mut self.second, second, history, "Current");
+
+            if self.mode == CompareMode::Flicker {
+                ui.add(
+                    Slider::new(&mut self.flicker_period, 0.1..=2.0)
+                        .suffix(" s")
+                        .text("period"),
+                );
+            }
+        });
+    }
+
+    /// Нарисовать сравнение на всё оставшееся место
+    pub fn show(&mut self, ui: &mut Ui, view: &mut ImageView, history: &mut History) {
+        let (first, second) = self.indices(history);
+        let (Some(tex_1), Some(tex_2)) = (
+            history.texture(ui.ctx(), first),
+            history.texture(ui.ctx(), second),
+        ) else {
+            return;
+        };
+        let name_1 = history.label(first);
+        let name_2 = history.label(second);
+
+        let (rect, response) = ui.allocate_exact_size(ui.available_size(), Sense::click_and_drag());
+        let painter = ui.painter_at(rect);
+
+        match self.mode {
+            CompareMode::Off => {}
+            CompareMode::SideBySide => {
+                let gap = 4.0;
+                let half = vec2((rect.width() - gap) / 2.0, rect.height());
+                let left = Rect::from_min_size(rect.min, half);
+                let right = Rect::from_min_size(rect.min + vec2(half.x + gap, 0.0), half);
+
+                // масштаб считается в той панели и по той картинке, над которой курсор
+                let (pane, texture) = match response.hover_pos() {
+                    Some(pos) if right.contains(pos) => (right, &tex_2),
+                    _ => (left, &tex_1),
+                };
+                view.handle_input(ui, &response, pane, texture.size_vec2());
+
+                view.paint(&painter, left, left, &tex_1);
+                view.paint(&painter, right, right, &tex_2);
+                painter.vline(
+                    left.right() + gap / 2.0,
+                    rect.y_range(),
+                    ui.visuals().window_stroke(),
+                );
+                pane_label(&painter, left, &name_1);
+                pane_label(&painter, right, &name_2);
+            }
+            CompareMode::Swipe => {
+                let split_x = rect.left() + self.split * rect.width();
+                let handle_rect =
+                    Rect::from_x_y_ranges(split_x - 6.0..=split_x + 6.0, rect.y_range());
+                let handle = ui
+                    .interact(handle_rect, ui.id().with("swipe_handle"), Sense::drag())
+                    .on_hover_cursor(CursorIcon::ResizeHorizontal);
+                if handle.dragged() {
+                    self.split =
+                        (self.split + handle.drag_delta().x / rect.width()).clamp(0.0, 1.0);
+                }
+                let texture = match response.hover_pos() {
+                    Some(pos) if pos.x > split_x => &tex_2,
+                    _ => &tex_1,
+                };
+                view.handle_input(ui, &response, rect, texture.size_vec2());
+
+                let (left, right) = rect.split_left_right_at_x(split_x);
+                view.paint(&painter, rect, left, &tex_1);
+                view.paint(&painter, rect, right, &tex_2);
+
+                let stroke = Stroke::new(2.0, ui.visuals().selection.bg_fill);
+                painter.vline(split_x, rect.y_range(), stroke);
+                painter.circle_filled(pos2(split_x, rect.center().y), 6.0, stroke.color);
+                pane_label(&painter, left, &name_1);
+                pane_label(&painter, right, &name_2);
+            }
+            CompareMode::Flicker => {
+                let period = self.flicker_period as f64;
+                let show_first = (ui.input(|i| i.time) / period) as i64 % 2 == 0;
+                let (texture, name) = if show_first {
+                    (&tex_1, &name_1)
+                } else {
+                    (&tex_2, &name_2)
+                };
+                view.handle_input(ui, &response, rect, texture.size_vec2());
+                view.paint(&painter, rect, rect, texture);
+                pane_label(&painter, rect, name);
+                ui.ctx()
+                    .request_repaint_after(std::time::Duration::from_secs_f64(period / 4.0));
+            }
+        }
+    }
+}
+
+/// Выбор записи истории по её номеру, None - запись по умолчанию
+fn history_combo(
+    ui: &mut Ui,
+    label: &str,
+    selected: &mut Option<u64>,
+    current: usize,
+    history: &History,
+    default_name: &str,
+) {
+    let text = match selected {
+        Some(_) => history.label(current),
+        None => default_name.to_string(),
+    };
+    ComboBox::from_label(label)
+        .selected_text(text)
+        .show_ui(ui, |ui| {
+            ui.selectable_value(selected, None, default_name);
+            for index in 0..history.len() {
+                if let Some(entry) = history.get(index) {
+                    ui.selectable_value(selected, Some(entry.id), history.label(index));
+                }
+            }
+        });
+}
+
+/// Подпись картинки в углу панели
+fn pane_label(painter: &Painter, rect: Rect, text: &str) {
+    let pos = rect.left_top() + vec2(6.0, 6.0);
+    let galley =
+        painter.layout_no_wrap(text.to_string(), FontId::proportional(14.0), Color32::WHITE);
+    let bg = Rect::from_min_size(pos, galley.size()).expand(3.0);
+    painter.rect_filled(bg, 3.0, Color32::from_black_alpha(160));
+    painter.galley(pos, galley, Color32::WHITE);
+}
@@ -0,0 +1,123 @@
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Ошибка операции приложения
+#[derive(Debug)]
+pub enum AppError {
+    /// Файл не удалось открыть или прочитать
+    Read { path: PathBuf, source: io::Error },
+    /// Формат файла не поддерживается
+    UnsupportedFormat { path: PathBuf, details: String },
+    /// Файл повреждён или не декодируется
+    Decode { path: PathBuf, details: String },
+    /// Нет прав на запись файла
+    PermissionDenied { path: PathBuf },
+    /// Файл не удалось записать
+    Write { path: PathBuf, source: io::Error },
+    /// Картинку не удалось закодировать в выбранный формат
+    Encode {
+        format: &'static str,
+        details: String,
+    },
+    /// Картинки разных размеров, в пикселях
+    SizeMismatch { first: usize, second: usize },
+    /// Альфа-канал не совпадает с картинкой по числу пикселей
+    AlphaSize { alpha: usize, pixels: usize },
+    /// Буфер не соответствует размеру картинки
+    BufferSize,
+    /// Картинки не пересекаются при сдвиге
+    NoOverlap { dx: i32, dy: i32 },
+    /// Для сравнения не загружена вторая картинка
+    SecondImageMissing,
+    /// Буфер обмена недоступен или в нём нет картинки
+    Clipboard(String),
+}
+
+impl AppError {
+    /// Ошибка открытия файла path
+    pub fn open(path: &Path, error: image::ImageError) -> Self {
+        let path = path.to_path_buf();
+        match error {
+            image::ImageError::IoError(source) => AppError::Read { path, source },
+            image::ImageError::Unsupported(e) => AppError::UnsupportedFormat {
+                path,
+                details: e.to_string(),
+            },
+            e => AppError::Decode {
+                path,
+                details: e.to_string(),
+            },
+        }
+    }
+
+    /// Ошибка записи файла path
+    pub fn write(path: &Path, source: io::Error) -> Self {
+        let path = path.to_path_buf();
+        match source.kind() {
+            io::ErrorKind::PermissionDenied | io::ErrorKind::ReadOnlyFilesystem => {
+                AppError::PermissionDenied { path }
+            }
+            _ => AppError::Write { path, source },
+        }
+    }
+
+    /// Ошибка кодирования в формат format
+    pub fn encode(format: &'static str, error: image::ImageError) -> Self {
+        AppError::Encode {
+            format,
+            details: error.to_string(),
+        }
+    }
+
+    /// Ошибка работы с буфером обмена
+    pub fn clipboard(error: arboard::Error) -> Self {
+        AppError::Clipboard(error.to_string())
+    }
+}
+
+impl fmt::Display for AppError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AppError::Read { path, source } => {
+                write!(f, "Не удалось прочитать {}: {source}", path.display())
+            }
+            AppError::UnsupportedFormat { path, details } => {
+                write!(f, "Формат {} не поддерживается: {details}", path.display())
+            }
+            AppError::Decode { path, details } => {
+                write!(f, "Не удалось декодировать {}: {details}", path.display())
+            }
+            AppError::PermissionDenied { path } => {
+                write!(f, "Нет прав на запись {}", path.display())
+            }
+            AppError::Write { path, source } => {
+                write!(f, "Не удалось записать {}: {source}", path.display())
+            }
+            AppError::Encode { format, details } => {
+                write!(f, "Не удалось закодировать в {format}: {details}")
+            }
+            AppError::SizeMismatch { first, second } => {
+                write!(f, "Картинки разных размеров: {first} и {second} пикселей")
+            }
+            AppError::AlphaSize { alpha, pixels } => {
+                write!(f, "Альфа-канал на {alpha} пикселей, а картинка на {pixels}")
+            }
+            AppError::BufferSize => write!(f, "Размер буфера не совпадает с размером картинки"),
+            AppError::NoOverlap { dx, dy } => {
+                write!(f, "Картинки не пересекаются при сдвиге ({dx}, {dy})")
+            }
+            AppError::SecondImageMissing => write!(f, "Нужно загрузить обе картинки"),
+            AppError::Clipboard(details) => write!(f, "Буфер обмена: {details}"),
+        }
+    }
+}
+
+impl std::error::Error for AppError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            AppError::Read { source, .. } | AppError::Write { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
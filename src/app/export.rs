@@ -0,0 +1,582 @@
+use std::io::Cursor;
+
+use egui::*;
+use image::codecs::{
+    avif::AvifEncoder,
+    bmp::BmpEncoder,
+    png::{CompressionType, FilterType, PngEncoder},
+    qoi::QoiEncoder,
+    tga::TgaEncoder,
+    tiff::TiffEncoder,
+    webp::WebPEncoder,
+};
+use image::error::{
+    EncodingError, ImageError, LimitError, LimitErrorKind, UnsupportedError, UnsupportedErrorKind,
+};
+use image::{DynamicImage, ImageEncoder, ImageFormat, ImageResult};
+
+use super::error::AppError;
+use super::image_op::SampleDepth;
+use super::jobs::Jobs;
+
+/// Сколько секунд настройки должны не меняться, чтобы размер файла посчитался заново
+const ESTIMATE_DELAY: f64 = 0.4;
+
+/// Формат файла для экспорта
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    #[default]
+    Png,
+    Jpeg,
+    WebP,
+    Tiff,
+    Tga,
+    Qoi,
+    Avif,
+    Bmp,
+}
+
+impl ExportFormat {
+    pub const ALL: [ExportFormat; 8] = [
+        ExportFormat::Png,
+        ExportFormat::Jpeg,
+        ExportFormat::WebP,
+        ExportFormat::Tiff,
+        ExportFormat::Tga,
+        ExportFormat::Qoi,
+        ExportFormat::Avif,
+        ExportFormat::Bmp,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ExportFormat::Png => "PNG",
+            ExportFormat::Jpeg => "JPEG",
+            ExportFormat::WebP => "WebP (lossless)",
+            ExportFormat::Tiff => "TIFF",
+            ExportFormat::Tga => "TGA",
+            ExportFormat::Qoi => "QOI",
+            ExportFormat::Avif => "AVIF",
+            ExportFormat::Bmp => "BMP",
+        }
+    }
+
+    /// Расширения файла, первое - основное
+    pub fn extensions(self) -> &'static [&'static str] {
+        match self {
+            ExportFormat::Png => &["png"],
+            ExportFormat::Jpeg => &["jpg", "jpeg"],
+            ExportFormat::WebP => &["webp"],
+            ExportFormat::Tiff => &["tiff", "tif"],
+            ExportFormat::Tga => &["tga"],
+            ExportFormat::Qoi => &["qoi"],
+            ExportFormat::Avif => &["avif"],
+            ExportFormat::Bmp => &["bmp"],
+        }
+    }
+
+    fn supports_alpha(self) -> bool {
+        self != ExportFormat::Jpeg
+    }
+
+    /// Формат умеет хранить один канал серого
+    fn supports_gray(self) -> bool {
+        self != ExportFormat::Qoi
+    }
+
+    /// Наибольшая глубина цвета, которую умеет записать кодировщик
+    fn max_depth(self) -> SampleDepth {
+        match self {
+            ExportFormat::Png => SampleDepth::U16,
+            ExportFormat::Tiff => SampleDepth::F32,
+            _ => SampleDepth::U8,
+        }
+    }
+
+    /// Формат хранит ICC профиль и EXIF
+    pub fn supports_metadata(self) -> bool {
+        matches!(
+            self,
+            ExportFormat::Png | ExportFormat::Jpeg | ExportFormat::WebP
+        )
+    }
+}
+
+/// Степень сжатия PNG
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum PngCompression {
+    Fast,
+    #[default]
+    Default,
+    Best,
+}
+
+impl PngCompression {
+    pub const ALL: [PngCompression; 3] = [
+        PngCompression::Fast,
+        PngCompression::Default,
+        PngCompression::Best,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            PngCompression::Fast => "Fast",
+            PngCompression::Default => "Default",
+            PngCompression::Best => "Best",
+        }
+    }
+
+    fn compression_type(self) -> CompressionType {
+        match self {
+            PngCompression::Fast => CompressionType::Fast,
+            PngCompression::Default => CompressionType::Default,
+            PngCompression::Best => CompressionType::Best,
+        }
+    }
+}
+
+/// Субдискретизация цветности JPEG
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ChromaSubsampling {
+    #[default]
+    Yuv444,
+    Yuv422,
+    Yuv420,
+}
+
+impl ChromaSubsampling {
+    pub const ALL: [ChromaSubsampling; 3] = [
+        ChromaSubsampling::Yuv444,
+        ChromaSubsampling::Yuv422,
+        ChromaSubsampling::Yuv420,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ChromaSubsampling::Yuv444 => "4:4:4",
+            ChromaSubsampling::Yuv422 => "4:2:2",
+            ChromaSubsampling::Yuv420 => "4:2:0",
+        }
+    }
+
+    /// Во сколько раз цветность записывается реже яркости по горизонтали и вертикали
+    fn sampling_factor(self) -> jpeg_encoder::SamplingFactor {
+        match self {
+            ChromaSubsampling::Yuv444 => jpeg_encoder::SamplingFactor::R_4_4_4,
+            ChromaSubsampling::Yuv422 => jpeg_encoder::SamplingFactor::R_4_2_2,
+            ChromaSubsampling::Yuv420 => jpeg_encoder::SamplingFactor::R_4_2_0,
+        }
+    }
+}
+
+/// Настройки экспорта
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExportSettings {
+    pub format: ExportFormat,
+    pub jpeg_quality: u8,
+    pub chroma: ChromaSubsampling,
+    pub png_compression: PngCompression,
+    pub avif_quality: u8,
+    /// Скорость кодирования AVIF: 1 - медленно и мельче, 10 - быстро
+    pub avif_speed: u8,
+    /// Записывать серую картинку одним каналом
+    pub grayscale: bool,
+    /// Глубина цвета; ограничивается исходником и форматом
+    pub depth: SampleDepth,
+    /// Сохранять прозрачность, если она есть и формат её поддерживает
+    pub keep_alpha: bool,
+}
+
+impl Default for ExportSettings {
+    fn default() -> Self {
+        Self {
+            format: ExportFormat::default(),
+            jpeg_quality: 90,
+            chroma: ChromaSubsampling::default(),
+            png_compression: PngCompression::default(),
+            avif_quality: 80,
+            avif_speed: 6,
+            grayscale: true,
+            depth: SampleDepth::F32,
+            keep_alpha: true,
+        }
+    }
+}
+
+/// Метаданные, встраиваемые в файл
+pub struct FileMetadata {
+    pub icc_profile: Option<Vec<u8>>,
+    pub exif: Option<Vec<u8>>,
+}
+
+/// Закодированный файл
+pub struct Encoded {
+    pub bytes: Vec<u8>,
+    /// Какие метаданные кодировщик не смог встроить и почему
+    pub warnings: Vec<String>,
+}
+
+/// Что известно о выводимой картинке для выбора настроек
+#[derive(Debug, Clone, Copy)]
+pub struct SourceInfo {
+    pub depth: SampleDepth,
+    pub has_alpha: bool,
+    /// Все пиксели серые
+    pub is_gray: bool,
+}
+
+impl ExportSettings {
+    /// Глубина цвета, которая будет записана
+    fn effective_depth(&self, source: SampleDepth) -> SampleDepth {
+        self.depth.min(source).min(self.format.max_depth())
+    }
+
+    /// Привести картинку к каналам и глубине, которые будут записаны
+    fn prepare(&self, image: &DynamicImage, source: &SourceInfo) -> DynamicImage {
+        let format = self.format;
+        let alpha = source.has_alpha && self.keep_alpha && format.supports_alpha();
+        let gray = source.is_gray && self.grayscale && format.supports_gray();
+        match (self.effective_depth(source.depth), gray, alpha) {
+            (SampleDepth::U8, false, false) => image.to_rgb8().into(),
+            (SampleDepth::U8, false, true) => image.to_rgba8().into(),
+            (SampleDepth::U8, true, false) => image.to_luma8().into(),
+            (SampleDepth::U8, true, true) => image.to_luma_alpha8().into(),
+            (SampleDepth::U16, false, false) => image.to_rgb16().into(),
+            (SampleDepth::U16, false, true) => image.to_rgba16().into(),
+            (SampleDepth::U16, true, false) => image.to_luma16().into(),
+            (SampleDepth::U16, true, true) => image.to_luma_alpha16().into(),
+            // TIFF не хранит серый с плавающей точкой
+            (SampleDepth::F32, _, false) => image.to_rgb32f().into(),
+            (SampleDepth::F32, _, true) => image.to_rgba32f().into(),
+        }
+    }
+
+    /// Закодировать картинку в файл выбранного формата в памяти
+    pub fn encode(
+        &self,
+        image: &DynamicImage,
+        source: &SourceInfo,
+        metadata: &FileMetadata,
+    ) -> Result<Encoded, AppError> {
+        let mut warnings = Vec::new();
+        let bytes = self
+            .encode_image(image, source, metadata, &mut warnings)
+            .map_err(|e| AppError::encode(self.format.name(), e))?;
+        Ok(Encoded { bytes, warnings })
+    }
+
+    fn encode_image(
+        &self,
+        image: &DynamicImage,
+        source: &SourceInfo,
+        metadata: &FileMetadata,
+        warnings: &mut Vec<String>,
+    ) -> ImageResult<Vec<u8>> {
+        let image = self.prepare(image, source);
+
+        let mut out = Cursor::new(Vec::new());
+        match self.format {
+            ExportFormat::Png => {
+                let compression = self.png_compression.compression_type();
+                let encoder =
+                    PngEncoder::new_with_quality(&mut out, compression, FilterType::Adaptive);
+                encode_with_metadata(&image, encoder, metadata, warnings)?
+            }
+            ExportFormat::Jpeg => self.encode_jpeg(&image, &mut out, metadata, warnings)?,
+            ExportFormat::WebP => {
+                let encoder = WebPEncoder::new_lossless(&mut out);
+                encode_with_metadata(&image, encoder, metadata, warnings)?
+            }
+            ExportFormat::Tiff => image.write_with_encoder(TiffEncoder::new(&mut out))?,
+            ExportFormat::Tga => image.write_with_encoder(TgaEncoder::new(&mut out))?,
+            ExportFormat::Qoi => image.write_with_encoder(QoiEncoder::new(&mut out))?,
+            ExportFormat::Avif => {
+                let encoder = AvifEncoder::new_with_speed_quality(
+                    &mut out,
+                    self.avif_speed,
+                    self.avif_quality,
+                );
+                image.write_with_encoder(encoder)?
+            }
+            ExportFormat::Bmp => image.write_with_encoder(BmpEncoder::new(&mut out))?,
+        }
+        Ok(out.into_inner())
+    }
+
+    /// Записать JPEG с выбранной субдискретизацией цветности.
+    /// Кодировщик image всегда пишет 4:4:4, поэтому используется jpeg-encoder.
+    fn encode_jpeg(
+        &self,
+        image: &DynamicImage,
+        out: &mut Cursor<Vec<u8>>,
+        metadata: &FileMetadata,
+        warnings: &mut Vec<String>,
+    ) -> ImageResult<()> {
+        let to_image_error = |e: jpeg_encoder::EncodingError| {
+            ImageError::Encoding(EncodingError::new(ImageFormat::Jpeg.into(), e))
+        };
+        let (width, height) = (u16::try_from(image.width()), u16::try_from(image.height()));
+        let (Ok(width), Ok(height)) = (width, height) else {
+            return Err(ImageError::Limits(LimitError::from_kind(
+                LimitErrorKind::DimensionError,
+            )));
+        };
+
+        let mut encoder = jpeg_encoder::Encoder::new(out, self.jpeg_quality);
+        encoder.set_sampling_factor(self.chroma.sampling_factor());
+        encoder.set_chroma_subsampling_method(jpeg_encoder::ChromaSubsamplingMethod::Average);
+        // без профиля файл всё равно читается как sRGB
+        if let Some(icc_profile) = &metadata.icc_profile
+            && let Err(e) = encoder.add_icc_profile(icc_profile)
+        {
+            warnings.push(format!("ICC профиль не записан: {e}"));
+        }
+        if let Some(exif) = &metadata.exif
+            && let Err(e) = encoder.add_exif_metadata(exif)
+        {
+            warnings.push(format!("EXIF не записан: {e}"));
+        }
+        // prepare оставляет для JPEG только 8-битные серый и RGB
+        let (data, color) = match image {
+            DynamicImage::ImageLuma8(gray) => (gray.as_raw(), jpeg_encoder::ColorType::Luma),
+            DynamicImage::ImageRgb8(rgb) => (rgb.as_raw(), jpeg_encoder::ColorType::Rgb),
+            _ => {
+                return Err(ImageError::Unsupported(
+                    UnsupportedError::from_format_and_kind(
+                        ImageFormat::Jpeg.into(),
+                        UnsupportedErrorKind::Color(image.color().into()),
+                    ),
+                ));
+            }
+        };
+        encoder
+            .encode(data, width, height, color)
+            .map_err(to_image_error)
+    }
+}
+
+/// Закодировать картинку, встроив метаданные, которые кодировщик умеет хранить.
+/// Невстроенные метаданные не мешают записи, о них пишется в warnings.
+fn encode_with_metadata(
+    image: &DynamicImage,
+    mut encoder: impl ImageEncoder,
+    metadata: &FileMetadata,
+    warnings: &mut Vec<String>,
+) -> ImageResult<()> {
+    // без профиля файл всё равно читается как sRGB
+    if let Some(icc_profile) = &metadata.icc_profile
+        && let Err(e) = encoder.set_icc_profile(icc_profile.clone())
+    {
+        warnings.push(format!("ICC профиль не записан: {e}"));
+    }
+    if let Some(exif) = &metadata.exif
+        && let Err(e) = encoder.set_exif_metadata(exif.clone())
+    {
+        warnings.push(format!("EXIF не записан: {e}"));
+    }
+    image.write_with_encoder(encoder)
+}
+
+/// Что нужно сделать по кнопкам диалога
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportAction {
+    None,
+    /// Посчитать размер файла для текущих настроек
+    Estimate,
+    /// Выбрать файл и записать
+    Export,
+}
+
+/// Окно экспорта: формат, его настройки и оценка размера файла
+#[derive(Default)]
+pub struct ExportDialog {
+    pub open: bool,
+    pub settings: ExportSettings,
+    /// Сведения о выводимой картинке; None - картинка сменилась
+    pub source: Option<SourceInfo>,
+    /// Размер файла и настройки, для которых он посчитан
+    estimate: Option<(Result<usize, AppError>, ExportSettings)>,
+    /// Подсчёт размера в фоне и настройки, для которых он запущен
+    estimate_job: Jobs<Result<usize, AppError>>,
+    estimating: Option<ExportSettings>,
+    /// Последние изменённые настройки и время изменения, чтобы не кодировать на каждый шаг слайдера
+    changed: Option<(ExportSettings, f64)>,
+}
+
+impl ExportDialog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Выводимая картинка сменилась: сведения и оценку нужно посчитать заново
+    pub fn invalidate(&mut self) {
+        self.source = None;
+        self.estimate = None;
+        self.estimate_job.cancel();
+        self.estimating = None;
+    }
+
+    /// Посчитать размер файла для текущих настроек в фоне
+    pub fn start_estimate(
+        &mut self,
+        ctx: &Context,
+        image: DynamicImage,
+        source: SourceInfo,
+        metadata: FileMetadata,
+    ) {
+        let settings = self.settings.clone();
+        self.estimating = Some(settings.clone());
+        self.estimate_job
+            .spawn(ctx, "Estimating size", move |progress| {
+                let size = settings
+                    .encode(&image, &source, &metadata)
+                    .map(|encoded| encoded.bytes.len());
+                progress.set(1.0);
+                Some(size)
+            });
+    }
+
+    /// Пора ли считать размер: настройки не менялись ESTIMATE_DELAY секунд и слайдер отпущен
+    fn estimate_due(&mut self, ui: &Ui) -> bool {
+        let now = ui.input(|i| i.time);
+        match &self.changed {
+            Some((settings, _)) if *settings == self.settings => {}
+            _ => self.changed = Some((self.settings.clone(), now)),
+        }
+        let waited = self.changed.as_ref().map_or(0.0, |(_, at)| now - at);
+        if ui.input(|i| i.pointer.any_down()) {
+            return false;
+        }
+        if waited < ESTIMATE_DELAY {
+            ui.ctx()
+                .request_repaint_after(std::time::Duration::from_secs_f64(ESTIMATE_DELAY - waited));
+            return false;
+        }
+        true
+    }
+
+    pub fn ui(&mut self, ui: &mut Ui) -> ExportAction {
+        let Some(source) = self.source else {
+            ui.label("Необходимо загрузить картинку.");
+            return ExportAction::None;
+        };
+        let s = &mut self.settings;
+
+        ComboBox::from_label("format")
+            .selected_text(s.format.name())
+            .show_ui(ui, |ui| {
+                for format in ExportFormat::ALL {
+                    ui.selectable_value(&mut s.format, format, format.name());
+                }
+            });
+
+        match s.format {
+            ExportFormat::Jpeg => {
+                ui.add(Slider::new(&mut s.jpeg_quality, 1..=100).text("quality"));
+                ui.horizontal(|ui| {
+                    ui.label("Chroma");
+                    for chroma in ChromaSubsampling::ALL {
+                        ui.radio_value(&mut s.chroma, chroma, chroma.name());
+                    }
+                })
+                .response
+                .on_hover_text("Во сколько раз цветность записывается реже яркости");
+            }
+            ExportFormat::Png => {
+                ui.horizontal(|ui| {
+                    ui.label("Compression");
+                    for compression in PngCompression::ALL {
+                        ui.radio_value(&mut s.png_compression, compression, compression.name());
+                    }
+                });
+            }
+            ExportFormat::Avif => {
+                ui.add(Slider::new(&mut s.avif_quality, 1..=100).text("quality"));
+                ui.add(Slider::new(&mut s.avif_speed, 1..=10).text("speed"));
+            }
+            _ => {}
+        }
+
+        let max_depth = source.depth.min(s.format.max_depth());
+        ui.horizontal(|ui| {
+            ui.label("Depth");
+            for depth in [SampleDepth::U8, SampleDepth::U16, SampleDepth::F32] {
+                if depth <= max_depth {
+                    ui.radio_value(&mut s.depth, depth, depth.name());
+                }
+            }
+        });
+        if s.depth > max_depth {
+            s.depth = max_depth;
+        }
+        if source.is_gray && s.format.supports_gray() {
+            ui.horizontal(|ui| {
+                ui.label("Channels");
+                ui.radio_value(&mut s.grayscale, true, "Grayscale");
+                ui.radio_value(&mut s.grayscale, false, "RGB");
+            });
+        }
+        if source.has_alpha {
+            ui.add_enabled_ui(s.format.supports_alpha(), |ui| {
+                ui.checkbox(&mut s.keep_alpha, "Keep transparency");
+            });
+        }
+        if !s.format.supports_metadata() {
+            ui.label("ICC профиль и EXIF в этот формат не записываются");
+        }
+        ui.separator();
+
+        if let Some(size) = self.estimate_job.poll()
+            && let Some(settings) = self.estimating.take()
+        {
+            self.estimate = Some((size, settings));
+        }
+
+        let mut action = ExportAction::None;
+        match &self.estimate {
+            Some((size, settings)) if *settings == self.settings => match size {
+                Ok(size) => {
+                    ui.label(format!("Size: {}", format_size(*size)));
+                }
+                Err(e) => {
+                    ui.colored_label(ui.visuals().error_fg_color, e.to_string());
+                }
+            },
+            _ if self.estimate_job.is_running()
+                && self.estimating.as_ref() == Some(&self.settings) =>
+            {
+                ui.horizontal(|ui| self.estimate_job.ui(ui));
+            }
+            // AVIF кодируется долго, его размер считается по кнопке,
+            // как и после отмены подсчёта
+            _ if self.settings.format == ExportFormat::Avif
+                || self.estimating.as_ref() == Some(&self.settings) =>
+            {
+                if ui.button("Estimate size").clicked() {
+                    action = ExportAction::Estimate;
+                }
+            }
+            _ => {
+                if self.estimate_due(ui) {
+                    action = ExportAction::Estimate;
+                }
+            }
+        }
+        if ui.button("Export…").clicked() {
+            action = ExportAction::Export;
+        }
+        action
+    }
+}
+
+/// Размер файла в байтах, КБ или МБ
+fn format_size(bytes: usize) -> String {
+    let bytes = bytes as f64;
+    if bytes < 1024.0 {
+        format!("{bytes} B")
+    } else if bytes < 1024.0 * 1024.0 {
+        format!("{:.1} KB", bytes / 1024.0)
+    } else {
+        format!("{:.2} MB", bytes / (1024.0 * 1024.0))
+    }
+}
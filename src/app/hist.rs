@@ -1,13 +1,40 @@
 use egui::*;
-use egui_plot::{Bar, BarChart, Plot};
+use egui_plot::{Bar, BarChart, Line, LineStyle, Plot, PlotImage, PlotPoint, Text, VLine};
+
+use super::color_space::RgbSpace;
+use super::error::AppError;
+use super::image_op;
+
+/// Допустимое число ячеек двумерной гистограммы по оси
+const JOINT_BINS: std::ops::RangeInclusive<usize> = 16..=256;
 
 pub struct RGBHistogram {
     data: Vec<u8>, // RGB buffer
     red_bins: Vec<u32>,
     green_bins: Vec<u32>,
     blue_bins: Vec<u32>,
+    /// Альфа-канал картинки, пустой - картинка непрозрачна
+    alpha: Vec<u8>,
+    alpha_bins: Vec<u32>,
+    /// Исходные значения 16 бит или с плавающей точкой, пустой - только 8 бит
+    precise: Vec<f32>,
+    /// Верхняя граница значений по оси: 1 для обычных картинок, больше для HDR
+    range: f32,
+    /// Рисовать гистограмму альфа-канала
+    pub show_alpha: bool,
     max_count: u32,
     needs_update: bool,
+    /// Значения пикселей, отмеченные на гистограмме
+    highlighted: Vec<[u8; 3]>,
+    /// Двумерная гистограмма: оси, число ячеек по оси и посчитанные ячейки
+    pub joint_axes: JointAxes,
+    pub joint_bins: usize,
+    /// Рабочее пространство для осей a*b*
+    pub space: RgbSpace,
+    joint_counts: Vec<u32>,
+    joint_texture: Option<TextureHandle>,
+    /// Для каких осей, числа ячеек и пространства посчитана двумерная гистограмма
+    joint_for: Option<(JointAxes, usize, RgbSpace)>,
 }
 
 impl Default for RGBHistogram {
@@ -17,8 +44,20 @@ impl Default for RGBHistogram {
             red_bins: vec![0; 256],
             green_bins: vec![0; 256],
             blue_bins: vec![0; 256],
+            alpha: Vec::new(),
+            alpha_bins: vec![0; 256],
+            precise: Vec::new(),
+            range: 1.0,
+            show_alpha: true,
             max_count: 0,
             needs_update: true,
+            highlighted: Vec::new(),
+            joint_axes: JointAxes::default(),
+            joint_bins: 64,
+            space: RgbSpace::default(),
+            joint_counts: Vec::new(),
+            joint_texture: None,
+            joint_for: None,
         }
     }
 }
@@ -28,23 +67,67 @@ impl RGBHistogram {
         Self::default()
     }
 
-    /// Обновить гистограмму под новую картинку
-    pub fn update_data(&mut self, data: Vec<u8>) {
-        if data.len() % 3 != 0 {
-            panic!("Буфер картинки не кратен 3");
+    /// Обновить гистограмму под новую картинку.
+    /// Буфер не из целых RGB пикселей не принимается, гистограмма остаётся прежней.
+    pub fn update_data(&mut self, data: Vec<u8>) -> Result<(), AppError> {
+        if !data.len().is_multiple_of(3) {
+            return Err(AppError::BufferSize);
         }
         self.data = data;
         self.needs_update = true;
+        self.joint_for = None;
+        Ok(())
+    }
+
+    /// Значения картинки с большей точностью, по ним строятся столбцы
+    pub fn set_precise(&mut self, precise: Option<Vec<f32>>) {
+        self.precise = precise.unwrap_or_default();
+        self.needs_update = true;
+    }
+
+    /// Альфа-канал картинки для гистограммы прозрачности
+    pub fn set_alpha(&mut self, alpha: Option<Vec<u8>>) {
+        self.alpha = alpha.unwrap_or_default();
+        self.needs_update = true;
+    }
+
+    /// Настройки вывода для сохранения между запусками
+    pub fn options(&self) -> HistogramOptions {
+        HistogramOptions {
+            show_alpha: self.show_alpha,
+            joint_axes: self.joint_axes,
+            joint_bins: self.joint_bins,
+        }
+    }
+
+    pub fn set_options(&mut self, options: HistogramOptions) {
+        self.show_alpha = options.show_alpha;
+        self.joint_axes = options.joint_axes;
+        // число ячеек приходит из хранилища, за пределами слайдера его быть не может
+        self.joint_bins = options
+            .joint_bins
+            .clamp(*JOINT_BINS.start(), *JOINT_BINS.end());
+    }
+
+    /// Отметить на гистограмме столбцы, в которые попадают эти пиксели
+    pub fn set_highlighted(&mut self, values: Vec<[u8; 3]>) {
+        self.highlighted = values;
     }
 
     /// Очистить гистограмму
     pub fn clear(&mut self) {
         self.data.clear();
+        self.precise.clear();
+        self.alpha.clear();
+        self.alpha_bins.iter_mut().for_each(|x| *x = 0);
         self.red_bins.iter_mut().for_each(|x| *x = 0);
         self.green_bins.iter_mut().for_each(|x| *x = 0);
         self.blue_bins.iter_mut().for_each(|x| *x = 0);
         self.max_count = 0;
         self.needs_update = false;
+        self.joint_counts.clear();
+        self.joint_texture = None;
+        self.joint_for = None;
     }
 
     /// Просмотреть буфер картинки для построения гистограммы
@@ -55,15 +138,39 @@ impl RGBHistogram {
         self.blue_bins.iter_mut().for_each(|x| *x = 0);
         self.max_count = 0;
 
-        // обработать RGX буфер
-        for chunk in self.data.chunks_exact(3) {
-            let r = chunk[0] as usize;
-            let g = chunk[1] as usize;
-            let b = chunk[2] as usize;
+        if self.precise.is_empty() {
+            self.range = 1.0;
+
+            // обработать RGX буфер
+            for chunk in self.data.chunks_exact(3) {
+                let r = chunk[0] as usize;
+                let g = chunk[1] as usize;
+                let b = chunk[2] as usize;
 
-            self.red_bins[r] += 1;
-            self.green_bins[g] += 1;
-            self.blue_bins[b] += 1;
+                self.red_bins[r] += 1;
+                self.green_bins[g] += 1;
+                self.blue_bins[b] += 1;
+            }
+        } else {
+            // 256 столбцов на весь диапазон значений, для HDR он шире [0, 1]
+            self.range = self
+                .precise
+                .iter()
+                .copied()
+                .filter(|v| v.is_finite())
+                .fold(1.0, f32::max);
+            let scale = 255.0 / self.range;
+            let bin = |v: f32| (v * scale).round().clamp(0.0, 255.0) as usize;
+            for chunk in self.precise.chunks_exact(3) {
+                self.red_bins[bin(chunk[0])] += 1;
+                self.green_bins[bin(chunk[1])] += 1;
+                self.blue_bins[bin(chunk[2])] += 1;
+            }
+        }
+
+        self.alpha_bins.iter_mut().for_each(|x| *x = 0);
+        for &a in &self.alpha {
+            self.alpha_bins[a as usize] += 1;
         }
 
         // найти значение с наибольшим количеством
@@ -99,7 +206,19 @@ impl RGBHistogram {
             .width(size.x);
 
         plot.show(ui, |plot_ui| {
+            if self.range > 1.0 {
+                plot_ui.text(
+                    Text::new(
+                        "range",
+                        PlotPoint::new(255.5, 0.0),
+                        format!("0 – {:.2}", self.range),
+                    )
+                    .anchor(Align2::RIGHT_BOTTOM),
+                );
+            }
             self.draw_separate_bars(plot_ui);
+            self.draw_alpha(plot_ui);
+            self.draw_highlighted(plot_ui);
         })
         .response
     }
@@ -132,6 +251,44 @@ impl RGBHistogram {
         }
     }
 
+    /// Нарисовать гистограмму альфа-канала линией поверх столбцов
+    fn draw_alpha(&self, plot_ui: &mut egui_plot::PlotUi) {
+        if !self.show_alpha || self.alpha.is_empty() {
+            return;
+        }
+        let points: Vec<[f64; 2]> = self
+            .alpha_bins
+            .iter()
+            .enumerate()
+            .map(|(value, &count)| [value as f64 + 0.5, count as f64])
+            .collect();
+        plot_ui.line(
+            Line::new("Alpha", points)
+                .color(Color32::from_gray(90))
+                .width(1.5),
+        );
+    }
+
+    /// Нарисовать отметки выбранных пикселей поверх столбцов их каналов
+    fn draw_highlighted(&self, plot_ui: &mut egui_plot::PlotUi) {
+        for [r, g, b] in &self.highlighted {
+            for (value, offset, color) in [
+                (*r, 0.0, Color32::RED),
+                (*g, 1.0 / 3.0, Color32::GREEN),
+                (*b, 2.0 / 3.0, Color32::BLUE),
+            ] {
+                let name = format!("{}: {}", channel_name(color), value);
+                let x = value as f64 / self.range as f64;
+                plot_ui.vline(
+                    VLine::new(name, x + offset)
+                        .color(color)
+                        .width(1.5)
+                        .style(LineStyle::dashed_dense()),
+                );
+            }
+        }
+    }
+
     /// Обработать гистограмму для отдельного канала
     fn create_channel_bars(
         &self,
@@ -149,11 +306,110 @@ impl RGBHistogram {
                     .width(bar_width)
                     .fill(color)
                     .stroke(Stroke::new(0.5, color))
-                    .name(format!("{}: {}", channel_name(color), intensity_value))
+                    .name(format!(
+                        "{}: {}",
+                        channel_name(color),
+                        self.bin_label(intensity_value)
+                    ))
             })
             .collect()
     }
 
+    /// Посчитать двумерную гистограмму для выбранных осей
+    fn calculate_joint(&mut self, ctx: &Context) {
+        let bins = self.joint_bins;
+        let (x_range, y_range) = self.joint_axes.ranges();
+        self.joint_counts = vec![0; bins * bins];
+
+        let to_bin = |value: f32, (min, max): (f32, f32)| {
+            let t = (value - min) / (max - min);
+            ((t * bins as f32) as usize).min(bins - 1)
+        };
+        for chunk in self.data.chunks_exact(3) {
+            let (x, y) = self
+                .joint_axes
+                .values(chunk[0], chunk[1], chunk[2], self.space);
+            // первая строка - верх графика
+            let row = bins - 1 - to_bin(y, y_range);
+            self.joint_counts[row * bins + to_bin(x, x_range)] += 1;
+        }
+
+        let max = self.joint_counts.iter().copied().max().unwrap_or(0).max(1) as f32;
+        let pixels = self
+            .joint_counts
+            .iter()
+            .map(|&count| {
+                if count == 0 {
+                    Color32::TRANSPARENT
+                } else {
+                    heat_color((1.0 + count as f32).ln() / (1.0 + max).ln())
+                }
+            })
+            .collect();
+        self.joint_texture = Some(ctx.load_texture(
+            "joint_histogram",
+            ColorImage::new([bins, bins], pixels),
+            TextureOptions::NEAREST,
+        ));
+        self.joint_for = Some((self.joint_axes, bins, self.space));
+    }
+
+    /// Нарисовать двумерную гистограмму в виде тепловой карты
+    pub fn show_joint(&mut self, ui: &mut Ui) -> Response {
+        ui.horizontal(|ui| {
+            ComboBox::from_id_salt("joint_axes")
+                .selected_text(self.joint_axes.name())
+                .show_ui(ui, |ui| {
+                    for axes in JointAxes::ALL {
+                        ui.selectable_value(&mut self.joint_axes, axes, axes.name());
+                    }
+                });
+            ui.add(Slider::new(&mut self.joint_bins, JOINT_BINS).text("bins"));
+        });
+
+        if self.joint_for != Some((self.joint_axes, self.joint_bins, self.space)) {
+            self.calculate_joint(ui.ctx());
+        }
+
+        let (x_range, y_range) = self.joint_axes.ranges();
+        let (x_name, y_name) = self.joint_axes.axis_names();
+        let plot = Plot::new("joint_histogram")
+            .include_x(x_range.0)
+            .include_x(x_range.1)
+            .include_y(y_range.0)
+            .include_y(y_range.1)
+            .x_axis_label(x_name)
+            .y_axis_label(y_name)
+            .show_grid([false, false]);
+
+        plot.show(ui, |plot_ui| {
+            if let Some(texture) = &self.joint_texture {
+                plot_ui.image(
+                    PlotImage::new(
+                        "joint",
+                        texture.id(),
+                        PlotPoint::new(
+                            (x_range.0 + x_range.1) / 2.0,
+                            (y_range.0 + y_range.1) / 2.0,
+                        ),
+                        vec2(x_range.1 - x_range.0, y_range.1 - y_range.0),
+                    )
+                    .bg_fill(Color32::BLACK),
+                );
+            }
+        })
+        .response
+    }
+
+    /// Значение, соответствующее столбцу
+    fn bin_label(&self, bin: usize) -> String {
+        if self.precise.is_empty() {
+            bin.to_string()
+        } else {
+            format!("{:.4}", bin as f32 / 255.0 * self.range)
+        }
+    }
+
     /// Данные о гистограмме
     pub fn statistics(&self) -> HistogramStats {
         HistogramStats {
@@ -207,3 +463,102 @@ fn channel_name(color: Color32) -> &'static str {
         "Unknown"
     }
 }
+
+/// Настройки вывода гистограмм
+#[derive(Debug, Clone, Copy, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct HistogramOptions {
+    pub show_alpha: bool,
+    pub joint_axes: JointAxes,
+    pub joint_bins: usize,
+}
+
+impl Default for HistogramOptions {
+    fn default() -> Self {
+        RGBHistogram::default().options()
+    }
+}
+
+/// Оси двумерной гистограммы
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum JointAxes {
+    #[default]
+    HueSaturation,
+    RedGreen,
+    GreenBlue,
+    BlueRed,
+    /// a* и b* пространства Lab
+    AB,
+}
+
+impl JointAxes {
+    pub const ALL: [JointAxes; 5] = [
+        JointAxes::HueSaturation,
+        JointAxes::RedGreen,
+        JointAxes::GreenBlue,
+        JointAxes::BlueRed,
+        JointAxes::AB,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            JointAxes::HueSaturation => "Hue – Saturation",
+            JointAxes::RedGreen => "Red – Green",
+            JointAxes::GreenBlue => "Green – Blue",
+            JointAxes::BlueRed => "Blue – Red",
+            JointAxes::AB => "a* – b*",
+        }
+    }
+
+    fn axis_names(self) -> (&'static str, &'static str) {
+        match self {
+            JointAxes::HueSaturation => ("Hue", "Saturation"),
+            JointAxes::RedGreen => ("Red", "Green"),
+            JointAxes::GreenBlue => ("Green", "Blue"),
+            JointAxes::BlueRed => ("Blue", "Red"),
+            JointAxes::AB => ("a*", "b*"),
+        }
+    }
+
+    /// Пределы значений по осям x и y
+    fn ranges(self) -> ((f32, f32), (f32, f32)) {
+        match self {
+            JointAxes::HueSaturation => ((0.0, 360.0), (0.0, 100.0)),
+            JointAxes::AB => ((-128.0, 128.0), (-128.0, 128.0)),
+            _ => ((0.0, 256.0), (0.0, 256.0)),
+        }
+    }
+
+    /// Значения пикселя по осям x и y
+    fn values(self, r: u8, g: u8, b: u8, space: RgbSpace) -> (f32, f32) {
+        match self {
+            JointAxes::HueSaturation => {
+                let (h, s, _) = image_op::rgb_to_hsv(r, g, b);
+                (h as f32, s as f32)
+            }
+            JointAxes::RedGreen => (r as f32, g as f32),
+            JointAxes::GreenBlue => (g as f32, b as f32),
+            JointAxes::BlueRed => (b as f32, r as f32),
+            JointAxes::AB => {
+                let (_, a, b) = image_op::rgb_to_lab(r, g, b, space);
+                (a, b)
+            }
+        }
+    }
+}
+
+/// Цвет тепловой карты для плотности t [0, 1]: синий, пурпурный, оранжевый, жёлтый
+fn heat_color(t: f32) -> Color32 {
+    const STOPS: [[f32; 3]; 5] = [
+        [20.0, 10.0, 80.0],
+        [120.0, 30.0, 160.0],
+        [220.0, 60.0, 90.0],
+        [250.0, 160.0, 30.0],
+        [255.0, 250.0, 180.0],
+    ];
+    let pos = t.clamp(0.0, 1.0) * (STOPS.len() - 1) as f32;
+    let i = (pos as usize).min(STOPS.len() - 2);
+    let f = pos - i as f32;
+    let [r, g, b] = [0, 1, 2].map(|c| (STOPS[i][c] + (STOPS[i + 1][c] - STOPS[i][c]) * f) as u8);
+    Color32::from_rgb(r, g, b)
+}
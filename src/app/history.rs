@@ -0,0 +1,173 @@
+use eframe::egui;
+
+use super::color_space::RgbSpace;
+
+/// Сколько результатов хранить, не считая оригинала
+const MAX_ENTRIES: usize = 16;
+
+/// Результат одной операции над картинкой
+pub struct HistoryEntry {
+    /// Номер записи, не меняется, когда старые записи вытесняются
+    pub id: u64,
+    pub name: String,
+    /// RGB буфер
+    pub image: Vec<u8>,
+    /// Альфа-канал, None - картинка непрозрачна
+    pub alpha: Option<Vec<u8>>,
+    /// Размер (ширина, высота)
+    pub size: (usize, usize),
+    /// Пространство, в котором записаны пиксели
+    pub space: RgbSpace,
+    texture: Option<egui::TextureHandle>,
+}
+
+/// История результатов операций, первая запись - загруженная картинка
+#[derive(Default)]
+pub struct History {
+    entries: Vec<HistoryEntry>,
+    /// Растёт при каждом изменении истории
+    revision: u64,
+}
+
+impl History {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Начать историю заново с оригинала
+    pub fn reset(
+        &mut self,
+        image: Vec<u8>,
+        alpha: Option<Vec<u8>>,
+        size: (usize, usize),
+        space: RgbSpace,
+    ) {
+        self.entries.clear();
+        self.push("Original", image, alpha, size, space);
+    }
+
+    /// Добавить результат операции.
+    /// Повтор той же операции (например, движение слайдера) заменяет последнюю запись.
+    pub fn push(
+        &mut self,
+        name: &str,
+        image: Vec<u8>,
+        alpha: Option<Vec<u8>>,
+        size: (usize, usize),
+        space: RgbSpace,
+    ) {
+        self.revision += 1;
+        let mut entry = HistoryEntry {
+            id: self.revision,
+            name: name.to_string(),
+            image,
+            alpha,
+            size,
+            space,
+            texture: None,
+        };
+
+        let len = self.entries.len();
+        match self.entries.last_mut() {
+            // заменённая запись остаётся выбранной для сравнения
+            Some(last) if len > 1 && last.name == name => {
+                entry.id = last.id;
+                *last = entry;
+            }
+            _ => self.entries.push(entry),
+        }
+        if self.entries.len() > MAX_ENTRIES + 1 {
+            self.entries.remove(1);
+        }
+    }
+
+    /// Номер изменения, чтобы не сохранять неизменившуюся историю повторно
+    pub fn revision(&self) -> u64 {
+        self.revision
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Название записи для вывода в списке
+    pub fn label(&self, index: usize) -> String {
+        match self.entries.get(index) {
+            Some(entry) => format!("{}. {}", index, entry.name),
+            None => String::new(),
+        }
+    }
+
+    pub fn get(&self, index: usize) -> Option<&HistoryEntry> {
+        self.entries.get(index)
+    }
+
+    /// Индекс записи с номером id, None - запись вытеснена
+    pub fn index_of(&self, id: u64) -> Option<usize> {
+        self.entries.iter().position(|entry| entry.id == id)
+    }
+
+    /// Текстура записи, создаётся при первом обращении
+    pub fn texture(&mut self, ctx: &egui::Context, index: usize) -> Option<egui::TextureHandle> {
+        let entry = self.entries.get_mut(index)?;
+        let texture = entry.texture.get_or_insert_with(|| {
+            ctx.load_texture(
+                format!("history_{}", entry.id),
+                super::viewer::color_image(
+                    &entry.image,
+                    entry.alpha.as_deref(),
+                    entry.size,
+                    entry.space,
+                ),
+                super::viewer::TEXTURE_OPTIONS,
+            )
+        });
+        Some(texture.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Добавить в историю однопиксельный результат value
+    fn push(history: &mut History, name: &str, value: u8) {
+        history.push(name, vec![value; 3], None, (1, 1), RgbSpace::Srgb);
+    }
+
+    #[test]
+    fn ids_survive_eviction() {
+        let mut history = History::new();
+        history.reset(vec![0; 3], None, (1, 1), RgbSpace::Srgb);
+        push(&mut history, "op 1", 1);
+        push(&mut history, "op 2", 2);
+        let first = history.get(1).unwrap().id;
+        let second = history.get(2).unwrap().id;
+
+        for i in 3..=MAX_ENTRIES as u8 + 1 {
+            push(&mut history, &format!("op {i}"), i);
+        }
+        // запись op 1 вытеснена, op 2 сдвинулась, оригинал остался первым
+        assert_eq!(history.len(), MAX_ENTRIES + 1);
+        assert_eq!(history.index_of(first), None);
+        assert_eq!(history.index_of(second), Some(1));
+        assert_eq!(history.get(1).unwrap().image, vec![2; 3]);
+        assert_eq!(history.get(0).unwrap().name, "Original");
+    }
+
+    #[test]
+    fn replaced_entry_keeps_id() {
+        let mut history = History::new();
+        history.reset(vec![0; 3], None, (1, 1), RgbSpace::Srgb);
+        push(&mut history, "HSV", 1);
+        let id = history.get(1).unwrap().id;
+        push(&mut history, "HSV", 2);
+        assert_eq!(history.len(), 2);
+        assert_eq!(history.index_of(id), Some(1));
+        assert_eq!(history.get(1).unwrap().image, vec![2; 3]);
+    }
+}
@@ -0,0 +1,324 @@
+use egui::*;
+
+use super::color_space::{self, GamutMapping, RgbSpace};
+use super::image_op;
+use super::viewer;
+
+/// Сколько пикселей картинки брать для построения
+const MAX_SAMPLES: usize = 60_000;
+
+/// Разрешение текстур плотности
+const DENSITY_BINS: usize = 128;
+
+/// Разрешение фона круга оттенков
+const WHEEL_TEXTURE_SIZE: usize = 256;
+
+/// Цвета слоёв: пиксели до и после сдвига HSV
+const ORIGINAL_COLOR: Color32 = Color32::BLACK;
+const SHIFTED_COLOR: Color32 = Color32::WHITE;
+
+/// Распределение пикселей картинки в цилиндре HSV:
+/// круг оттенков (угол - hue, радиус - saturation) и квадрат S/V.
+/// Показывает пиксели до и после сдвига hue/saturation/value.
+#[derive(Default)]
+pub struct HsvWheel {
+    /// Подвыборка пикселей картинки, RGB буфер
+    samples: Vec<u8>,
+    /// Сдвиг HSV и приведение к охвату, для которых построены текстуры
+    built_for: Option<(i32, i16, i16, RgbSpace, GamutMapping)>,
+    /// Фон круга оттенков и пространство, из которого его цвета переведены в sRGB
+    wheel_background: Option<(RgbSpace, TextureHandle)>,
+    /// Фон квадрата S/V, оттенок и пространство, для которых он построен
+    sv_background: Option<(u16, RgbSpace, TextureHandle)>,
+    /// Плотность пикселей до и после сдвига
+    wheel_density: Vec<TextureHandle>,
+    sv_density: Vec<TextureHandle>,
+    /// Средние точки до и после сдвига: (hue, saturation, value)
+    means: Vec<(f32, f32, f32)>,
+}
+
+impl HsvWheel {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Взять пиксели новой картинки
+    pub fn set_image(&mut self, buf: &[u8]) {
+        let pixels = buf.len() / 3;
+        let step = pixels.div_ceil(MAX_SAMPLES).max(1);
+        self.samples = buf
+            .chunks_exact(3)
+            .step_by(step)
+            .flatten()
+            .copied()
+            .collect();
+        self.built_for = None;
+    }
+
+    /// Окно с кругом оттенков и квадратом S/V для текущего сдвига HSV.
+    /// Сдвинутые пиксели приводятся к охвату space так же, как в операции HSV.
+    pub fn ui(
+        &mut self,
+        ui: &mut Ui,
+        hue: i32,
+        saturation: i16,
+        value: i16,
+        space: RgbSpace,
+        mapping: GamutMapping,
+    ) {
+        if self.samples.is_empty() {
+            ui.label("Необходимо загрузить картинку.");
+            return;
+        }
+        let key = (hue, saturation, value, space, mapping);
+        if self.built_for != Some(key) {
+            self.build(ui.ctx(), key);
+        }
+
+        ui.horizontal(|ui| {
+            legend(ui, ORIGINAL_COLOR, "до сдвига");
+            legend(ui, SHIFTED_COLOR, "после сдвига");
+        });
+        ui.label(format!(
+            "hue {hue:+}°, saturation {saturation:+}, value {value:+}"
+        ));
+
+        let side = (ui.available_width() / 2.0 - 8.0).clamp(160.0, 320.0);
+        ui.horizontal(|ui| {
+            self.wheel_ui(ui, side, space);
+            self.sv_square_ui(ui, side, space);
+        });
+    }
+
+    /// Пересчитать плотности до и после сдвига
+    fn build(&mut self, ctx: &Context, key: (i32, i16, i16, RgbSpace, GamutMapping)) {
+        let (hue, saturation, value, space, mapping) = key;
+        let mut shifted: Vec<f32> = self.samples.iter().map(|&c| c as f32 / 255.0).collect();
+        image_op::add_hsv_unbounded(&mut shifted, hue, saturation, value);
+        color_space::map_gamut(&mut shifted, space, mapping);
+        let shifted = image_op::f32_buffer_to_u8(&shifted);
+
+        self.wheel_density.clear();
+        self.sv_density.clear();
+        self.means.clear();
+        for (name, buf, color) in [
+            ("original", &self.samples, ORIGINAL_COLOR),
+            ("shifted", &shifted, SHIFTED_COLOR),
+        ] {
+            let hsv: Vec<(u16, u8, u8)> = buf
+                .chunks_exact(3)
+                .map(|p| image_op::rgb_to_hsv(p[0], p[1], p[2]))
+                .collect();
+
+            let mut wheel_bins = vec![0u32; DENSITY_BINS * DENSITY_BINS];
+            let mut sv_bins = vec![0u32; DENSITY_BINS * DENSITY_BINS];
+            for &(h, s, v) in &hsv {
+                let p = wheel_point(h as f32, s as f32);
+                wheel_bins[bin_index((p.x + 1.0) / 2.0, (1.0 - p.y) / 2.0)] += 1;
+                sv_bins[bin_index(s as f32 / 100.0, 1.0 - v as f32 / 100.0)] += 1;
+            }
+
+            self.wheel_density.push(density_texture(
+                ctx,
+                &format!("hsv_wheel_{name}"),
+                &wheel_bins,
+                color,
+            ));
+            self.sv_density.push(density_texture(
+                ctx,
+                &format!("hsv_sv_{name}"),
+                &sv_bins,
+                color,
+            ));
+            self.means.push(mean_hsv(&hsv));
+        }
+        self.built_for = Some(key);
+    }
+
+    /// Круг оттенков: угол - hue, расстояние от центра - saturation.
+    /// Оттенки фона - цвета пространства space, на экран они выводятся в sRGB.
+    fn wheel_ui(&mut self, ui: &mut Ui, side: f32, space: RgbSpace) {
+        let (rect, _) = ui.allocate_exact_size(vec2(side, side), Sense::hover());
+        if self.wheel_background.as_ref().map(|(s, _)| *s) != Some(space) {
+            self.wheel_background = Some((space, wheel_texture(ui.ctx(), space)));
+        }
+
+        let painter = ui.painter_at(rect);
+        let uv = Rect::from_min_max(pos2(0.0, 0.0), pos2(1.0, 1.0));
+        if let Some((_, background)) = &self.wheel_background {
+            painter.image(background.id(), rect, uv, Color32::WHITE);
+        }
+        for texture in &self.wheel_density {
+            painter.image(texture.id(), rect, uv, Color32::WHITE);
+        }
+        painter.circle_stroke(
+            rect.center(),
+            side / 2.0,
+            ui.visuals().widgets.noninteractive.fg_stroke,
+        );
+
+        // смещение средней точки
+        let to_screen = |p: Vec2| rect.center() + vec2(p.x, -p.y) * side / 2.0;
+        if let [(h0, s0, _), (h1, s1, _)] = self.means[..] {
+            let from = to_screen(wheel_point(h0, s0));
+            let to = to_screen(wheel_point(h1, s1));
+            mean_arrow(&painter, from, to);
+        }
+    }
+
+    /// Квадрат S/V: по горизонтали saturation, по вертикали value
+    fn sv_square_ui(&mut self, ui: &mut Ui, side: f32, space: RgbSpace) {
+        let (rect, _) = ui.allocate_exact_size(vec2(side, side), Sense::hover());
+
+        // фон для среднего оттенка после сдвига
+        let slice_hue = self.means.last().map_or(0, |m| m.0.round() as u16 % 360);
+        let built = self.sv_background.as_ref().map(|(h, s, _)| (*h, *s));
+        if built != Some((slice_hue, space)) {
+            let texture = sv_texture(ui.ctx(), slice_hue, space);
+            self.sv_background = Some((slice_hue, space, texture));
+        }
+
+        let painter = ui.painter_at(rect);
+        let uv = Rect::from_min_max(pos2(0.0, 0.0), pos2(1.0, 1.0));
+        if let Some((_, _, background)) = &self.sv_background {
+            painter.image(background.id(), rect, uv, Color32::WHITE);
+        }
+        for texture in &self.sv_density {
+            painter.image(texture.id(), rect, uv, Color32::WHITE);
+        }
+        painter.rect_stroke(
+            rect,
+            0.0,
+            ui.visuals().widgets.noninteractive.fg_stroke,
+            StrokeKind::Inside,
+        );
+        painter.text(
+            rect.left_bottom() + vec2(4.0, -4.0),
+            Align2::LEFT_BOTTOM,
+            format!("S →, V ↑, hue {slice_hue}°"),
+            FontId::proportional(12.0),
+            Color32::WHITE,
+        );
+
+        let to_screen = |s: f32, v: f32| rect.min + vec2(s / 100.0, 1.0 - v / 100.0) * side;
+        if let [(_, s0, v0), (_, s1, v1)] = self.means[..] {
+            mean_arrow(&painter, to_screen(s0, v0), to_screen(s1, v1));
+        }
+    }
+}
+
+/// Точка круга оттенков в координатах [-1, 1], ось y вверх
+fn wheel_point(hue: f32, saturation: f32) -> Vec2 {
+    let angle = hue.to_radians();
+    vec2(angle.cos(), angle.sin()) * saturation / 100.0
+}
+
+/// Индекс ячейки плотности для точки (u, v) в [0, 1], ось v вниз
+fn bin_index(u: f32, v: f32) -> usize {
+    let col = ((u * DENSITY_BINS as f32) as usize).min(DENSITY_BINS - 1);
+    let row = ((v * DENSITY_BINS as f32) as usize).min(DENSITY_BINS - 1);
+    row * DENSITY_BINS + col
+}
+
+/// Текстура плотности: прозрачность растёт с логарифмом количества пикселей
+fn density_texture(ctx: &Context, name: &str, bins: &[u32], color: Color32) -> TextureHandle {
+    let max = bins.iter().copied().max().unwrap_or(0).max(1) as f32;
+    let pixels = bins
+        .iter()
+        .map(|&count| {
+            let alpha = if count == 0 {
+                0.0
+            } else {
+                0.35 + 0.65 * (1.0 + count as f32).ln() / (1.0 + max).ln()
+            };
+            Color32::from_rgba_unmultiplied(color.r(), color.g(), color.b(), (alpha * 255.0) as u8)
+        })
+        .collect();
+    let image = ColorImage::new([DENSITY_BINS, DENSITY_BINS], pixels);
+    ctx.load_texture(name, image, TextureOptions::NEAREST)
+}
+
+/// Фон круга оттенков при value = 100 для пространства space
+fn wheel_texture(ctx: &Context, space: RgbSpace) -> TextureHandle {
+    let n = WHEEL_TEXTURE_SIZE;
+    let mut rgb = Vec::with_capacity(n * n * 3);
+    let mut alpha = Vec::with_capacity(n * n);
+    for row in 0..n {
+        for col in 0..n {
+            let x = (col as f32 + 0.5) / n as f32 * 2.0 - 1.0;
+            let y = 1.0 - (row as f32 + 0.5) / n as f32 * 2.0;
+            let radius = x.hypot(y);
+            let hue = y.atan2(x).to_degrees().rem_euclid(360.0);
+            let saturation = (radius.min(1.0) * 100.0) as u8;
+            let (r, g, b) = image_op::hsv_to_rgb(hue.round() as u16 % 360, saturation, 100);
+            rgb.extend([r, g, b]);
+            alpha.push(if radius > 1.0 { 0 } else { 255 });
+        }
+    }
+    ctx.load_texture(
+        "hsv_wheel",
+        viewer::color_image(&rgb, Some(&alpha), (n, n), space),
+        TextureOptions::LINEAR,
+    )
+}
+
+/// Фон квадрата S/V для одного оттенка пространства space
+fn sv_texture(ctx: &Context, hue: u16, space: RgbSpace) -> TextureHandle {
+    let n = 101;
+    let mut rgb = Vec::with_capacity(n * n * 3);
+    for row in 0..n {
+        for col in 0..n {
+            let (r, g, b) = image_op::hsv_to_rgb(hue, col as u8, (100 - row) as u8);
+            rgb.extend([r, g, b]);
+        }
+    }
+    ctx.load_texture(
+        "hsv_sv",
+        viewer::color_image(&rgb, None, (n, n), space),
+        TextureOptions::LINEAR,
+    )
+}
+
+/// Средняя точка: оттенок усредняется по кругу с весом насыщенности
+fn mean_hsv(hsv: &[(u16, u8, u8)]) -> (f32, f32, f32) {
+    if hsv.is_empty() {
+        return (0.0, 0.0, 0.0);
+    }
+    let (mut x, mut y, mut s_sum, mut v_sum) = (0.0f32, 0.0f32, 0.0f32, 0.0f32);
+    for &(h, s, v) in hsv {
+        let p = wheel_point(h as f32, s as f32);
+        x += p.x;
+        y += p.y;
+        s_sum += s as f32;
+        v_sum += v as f32;
+    }
+    let n = hsv.len() as f32;
+    let hue = y.atan2(x).to_degrees().rem_euclid(360.0);
+    (hue, s_sum / n, v_sum / n)
+}
+
+/// Стрелка от средней точки до сдвига к средней точке после
+fn mean_arrow(painter: &Painter, from: Pos2, to: Pos2) {
+    let outline = Stroke::new(4.0, Color32::BLACK);
+    let stroke = Stroke::new(2.0, Color32::WHITE);
+    painter.circle_filled(from, 4.0, ORIGINAL_COLOR);
+    if from.distance(to) > 1.0 {
+        painter.arrow(from, to - from, outline);
+        painter.arrow(from, to - from, stroke);
+    }
+    painter.circle_stroke(to, 4.0, outline);
+    painter.circle_filled(to, 3.0, SHIFTED_COLOR);
+}
+
+/// Подпись цвета слоя
+fn legend(ui: &mut Ui, color: Color32, text: &str) {
+    let (rect, _) = ui.allocate_exact_size(vec2(12.0, 12.0), Sense::hover());
+    ui.painter().rect_filled(rect, 2.0, color);
+    ui.painter().rect_stroke(
+        rect,
+        2.0,
+        ui.visuals().widgets.noninteractive.fg_stroke,
+        StrokeKind::Inside,
+    );
+    ui.label(text);
+}
@@ -0,0 +1,319 @@
+use egui::*;
+use moxcms::{
+    ColorProfile, DataColorSpace, Layout, ProfileText, RenderingIntent, TransformOptions,
+};
+
+use super::color_space::RgbSpace;
+use super::jobs::{self, Progress};
+
+/// ICC профиль рабочего пространства
+fn space_profile(space: RgbSpace) -> ColorProfile {
+    match space {
+        RgbSpace::Srgb => ColorProfile::new_srgb(),
+        RgbSpace::DisplayP3 => ColorProfile::new_display_p3(),
+        RgbSpace::AdobeRgb => ColorProfile::new_adobe_rgb(),
+        RgbSpace::Rec2020 => ColorProfile::new_bt2020(),
+        RgbSpace::ProPhoto => ColorProfile::new_pro_photo_rgb(),
+    }
+}
+
+/// Каким профилем интерпретировать пиксели загруженной картинки
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ProfileSource {
+    /// Встроенный в файл профиль; без него картинка считается sRGB
+    #[default]
+    Embedded,
+    /// Назначенный профиль, встроенный игнорируется
+    Assigned(RgbSpace),
+}
+
+/// Способ перевода цветов, не попадающих в рабочее пространство
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Intent {
+    #[default]
+    Perceptual,
+    RelativeColorimetric,
+}
+
+impl Intent {
+    pub fn name(self) -> &'static str {
+        match self {
+            Intent::Perceptual => "Perceptual",
+            Intent::RelativeColorimetric => "Relative colorimetric",
+        }
+    }
+
+    fn rendering_intent(self) -> RenderingIntent {
+        match self {
+            Intent::Perceptual => RenderingIntent::Perceptual,
+            Intent::RelativeColorimetric => RenderingIntent::RelativeColorimetric,
+        }
+    }
+}
+
+/// Профиль, встроенный в загруженный файл
+pub struct EmbeddedProfile {
+    /// Название профиля из его описания
+    pub description: String,
+    /// Разобранный профиль; None, если разобрать не удалось
+    profile: Option<ColorProfile>,
+    /// Профиль переводит цвета в sRGB без изменений
+    pub is_srgb: bool,
+}
+
+impl EmbeddedProfile {
+    fn new(data: &[u8]) -> Self {
+        match ColorProfile::new_from_slice(data) {
+            Ok(profile) => Self {
+                description: profile
+                    .description
+                    .as_ref()
+                    .map(profile_text)
+                    .filter(|s| !s.is_empty())
+                    .unwrap_or_else(|| "Без названия".to_string()),
+                is_srgb: is_srgb(&profile),
+                profile: Some(profile),
+            },
+            Err(e) => Self {
+                description: format!("Не удалось разобрать профиль: {e}"),
+                profile: None,
+                is_srgb: false,
+            },
+        }
+    }
+}
+
+/// Управление цветом загруженной картинки: встроенный ICC профиль
+/// и перевод пикселей в рабочее пространство, в котором работают все операции.
+pub struct ColorManagement {
+    pub embedded: Option<EmbeddedProfile>,
+    pub source: ProfileSource,
+    pub intent: Intent,
+    /// Встраивать профиль пространства картинки в сохраняемые файлы
+    pub embed_on_save: bool,
+}
+
+impl Default for ColorManagement {
+    fn default() -> Self {
+        Self {
+            embedded: None,
+            source: ProfileSource::Embedded,
+            intent: Intent::default(),
+            embed_on_save: true,
+        }
+    }
+}
+
+impl ColorManagement {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Запомнить профиль нового файла; назначенный профиль сбрасывается
+    pub fn set_embedded(&mut self, data: Option<&[u8]>) {
+        self.embedded = data.map(EmbeddedProfile::new);
+        self.source = ProfileSource::Embedded;
+    }
+
+    /// Профиль, из которого нужно переводить пиксели в target;
+    /// None - пиксели уже в target
+    fn source_profile(&self, target: RgbSpace) -> Result<Option<ColorProfile>, String> {
+        let space = match (self.source, &self.embedded) {
+            (ProfileSource::Assigned(space), _) => space,
+            // без профиля картинка считается sRGB
+            (ProfileSource::Embedded, None) => RgbSpace::Srgb,
+            (ProfileSource::Embedded, Some(embedded)) if embedded.is_srgb => RgbSpace::Srgb,
+            (ProfileSource::Embedded, Some(embedded)) => {
+                return match &embedded.profile {
+                    Some(profile) if profile.color_space == DataColorSpace::Rgb => {
+                        Ok(Some(profile.clone()))
+                    }
+                    Some(_) => Err("Профиль не RGB, пиксели не переведены".to_string()),
+                    None => Err("Профиль не разобран, пиксели не переведены".to_string()),
+                };
+            }
+        };
+        Ok((space != target).then(|| space_profile(space)))
+    }
+
+    /// Перевод пикселей в рабочее пространство target; Ok(None) - пиксели уже в target
+    pub fn conversion(&self, target: RgbSpace) -> Result<Option<Conversion>, String> {
+        let Some(source) = self.source_profile(target)? else {
+            return Ok(None);
+        };
+        Ok(Some(Conversion {
+            source,
+            destination: space_profile(target),
+            options: TransformOptions {
+                rendering_intent: self.intent.rendering_intent(),
+                ..TransformOptions::default()
+            },
+        }))
+    }
+
+    /// Краткое описание профиля для панели инструментов
+    pub fn label(&self) -> Option<String> {
+        match (self.source, &self.embedded) {
+            (ProfileSource::Assigned(space), _) => Some(format!("{} (assigned)", space.name())),
+            (ProfileSource::Embedded, Some(embedded)) if !embedded.is_srgb => {
+                Some(embedded.description.clone())
+            }
+            _ => None,
+        }
+    }
+
+    /// Меню профиля; true, если пиксели нужно перевести заново
+    pub fn menu_ui(&mut self, ui: &mut Ui) -> bool {
+        let before = (self.source, self.intent);
+
+        match &self.embedded {
+            Some(embedded) => {
+                ui.label(format!("Embedded: {}", embedded.description));
+                if embedded.is_srgb {
+                    ui.label("Совпадает с sRGB");
+                }
+            }
+            None => {
+                ui.label("Профиль не встроен, картинка считается sRGB");
+            }
+        }
+        if let ProfileSource::Embedded = self.source
+            && let Err(e) = self.source_profile(RgbSpace::Srgb)
+        {
+            ui.colored_label(ui.visuals().warn_fg_color, e);
+        }
+        ui.separator();
+
+        ui.add_enabled_ui(self.embedded.is_some(), |ui| {
+            ui.radio_value(
+                &mut self.source,
+                ProfileSource::Embedded,
+                "Convert from embedded profile",
+            );
+        });
+        ui.label("Assign profile:");
+        for space in RgbSpace::ALL {
+            ui.radio_value(
+                &mut self.source,
+                ProfileSource::Assigned(space),
+                space.name(),
+            );
+        }
+        ui.separator();
+        for intent in [Intent::Perceptual, Intent::RelativeColorimetric] {
+            ui.radio_value(&mut self.intent, intent, intent.name());
+        }
+        ui.separator();
+        ui.checkbox(&mut self.embed_on_save, "Embed profile on save");
+
+        (self.source, self.intent) != before
+    }
+}
+
+/// Перевод пикселей из профиля картинки в рабочее пространство.
+/// Преобразования создаются там, где выполняется перевод, например в фоновом потоке.
+pub struct Conversion {
+    source: ColorProfile,
+    destination: ColorProfile,
+    options: TransformOptions,
+}
+
+impl Conversion {
+    /// Перевести 8-битный RGB буфер и точный буфер кусками, отмечая прогресс.
+    /// Если преобразование не создаётся, буферы не меняются. Ok(false) - перевод отменили.
+    pub fn apply(
+        &self,
+        buf: &mut [u8],
+        precise: Option<&mut [f32]>,
+        progress: &Progress,
+    ) -> Result<bool, String> {
+        let (source, destination) = (&self.source, &self.destination);
+        // оба преобразования создаются до изменения буферов
+        let transform = source
+            .create_transform_8bit(Layout::Rgb, destination, Layout::Rgb, self.options)
+            .map_err(|e| e.to_string())?;
+        let precise_transform = match &precise {
+            Some(_) => Some(
+                source
+                    .create_transform_f32(Layout::Rgb, destination, Layout::Rgb, self.options)
+                    .map_err(|e| e.to_string())?,
+            ),
+            None => None,
+        };
+
+        let mut error = None;
+        let span = if precise.is_some() {
+            (0.0, 0.5)
+        } else {
+            (0.0, 1.0)
+        };
+        let mut done = jobs::for_chunks(buf, 3, progress, span, |chunk| {
+            let src = chunk.to_vec();
+            if let Err(e) = transform.transform(&src, chunk) {
+                error = Some(e.to_string());
+            }
+        });
+        if let (Some(precise), Some(transform)) = (precise, precise_transform) {
+            done = done
+                && jobs::for_chunks(precise, 3, progress, (0.5, 1.0), |chunk| {
+                    let src = chunk.to_vec();
+                    if let Err(e) = transform.transform(&src, chunk) {
+                        error = Some(e.to_string());
+                    }
+                });
+        }
+        match error {
+            Some(e) => Err(e),
+            None => Ok(done),
+        }
+    }
+}
+
+/// ICC профиль пространства space для встраивания в сохраняемые файлы
+pub fn space_icc(space: RgbSpace) -> Option<Vec<u8>> {
+    space_profile(space).encode().ok()
+}
+
+/// Текст описания профиля, английский вариант если есть
+fn profile_text(text: &ProfileText) -> String {
+    match text {
+        ProfileText::PlainString(s) => s.clone(),
+        ProfileText::Localizable(strings) => strings
+            .iter()
+            .find(|s| s.language == "en")
+            .or(strings.first())
+            .map(|s| s.value.clone())
+            .unwrap_or_default(),
+        ProfileText::Description(description) => description.ascii_string.clone(),
+    }
+    .trim_matches(char::from(0))
+    .trim()
+    .to_string()
+}
+
+/// Переводит ли профиль цвета в sRGB без изменений: проверка на сетке цветов
+fn is_srgb(profile: &ColorProfile) -> bool {
+    if profile.color_space != DataColorSpace::Rgb {
+        return false;
+    }
+    let Ok(transform) = profile.create_transform_8bit(
+        Layout::Rgb,
+        &ColorProfile::new_srgb(),
+        Layout::Rgb,
+        TransformOptions::default(),
+    ) else {
+        return false;
+    };
+    let levels = [0u8, 51, 102, 153, 204, 255];
+    let mut grid = Vec::with_capacity(levels.len().pow(3) * 3);
+    for r in levels {
+        for g in levels {
+            for b in levels {
+                grid.extend([r, g, b]);
+            }
+        }
+    }
+    let mut out = vec![0; grid.len()];
+    transform.transform(&grid, &mut out).is_ok()
+        && grid.iter().zip(&out).all(|(&a, &b)| a.abs_diff(b) <= 2)
+}
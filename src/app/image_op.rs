@@ -1,10 +1,13 @@
+use super::color_space::RgbSpace;
+use super::error::AppError;
+
 /// Первый вариант преобразования rbg в оттенки серого.
 /// r - красная составляющая;
 /// g - зелёная составляющая;
 /// b - синяя составляющая;
 ///
 /// Результат тоже в rgb формате, но должнен уже представлять собой оттенок серого.
-fn rgb_to_grayscale1(r: u8, g: u8, b: u8) -> (u8, u8, u8) {
+pub fn rgb_to_grayscale1(r: u8, g: u8, b: u8) -> (u8, u8, u8) {
     let r_f32 = r as f32;
     let g_f32 = g as f32;
     let b_f32 = b as f32;
@@ -19,7 +22,7 @@ fn rgb_to_grayscale1(r: u8, g: u8, b: u8) -> (u8, u8, u8) {
 /// b - синяя составляющая;
 ///
 /// Результат тоже в rgb формате, но должнен уже представлять собой оттенок серого.
-fn rgb_to_grayscale2(r: u8, g: u8, b: u8) -> (u8, u8, u8) {
+pub fn rgb_to_grayscale2(r: u8, g: u8, b: u8) -> (u8, u8, u8) {
     let r_f32 = r as f32;
     let g_f32 = g as f32;
     let b_f32 = b as f32;
@@ -32,7 +35,7 @@ fn rgb_to_grayscale2(r: u8, g: u8, b: u8) -> (u8, u8, u8) {
 /// buf - тупо вектор с цифрами от 0 до 255, соответственно, каждая тройка чисел это один пиксель.
 ///
 /// Результат должен быть записан в сам же buf.
-pub fn rgb_buffer_to_grayscale1(buf: &mut Vec<u8>) {
+pub fn rgb_buffer_to_grayscale1(buf: &mut [u8]) {
     for i in (0..buf.len()).step_by(3) {
         if i + 2 < buf.len() {
             let (r, g, b) = (buf[i], buf[i + 1], buf[i + 2]);
@@ -48,7 +51,7 @@ pub fn rgb_buffer_to_grayscale1(buf: &mut Vec<u8>) {
 /// buf - тупо вектор с цифрами от 0 до 255, соответственно, каждая тройка чисел это один пиксель.
 ///
 /// Результат должен быть записан в сам же buf.
-pub fn rgb_buffer_to_grayscale2(buf: &mut Vec<u8>) {
+pub fn rgb_buffer_to_grayscale2(buf: &mut [u8]) {
     for i in (0..buf.len()).step_by(3) {
         if i + 2 < buf.len() {
             let (r, g, b) = (buf[i], buf[i + 1], buf[i + 2]);
@@ -60,35 +63,218 @@ pub fn rgb_buffer_to_grayscale2(buf: &mut Vec<u8>) {
     }
 }
 
-/// Функция для вычисления разности двух изображений
-pub fn compute_difference(buf1: &[u8], buf2: &[u8]) -> Vec<u8> {
+/// Проверить, что буферы двух картинок одного размера
+pub fn check_same_size(buf1: &[u8], buf2: &[u8]) -> Result<(), AppError> {
     if buf1.len() != buf2.len() {
-        panic!("Картинки разных размеров");
+        return Err(AppError::SizeMismatch {
+            first: buf1.len() / 3,
+            second: buf2.len() / 3,
+        });
     }
+    Ok(())
+}
+
+/// Функция для вычисления разности двух изображений
+pub fn compute_difference(buf1: &[u8], buf2: &[u8]) -> Result<Vec<u8>, AppError> {
+    check_same_size(buf1, buf2)?;
 
     let mut res = Vec::with_capacity(buf1.len());
     for i in 0..buf1.len() {
-        let diff = (buf1[i] as i16 - buf2[i] as i16).abs() as u8;
+        let diff = buf1[i].abs_diff(buf2[i]);
         res.push(diff);
     }
-    res
+    Ok(res)
 }
 
 /// Функция для вычисления разности двух изображений
-pub fn compute_difference_neg(buf1: &[u8], buf2: &[u8]) -> Vec<u8> {
-    if buf1.len() != buf2.len() {
-        panic!("Картинки разных размеров");
-    }
+pub fn compute_difference_neg(buf1: &[u8], buf2: &[u8]) -> Result<Vec<u8>, AppError> {
+    check_same_size(buf1, buf2)?;
 
     let mut res = Vec::with_capacity(buf1.len());
     for i in 0..buf1.len() {
-        let diff = 255 - (buf1[i] as i16 - buf2[i] as i16).abs() as u8;
+        let diff = 255 - buf1[i].abs_diff(buf2[i]);
         res.push(diff);
     }
+    Ok(res)
+}
+
+/// Способ визуализации разности двух картинок.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DiffView {
+    /// Модуль разности
+    #[default]
+    Abs,
+    /// Негатив модуля разности
+    Neg,
+    /// Модуль разности, умноженный на усиление
+    Amplified,
+    /// Разность со знаком: красный там, где первая картинка ярче, синий - где темнее
+    Signed,
+}
+
+/// Разность двух изображений в выбранном виде.
+/// gain - усиление для Amplified и Signed, для остальных не используется.
+pub fn compute_difference_view(
+    buf1: &[u8],
+    buf2: &[u8],
+    view: DiffView,
+    gain: f32,
+) -> Result<Vec<u8>, AppError> {
+    match view {
+        DiffView::Abs => compute_difference(buf1, buf2),
+        DiffView::Neg => compute_difference_neg(buf1, buf2),
+        DiffView::Amplified => compute_difference_amplified(buf1, buf2, gain),
+        DiffView::Signed => compute_difference_signed(buf1, buf2, gain),
+    }
+}
+
+/// Модуль разности двух изображений, умноженный на gain с насыщением в 255
+pub fn compute_difference_amplified(
+    buf1: &[u8],
+    buf2: &[u8],
+    gain: f32,
+) -> Result<Vec<u8>, AppError> {
+    check_same_size(buf1, buf2)?;
+
+    let res = buf1
+        .iter()
+        .zip(buf2)
+        .map(|(&a, &b)| (a.abs_diff(b) as f32 * gain).round().min(255.0) as u8)
+        .collect();
+    Ok(res)
+}
+
+/// Разность со знаком, усреднённая по каналам пикселя.
+/// Положительная разность выводится красным, отрицательная - синим, нулевая - чёрным.
+pub fn compute_difference_signed(buf1: &[u8], buf2: &[u8], gain: f32) -> Result<Vec<u8>, AppError> {
+    check_same_size(buf1, buf2)?;
+
+    let mut res = Vec::with_capacity(buf1.len());
+    for (p1, p2) in buf1.chunks_exact(3).zip(buf2.chunks_exact(3)) {
+        let diff: i32 = (0..3).map(|c| p1[c] as i32 - p2[c] as i32).sum();
+        let value = (diff.unsigned_abs() as f32 / 3.0 * gain).round().min(255.0) as u8;
+        if diff > 0 {
+            res.extend_from_slice(&[value, 0, 0]);
+        } else {
+            res.extend_from_slice(&[0, 0, value]);
+        }
+    }
+    Ok(res)
+}
+
+/// Усиление, растягивающее наибольшую выводимую в виде view разность до 255:
+/// по каналам для Amplified, среднюю по каналам пикселя для Signed.
+/// Для одинаковых картинок усиление 1.
+pub fn stretch_gain(buf1: &[u8], buf2: &[u8], view: DiffView) -> Result<f32, AppError> {
+    check_same_size(buf1, buf2)?;
+
+    let max = match view {
+        DiffView::Signed => buf1
+            .chunks_exact(3)
+            .zip(buf2.chunks_exact(3))
+            .map(|(p1, p2)| {
+                let diff: i32 = (0..3).map(|c| p1[c] as i32 - p2[c] as i32).sum();
+                diff.unsigned_abs() as f32 / 3.0
+            })
+            .fold(0.0, f32::max),
+        _ => buf1
+            .iter()
+            .zip(buf2)
+            .map(|(&a, &b)| a.abs_diff(b))
+            .max()
+            .unwrap_or(0) as f32,
+    };
+    if max == 0.0 { Ok(1.0) } else { Ok(255.0 / max) }
+}
+
+/// Как привести вторую картинку к первой перед сравнением.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SizeMatch {
+    /// Взять общую часть от левого верхнего угла
+    #[default]
+    Crop,
+    /// Растянуть вторую картинку до размера первой
+    Resize,
+    /// Сдвинуть вторую картинку на (dx, dy) и взять пересечение
+    Offset(i32, i32),
+}
+
+/// Два выровненных буфера одинакового размера и их размер (ширина, высота).
+pub type AlignedPair = (Vec<u8>, Vec<u8>, (usize, usize));
+
+/// Выровнять две RGB картинки друг относительно друга.
+pub fn align_images(
+    buf1: &[u8],
+    size1: (usize, usize),
+    buf2: &[u8],
+    size2: (usize, usize),
+    mode: SizeMatch,
+) -> Result<AlignedPair, AppError> {
+    if buf1.len() != size1.0 * size1.1 * 3 || buf2.len() != size2.0 * size2.1 * 3 {
+        return Err(AppError::BufferSize);
+    }
+
+    match mode {
+        SizeMatch::Crop => align_images(buf1, size1, buf2, size2, SizeMatch::Offset(0, 0)),
+        SizeMatch::Resize => {
+            let resized = resize_rgb_buffer(buf2, size2, size1)?;
+            Ok((buf1.to_vec(), resized, size1))
+        }
+        SizeMatch::Offset(dx, dy) => {
+            // пересечение в координатах первой картинки
+            let left = dx.max(0) as i64;
+            let top = dy.max(0) as i64;
+            let right = (size1.0 as i64).min(dx as i64 + size2.0 as i64);
+            let bottom = (size1.1 as i64).min(dy as i64 + size2.1 as i64);
+            if right <= left || bottom <= top {
+                return Err(AppError::NoOverlap { dx, dy });
+            }
+
+            let (width, height) = ((right - left) as usize, (bottom - top) as usize);
+            let (x1, y1) = (left as usize, top as usize);
+            let (x2, y2) = ((left - dx as i64) as usize, (top - dy as i64) as usize);
+            Ok((
+                crop_rgb_buffer(buf1, size1.0, (x1, y1), (width, height)),
+                crop_rgb_buffer(buf2, size2.0, (x2, y2), (width, height)),
+                (width, height),
+            ))
+        }
+    }
+}
+
+/// Вырезать прямоугольник size с левым верхним углом origin из RGB буфера шириной width
+fn crop_rgb_buffer(
+    buf: &[u8],
+    width: usize,
+    origin: (usize, usize),
+    size: (usize, usize),
+) -> Vec<u8> {
+    let mut res = Vec::with_capacity(size.0 * size.1 * 3);
+    for row in origin.1..origin.1 + size.1 {
+        let start = (row * width + origin.0) * 3;
+        res.extend_from_slice(&buf[start..start + size.0 * 3]);
+    }
     res
 }
 
-pub fn rgb_buffer_to_red_channel(buf: &mut Vec<u8>) {
+/// Билинейно изменить размер RGB буфера
+fn resize_rgb_buffer(
+    buf: &[u8],
+    size: (usize, usize),
+    new_size: (usize, usize),
+) -> Result<Vec<u8>, AppError> {
+    let img = image::RgbImage::from_raw(size.0 as u32, size.1 as u32, buf.to_vec())
+        .ok_or(AppError::BufferSize)?;
+    let resized = image::imageops::resize(
+        &img,
+        new_size.0 as u32,
+        new_size.1 as u32,
+        image::imageops::FilterType::Triangle,
+    );
+    Ok(resized.into_raw())
+}
+
+pub fn rgb_buffer_to_red_channel(buf: &mut [u8]) {
     for i in (0..buf.len()).step_by(3) {
         if i + 2 < buf.len() {
             buf[i + 1] = 0;
@@ -97,7 +283,7 @@ pub fn rgb_buffer_to_red_channel(buf: &mut Vec<u8>) {
     }
 }
 
-pub fn rgb_buffer_to_green_channel(buf: &mut Vec<u8>) {
+pub fn rgb_buffer_to_green_channel(buf: &mut [u8]) {
     for i in (0..buf.len()).step_by(3) {
         if i + 2 < buf.len() {
             buf[i] = 0;
@@ -106,7 +292,7 @@ pub fn rgb_buffer_to_green_channel(buf: &mut Vec<u8>) {
     }
 }
 
-pub fn rgb_buffer_to_blue_channel(buf: &mut Vec<u8>) {
+pub fn rgb_buffer_to_blue_channel(buf: &mut [u8]) {
     for i in (0..buf.len()).step_by(3) {
         if i + 2 < buf.len() {
             buf[i] = 0;
@@ -115,6 +301,44 @@ pub fn rgb_buffer_to_blue_channel(buf: &mut Vec<u8>) {
     }
 }
 
+/// Разделить RGBA буфер на RGB буфер и плоскость альфа-канала
+pub fn split_alpha(rgba: &[u8]) -> (Vec<u8>, Vec<u8>) {
+    let pixels = rgba.len() / 4;
+    let mut rgb = Vec::with_capacity(pixels * 3);
+    let mut alpha = Vec::with_capacity(pixels);
+    for p in rgba.chunks_exact(4) {
+        rgb.extend_from_slice(&p[..3]);
+        alpha.push(p[3]);
+    }
+    (rgb, alpha)
+}
+
+/// Собрать RGBA буфер из RGB буфера и плоскости альфа-канала
+pub fn merge_alpha(rgb: &[u8], alpha: &[u8]) -> Result<Vec<u8>, AppError> {
+    if rgb.len() != alpha.len() * 3 {
+        return Err(AppError::AlphaSize {
+            alpha: alpha.len(),
+            pixels: rgb.len() / 3,
+        });
+    }
+    Ok(rgb
+        .chunks_exact(3)
+        .zip(alpha)
+        .flat_map(|(p, &a)| [p[0], p[1], p[2], a])
+        .collect())
+}
+
+/// Наложить картинку с альфа-каналом на сплошной фон background.
+/// После этого картинка непрозрачна.
+pub fn flatten_alpha(rgb: &mut [u8], alpha: &[u8], background: [u8; 3]) {
+    for (p, &a) in rgb.chunks_exact_mut(3).zip(alpha) {
+        let a = a as u32;
+        for c in 0..3 {
+            p[c] = ((p[c] as u32 * a + background[c] as u32 * (255 - a) + 127) / 255) as u8;
+        }
+    }
+}
+
 /// Преобразование представления цвета из RGB в HSV.
 /// r - красная составляющая [0, 255];
 /// g - зелёная составляющая [0, 255];
@@ -153,7 +377,7 @@ pub fn rgb_to_hsv(r: u8, g: u8, b: u8) -> (u16, u8, u8) {
     };
 
     // println!("rgb_to_hsv {} {} {}", h.round() as u16, s.round() as u8, v.round() as u8);
-    
+
     (h.round() as u16, s.round() as u8, v.round() as u8)
 }
 
@@ -199,7 +423,7 @@ pub fn hsv_to_rgb(h: u16, s: u8, v: u8) -> (u8, u8, u8) {
 /// v - value (brightness) [0, 100];
 ///
 /// Результат должен быть записан в сам же buf в rgb формате.
-pub fn add_hsv_to_buffer(buf: &mut Vec<u8>, h_add: i32, s_add: i16, v_add: i16) {
+pub fn add_hsv_to_buffer(buf: &mut [u8], h_add: i32, s_add: i16, v_add: i16) {
     for i in (0..buf.len()).step_by(3) {
         if i + 2 < buf.len() {
             let r = buf[i];
@@ -208,9 +432,9 @@ pub fn add_hsv_to_buffer(buf: &mut Vec<u8>, h_add: i32, s_add: i16, v_add: i16)
 
             let (mut h, mut s, mut v) = rgb_to_hsv(r, g, b);
 
-            h = ((h as i32 + h_add as i32) % 360).abs() as u16;
-            s = (s as i16 + s_add as i16).clamp(0, 100) as u8;
-            v = (v as i16 + v_add as i16).clamp(0, 100) as u8;
+            h = ((h as i32 + h_add) % 360).unsigned_abs() as u16;
+            s = (s as i16 + s_add).clamp(0, 100) as u8;
+            v = (v as i16 + v_add).clamp(0, 100) as u8;
 
             let (r_new, g_new, b_new) = hsv_to_rgb(h, s, v);
 
@@ -220,3 +444,776 @@ pub fn add_hsv_to_buffer(buf: &mut Vec<u8>, h_add: i32, s_add: i16, v_add: i16)
         }
     }
 }
+
+/// Глубина цвета исходной картинки, по возрастанию точности
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum SampleDepth {
+    #[default]
+    U8,
+    U16,
+    /// С плавающей точкой, значения могут быть больше 1 (HDR)
+    F32,
+}
+
+impl SampleDepth {
+    pub fn name(self) -> &'static str {
+        match self {
+            SampleDepth::U8 => "8 bit",
+            SampleDepth::U16 => "16 bit",
+            SampleDepth::F32 => "32 bit float",
+        }
+    }
+}
+
+/// Буфер с плавающей точкой в u8 для вывода: [0, 1] в [0, 255], остальное обрезается
+pub fn f32_buffer_to_u8(buf: &[f32]) -> Vec<u8> {
+    buf.iter().map(|&v| unit_to_u8(v)).collect()
+}
+
+/// Первый вариант оттенков серого для RGB буфера с плавающей точкой
+pub fn rgb_f32_buffer_to_grayscale1(buf: &mut [f32]) {
+    for p in buf.chunks_exact_mut(3) {
+        let y = 0.299 * p[0] + 0.587 * p[1] + 0.114 * p[2];
+        p.fill(y);
+    }
+}
+
+/// Второй вариант оттенков серого для RGB буфера с плавающей точкой
+pub fn rgb_f32_buffer_to_grayscale2(buf: &mut [f32]) {
+    for p in buf.chunks_exact_mut(3) {
+        let y = 0.2126 * p[0] + 0.7152 * p[1] + 0.0722 * p[2];
+        p.fill(y);
+    }
+}
+
+/// Оставить в RGB буфере с плавающей точкой только канал channel (0 - R, 1 - G, 2 - B)
+pub fn rgb_f32_buffer_to_channel(buf: &mut [f32], channel: usize) {
+    for p in buf.chunks_exact_mut(3) {
+        for (c, v) in p.iter_mut().enumerate() {
+            if c != channel {
+                *v = 0.0;
+            }
+        }
+    }
+}
+
+/// RGB с плавающей точкой в HSV: H [0, 360), S [0, 100], V [0, 100] для [0, 1], больше для HDR
+pub fn rgb_f32_to_hsv(r: f32, g: f32, b: f32) -> (f32, f32, f32) {
+    let max = r.max(g.max(b));
+    let min = r.min(g.min(b));
+    let delta = max - min;
+
+    let s = if max <= 0.0 { 0.0 } else { delta / max * 100.0 };
+    let h = if delta <= 0.0 {
+        0.0
+    } else if max == r {
+        60.0 * ((g - b) / delta)
+    } else if max == g {
+        60.0 * ((b - r) / delta) + 120.0
+    } else {
+        60.0 * ((r - g) / delta) + 240.0
+    };
+    (h.rem_euclid(360.0), s, max * 100.0)
+}
+
+/// HSV в RGB с плавающей точкой, обратное к rgb_f32_to_hsv
+pub fn hsv_to_rgb_f32(h: f32, s: f32, v: f32) -> (f32, f32, f32) {
+    let s = s / 100.0;
+    let v = v / 100.0;
+    let h = h.rem_euclid(360.0) / 60.0;
+    let f = h - h.floor();
+    let p = v * (1.0 - s);
+    let q = v * (1.0 - f * s);
+    let t = v * (1.0 - (1.0 - f) * s);
+    match h.floor() as u8 % 6 {
+        0 => (v, t, p),
+        1 => (q, v, p),
+        2 => (p, v, t),
+        3 => (p, q, v),
+        4 => (t, p, v),
+        _ => (v, p, q),
+    }
+}
+
+/// Прибавить HSV к буферу без обрезки сверху: при S больше 100 или V больше 100
+/// цвета выходят за охват, их нужно привести color_space::map_gamut
+pub fn add_hsv_unbounded(buf: &mut [f32], h_add: i32, s_add: i16, v_add: i16) {
+    for p in buf.chunks_exact_mut(3) {
+        let (h, s, v) = rgb_f32_to_hsv(p[0], p[1], p[2]);
+        let h = h + h_add as f32;
+        let s = (s + s_add as f32).max(0.0);
+        let v = (v + v_add as f32).max(0.0);
+        let (r, g, b) = hsv_to_rgb_f32(h, s, v);
+        p.copy_from_slice(&[r, g, b]);
+    }
+}
+
+/// Наложить RGB буфер с плавающей точкой на фон background, как flatten_alpha
+pub fn flatten_alpha_f32(rgb: &mut [f32], alpha: &[u8], background: [u8; 3]) {
+    for (p, &a) in rgb.chunks_exact_mut(3).zip(alpha) {
+        let a = a as f32 / 255.0;
+        for c in 0..3 {
+            p[c] = p[c] * a + background[c] as f32 / 255.0 * (1.0 - a);
+        }
+    }
+}
+
+/// Метрики качества одного изображения относительно другого.
+#[derive(Debug, Clone, Copy)]
+pub struct ImageMetrics {
+    /// Среднеквадратичная ошибка по всем каналам.
+    pub mse: f64,
+    /// Пиковое отношение сигнал/шум в дБ (бесконечность для одинаковых картинок).
+    pub psnr: f64,
+    /// Индекс структурного сходства по яркости [-1, 1].
+    pub ssim: f64,
+    /// Многомасштабный SSIM [0, 1].
+    pub ms_ssim: f64,
+}
+
+impl std::fmt::Display for ImageMetrics {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "MSE: {:.3}", self.mse)?;
+        if self.psnr.is_finite() {
+            writeln!(f, "PSNR: {:.2} dB", self.psnr)?;
+        } else {
+            writeln!(f, "PSNR: inf dB")?;
+        }
+        writeln!(f, "SSIM: {:.4}", self.ssim)?;
+        write!(f, "MS-SSIM: {:.4}", self.ms_ssim)
+    }
+}
+
+/// Константы стабилизации SSIM для диапазона [0, 255].
+const SSIM_C1: f32 = (0.01 * 255.0) * (0.01 * 255.0);
+const SSIM_C2: f32 = (0.03 * 255.0) * (0.03 * 255.0);
+
+/// Веса масштабов MS-SSIM (Wang, Simoncelli, Bovik, 2003).
+const MS_SSIM_WEIGHTS: [f64; 5] = [0.0448, 0.2856, 0.3001, 0.2363, 0.1333];
+
+/// Радиус гауссова окна SSIM (окно 11x11, sigma = 1.5).
+const SSIM_RADIUS: usize = 5;
+
+/// Среднеквадратичная ошибка между двумя буферами одинакового размера.
+pub fn compute_mse(buf1: &[u8], buf2: &[u8]) -> Result<f64, AppError> {
+    check_same_size(buf1, buf2)?;
+    if buf1.is_empty() {
+        return Ok(0.0);
+    }
+
+    let sum: u64 = buf1
+        .iter()
+        .zip(buf2)
+        .map(|(&a, &b)| {
+            let d = a.abs_diff(b) as u64;
+            d * d
+        })
+        .sum();
+    Ok(sum as f64 / buf1.len() as f64)
+}
+
+/// PSNR в децибелах для 8-битных буферов.
+/// Для одинаковых картинок возвращает бесконечность.
+pub fn compute_psnr(buf1: &[u8], buf2: &[u8]) -> Result<f64, AppError> {
+    Ok(psnr_from_mse(compute_mse(buf1, buf2)?))
+}
+
+/// PSNR в децибелах по среднеквадратичной ошибке 8-битных буферов
+fn psnr_from_mse(mse: f64) -> f64 {
+    if mse == 0.0 {
+        f64::INFINITY
+    } else {
+        10.0 * (255.0 * 255.0 / mse).log10()
+    }
+}
+
+/// SSIM двух RGB буферов размера width x height, считается по яркости.
+pub fn compute_ssim(
+    buf1: &[u8],
+    buf2: &[u8],
+    width: usize,
+    height: usize,
+) -> Result<f64, AppError> {
+    check_same_size(buf1, buf2)?;
+    if buf1.len() != width * height * 3 {
+        return Err(AppError::BufferSize);
+    }
+
+    let x = rgb_buffer_to_luma(buf1);
+    let y = rgb_buffer_to_luma(buf2);
+    Ok(ssim_components(&x, &y, width, height).0)
+}
+
+/// MS-SSIM двух RGB буферов размера width x height, считается по яркости.
+/// Если картинка слишком мала для 5 масштабов, используются только доступные.
+pub fn compute_ms_ssim(
+    buf1: &[u8],
+    buf2: &[u8],
+    width: usize,
+    height: usize,
+) -> Result<f64, AppError> {
+    check_same_size(buf1, buf2)?;
+    if buf1.len() != width * height * 3 {
+        return Err(AppError::BufferSize);
+    }
+
+    let x = rgb_buffer_to_luma(buf1);
+    let y = rgb_buffer_to_luma(buf2);
+    let (_, ms_ssim) = ssim_scales(x, y, width, height, |_| true).expect("счёт не прерывался");
+    Ok(ms_ssim)
+}
+
+/// SSIM и MS-SSIM яркостей x и y. SSIM - это полный индекс первого масштаба MS-SSIM.
+/// step(доля) вызывается после каждого масштаба; false прерывает счёт, тогда None.
+fn ssim_scales(
+    mut x: Vec<f32>,
+    mut y: Vec<f32>,
+    width: usize,
+    height: usize,
+    mut step: impl FnMut(f32) -> bool,
+) -> Option<(f64, f64)> {
+    let (mut w, mut h) = (width, height);
+
+    // количество масштабов, на которых окно ещё помещается в картинку
+    let mut scales = 1;
+    while scales < MS_SSIM_WEIGHTS.len() && (w >> scales).min(h >> scales) > 2 * SSIM_RADIUS {
+        scales += 1;
+    }
+    let weight_sum: f64 = MS_SSIM_WEIGHTS[..scales].iter().sum();
+
+    let mut first_ssim = 1.0;
+    let mut result = 1.0;
+    for (scale, &weight) in MS_SSIM_WEIGHTS[..scales].iter().enumerate() {
+        let (ssim, cs) = ssim_components(&x, &y, w, h);
+        if scale == 0 {
+            first_ssim = ssim;
+        }
+        let weight = weight / weight_sum;
+        if scale + 1 == scales {
+            result *= ssim.max(0.0).powf(weight);
+        } else {
+            result *= cs.max(0.0).powf(weight);
+            y = downsample2(&y, w, h).0;
+            (x, w, h) = downsample2(&x, w, h);
+        }
+        if !step((scale + 1) as f32 / scales as f32) {
+            return None;
+        }
+    }
+    Some((first_ssim, result))
+}
+
+/// Все метрики качества сразу.
+pub fn compute_metrics(
+    buf1: &[u8],
+    buf2: &[u8],
+    width: usize,
+    height: usize,
+) -> Result<ImageMetrics, AppError> {
+    let metrics = compute_metrics_with_progress(buf1, buf2, width, height, |_| true)?;
+    Ok(metrics.expect("счёт не прерывался"))
+}
+
+/// Все метрики качества с отметкой прогресса для фонового задания.
+/// step(доля) вызывается после каждого масштаба MS-SSIM; false прерывает счёт, тогда Ok(None).
+pub fn compute_metrics_with_progress(
+    buf1: &[u8],
+    buf2: &[u8],
+    width: usize,
+    height: usize,
+    step: impl FnMut(f32) -> bool,
+) -> Result<Option<ImageMetrics>, AppError> {
+    let mse = compute_mse(buf1, buf2)?;
+    if buf1.len() != width * height * 3 {
+        return Err(AppError::BufferSize);
+    }
+    let x = rgb_buffer_to_luma(buf1);
+    let y = rgb_buffer_to_luma(buf2);
+    let Some((ssim, ms_ssim)) = ssim_scales(x, y, width, height, step) else {
+        return Ok(None);
+    };
+    Ok(Some(ImageMetrics {
+        mse,
+        psnr: psnr_from_mse(mse),
+        ssim,
+        ms_ssim,
+    }))
+}
+
+/// Яркость каждого пикселя RGB буфера (без округления).
+fn rgb_buffer_to_luma(buf: &[u8]) -> Vec<f32> {
+    buf.chunks_exact(3)
+        .map(|p| 0.299 * p[0] as f32 + 0.587 * p[1] as f32 + 0.114 * p[2] as f32)
+        .collect()
+}
+
+/// Средние по картинке значения SSIM и его контрастно-структурной части.
+fn ssim_components(x: &[f32], y: &[f32], width: usize, height: usize) -> (f64, f64) {
+    if x.is_empty() {
+        return (1.0, 1.0);
+    }
+
+    let kernel = gaussian_kernel(SSIM_RADIUS, 1.5);
+    let xx: Vec<f32> = x.iter().map(|v| v * v).collect();
+    let yy: Vec<f32> = y.iter().map(|v| v * v).collect();
+    let xy: Vec<f32> = x.iter().zip(y).map(|(a, b)| a * b).collect();
+
+    let mu_x = gaussian_blur(x, width, height, &kernel);
+    let mu_y = gaussian_blur(y, width, height, &kernel);
+    let s_xx = gaussian_blur(&xx, width, height, &kernel);
+    let s_yy = gaussian_blur(&yy, width, height, &kernel);
+    let s_xy = gaussian_blur(&xy, width, height, &kernel);
+
+    let mut ssim_sum = 0.0;
+    let mut cs_sum = 0.0;
+    for i in 0..x.len() {
+        let (mx, my) = (mu_x[i], mu_y[i]);
+        let var_x = s_xx[i] - mx * mx;
+        let var_y = s_yy[i] - my * my;
+        let cov = s_xy[i] - mx * my;
+
+        let cs = (2.0 * cov + SSIM_C2) / (var_x + var_y + SSIM_C2);
+        let l = (2.0 * mx * my + SSIM_C1) / (mx * mx + my * my + SSIM_C1);
+        ssim_sum += (l * cs) as f64;
+        cs_sum += cs as f64;
+    }
+    let n = x.len() as f64;
+    (ssim_sum / n, cs_sum / n)
+}
+
+/// Нормированное одномерное гауссово ядро длины 2 * radius + 1.
+fn gaussian_kernel(radius: usize, sigma: f32) -> Vec<f32> {
+    let kernel: Vec<f32> = (0..=2 * radius)
+        .map(|i| {
+            let d = i as f32 - radius as f32;
+            (-d * d / (2.0 * sigma * sigma)).exp()
+        })
+        .collect();
+    let sum: f32 = kernel.iter().sum();
+    kernel.into_iter().map(|k| k / sum).collect()
+}
+
+/// Гауссово размытие плоскости width x height с радиусом 3 sigma
+pub fn blur_plane(plane: &[f32], width: usize, height: usize, sigma: f32) -> Vec<f32> {
+    let radius = (3.0 * sigma).ceil().max(1.0) as usize;
+    gaussian_blur(plane, width, height, &gaussian_kernel(radius, sigma))
+}
+
+/// Сепарабельное размытие плоскости, на границах повторяется крайний пиксель.
+fn gaussian_blur(plane: &[f32], width: usize, height: usize, kernel: &[f32]) -> Vec<f32> {
+    let radius = (kernel.len() / 2) as isize;
+    let clamp = |v: isize, max: usize| v.clamp(0, max as isize - 1) as usize;
+
+    let mut tmp = vec![0.0; plane.len()];
+    for row in 0..height {
+        for col in 0..width {
+            tmp[row * width + col] = kernel
+                .iter()
+                .enumerate()
+                .map(|(k, w)| {
+                    w * plane[row * width + clamp(col as isize + k as isize - radius, width)]
+                })
+                .sum();
+        }
+    }
+
+    let mut res = vec![0.0; plane.len()];
+    for row in 0..height {
+        for col in 0..width {
+            res[row * width + col] = kernel
+                .iter()
+                .enumerate()
+                .map(|(k, w)| {
+                    w * tmp[clamp(row as isize + k as isize - radius, height) * width + col]
+                })
+                .sum();
+        }
+    }
+    res
+}
+
+/// Уменьшение плоскости в 2 раза усреднением блоков 2x2.
+fn downsample2(plane: &[f32], width: usize, height: usize) -> (Vec<f32>, usize, usize) {
+    let (w, h) = ((width / 2).max(1), (height / 2).max(1));
+    let mut res = Vec::with_capacity(w * h);
+    for row in 0..h {
+        for col in 0..w {
+            let (r0, c0) = (row * 2, col * 2);
+            let (r1, c1) = ((r0 + 1).min(height - 1), (c0 + 1).min(width - 1));
+            let sum = plane[r0 * width + c0]
+                + plane[r0 * width + c1]
+                + plane[r1 * width + c0]
+                + plane[r1 * width + c1];
+            res.push(sum / 4.0);
+        }
+    }
+    (res, w, h)
+}
+
+/// Преобразование представления цвета из RGB в HSL.
+/// r, g, b - составляющие [0, 255];
+///
+/// На выходе HSL: H [0, 360), S [0, 100], L [0, 100].
+pub fn rgb_to_hsl(r: u8, g: u8, b: u8) -> (f32, f32, f32) {
+    let r_f = r as f32 / 255.0;
+    let g_f = g as f32 / 255.0;
+    let b_f = b as f32 / 255.0;
+
+    let max = r_f.max(g_f.max(b_f));
+    let min = r_f.min(g_f.min(b_f));
+    let delta = max - min;
+
+    let l = (max + min) / 2.0;
+    let s = if delta == 0.0 {
+        0.0
+    } else {
+        delta / (1.0 - (2.0 * l - 1.0).abs())
+    };
+
+    let h = if delta == 0.0 {
+        0.0
+    } else if max == r_f {
+        (60.0 * ((g_f - b_f) / delta)).rem_euclid(360.0)
+    } else if max == g_f {
+        60.0 * ((b_f - r_f) / delta) + 120.0
+    } else {
+        60.0 * ((r_f - g_f) / delta) + 240.0
+    };
+
+    (h, s * 100.0, l * 100.0)
+}
+
+/// Преобразование представления цвета из HSL в RGB.
+/// h - hue [0, 360];
+/// s - saturation [0, 100];
+/// l - lightness [0, 100];
+///
+/// На выходе RGB со значениями от 0 до 255.
+pub fn hsl_to_rgb(h: f32, s: f32, l: f32) -> (u8, u8, u8) {
+    let s = (s / 100.0).clamp(0.0, 1.0);
+    let l = (l / 100.0).clamp(0.0, 1.0);
+    let h = h.rem_euclid(360.0) / 60.0;
+
+    let c = (1.0 - (2.0 * l - 1.0).abs()) * s;
+    let x = c * (1.0 - (h % 2.0 - 1.0).abs());
+    let m = l - c / 2.0;
+
+    let (r, g, b) = match h as u8 {
+        0 => (c, x, 0.0),
+        1 => (x, c, 0.0),
+        2 => (0.0, c, x),
+        3 => (0.0, x, c),
+        4 => (x, 0.0, c),
+        _ => (c, 0.0, x),
+    };
+    (unit_to_u8(r + m), unit_to_u8(g + m), unit_to_u8(b + m))
+}
+
+/// Белая точка D65 в XYZ (Y = 100)
+const WHITE_D65: (f32, f32, f32) = (95.047, 100.0, 108.883);
+
+/// Гамма-кодированная составляющая sRGB [0, 1] в линейную
+pub fn srgb_to_linear(c: f32) -> f32 {
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// Линейная составляющая [0, 1] в гамма-кодированную sRGB
+pub fn linear_to_srgb(c: f32) -> f32 {
+    if c <= 0.0031308 {
+        c * 12.92
+    } else {
+        1.055 * c.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+/// Преобразование представления цвета из RGB пространства space в CIE XYZ (D65).
+/// r, g, b - составляющие [0, 255];
+///
+/// На выходе X, Y, Z, где Y [0, 100] - яркость.
+pub fn rgb_to_xyz(r: u8, g: u8, b: u8, space: RgbSpace) -> (f32, f32, f32) {
+    let linear = [r, g, b].map(|c| space.to_linear(c as f32 / 255.0));
+    let [x, y, z] = space.linear_to_xyz(linear);
+    (x * 100.0, y * 100.0, z * 100.0)
+}
+
+/// Координаты цветности CIE 1931 xy цвета из пространства space.
+/// У чёрного цветности нет, тогда None.
+pub fn rgb_to_xy(r: u8, g: u8, b: u8, space: RgbSpace) -> Option<(f32, f32)> {
+    let (x, y, z) = rgb_to_xyz(r, g, b, space);
+    let sum = x + y + z;
+    (sum > 0.0).then(|| (x / sum, y / sum))
+}
+
+/// Преобразование представления цвета из CIE XYZ (D65) в RGB пространство space.
+/// Цвета вне охвата пространства обрезаются.
+pub fn xyz_to_rgb(x: f32, y: f32, z: f32, space: RgbSpace) -> (u8, u8, u8) {
+    let [r, g, b] = space
+        .xyz_to_linear([x / 100.0, y / 100.0, z / 100.0])
+        .map(|c| unit_to_u8(space.from_linear(c.clamp(0.0, 1.0))));
+    (r, g, b)
+}
+
+/// Преобразование представления цвета из RGB пространства space в CIE L*a*b* (D65).
+/// r, g, b - составляющие [0, 255];
+///
+/// На выходе L [0, 100], a и b примерно [-128, 127] для sRGB.
+pub fn rgb_to_lab(r: u8, g: u8, b: u8, space: RgbSpace) -> (f32, f32, f32) {
+    let (x, y, z) = rgb_to_xyz(r, g, b, space);
+    let f = |t: f32| {
+        if t > 216.0 / 24389.0 {
+            t.cbrt()
+        } else {
+            (24389.0 / 27.0 * t + 16.0) / 116.0
+        }
+    };
+    let fx = f(x / WHITE_D65.0);
+    let fy = f(y / WHITE_D65.1);
+    let fz = f(z / WHITE_D65.2);
+
+    (116.0 * fy - 16.0, 500.0 * (fx - fy), 200.0 * (fy - fz))
+}
+
+/// Преобразование представления цвета из CIE L*a*b* (D65) в RGB пространство space.
+/// Цвета вне охвата пространства обрезаются.
+pub fn lab_to_rgb(l: f32, a: f32, b: f32, space: RgbSpace) -> (u8, u8, u8) {
+    let fy = (l + 16.0) / 116.0;
+    let fx = fy + a / 500.0;
+    let fz = fy - b / 200.0;
+    let f_inv = |t: f32| {
+        if t * t * t > 216.0 / 24389.0 {
+            t * t * t
+        } else {
+            (116.0 * t - 16.0) * 27.0 / 24389.0
+        }
+    };
+
+    xyz_to_rgb(
+        f_inv(fx) * WHITE_D65.0,
+        f_inv(fy) * WHITE_D65.1,
+        f_inv(fz) * WHITE_D65.2,
+        space,
+    )
+}
+
+/// Преобразование представления цвета из RGB в CMYK.
+/// r, g, b - составляющие [0, 255];
+///
+/// На выходе C, M, Y, K [0, 100].
+pub fn rgb_to_cmyk(r: u8, g: u8, b: u8) -> (f32, f32, f32, f32) {
+    let r_f = r as f32 / 255.0;
+    let g_f = g as f32 / 255.0;
+    let b_f = b as f32 / 255.0;
+
+    let k = 1.0 - r_f.max(g_f.max(b_f));
+    if k >= 1.0 {
+        return (0.0, 0.0, 0.0, 100.0);
+    }
+    let c = (1.0 - r_f - k) / (1.0 - k);
+    let m = (1.0 - g_f - k) / (1.0 - k);
+    let y = (1.0 - b_f - k) / (1.0 - k);
+    (c * 100.0, m * 100.0, y * 100.0, k * 100.0)
+}
+
+/// Преобразование представления цвета из CMYK в RGB.
+/// c, m, y, k - составляющие [0, 100];
+///
+/// На выходе RGB со значениями от 0 до 255.
+pub fn cmyk_to_rgb(c: f32, m: f32, y: f32, k: f32) -> (u8, u8, u8) {
+    let c = (c / 100.0).clamp(0.0, 1.0);
+    let m = (m / 100.0).clamp(0.0, 1.0);
+    let y = (y / 100.0).clamp(0.0, 1.0);
+    let k = (k / 100.0).clamp(0.0, 1.0);
+    (
+        unit_to_u8((1.0 - c) * (1.0 - k)),
+        unit_to_u8((1.0 - m) * (1.0 - k)),
+        unit_to_u8((1.0 - y) * (1.0 - k)),
+    )
+}
+
+/// Значение [0, 1] в [0, 255] с округлением
+fn unit_to_u8(v: f32) -> u8 {
+    (v * 255.0).round().clamp(0.0, 255.0) as u8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Однотонная RGB картинка width x height
+    fn flat(value: u8, width: usize, height: usize) -> Vec<u8> {
+        vec![value; width * height * 3]
+    }
+
+    /// RGB картинка с диагональным градиентом, чтобы у SSIM была структура
+    fn gradient(width: usize, height: usize) -> Vec<u8> {
+        (0..width * height)
+            .flat_map(|i| {
+                let v = ((i % width + i / width) * 255 / (width + height)) as u8;
+                [v, v / 2, 255 - v]
+            })
+            .collect()
+    }
+
+    #[test]
+    fn identical_images() {
+        let (w, h) = (64, 48);
+        let buf = gradient(w, h);
+        let metrics = compute_metrics(&buf, &buf, w, h).unwrap();
+        assert_eq!(metrics.mse, 0.0);
+        assert_eq!(metrics.psnr, f64::INFINITY);
+        assert!((metrics.ssim - 1.0).abs() < 1e-6, "{}", metrics.ssim);
+        assert!((metrics.ms_ssim - 1.0).abs() < 1e-6, "{}", metrics.ms_ssim);
+    }
+
+    #[test]
+    fn metrics_share_first_scale_with_ssim() {
+        let (w, h) = (64, 48);
+        let (a, b) = (gradient(w, h), flat(128, w, h));
+        let metrics = compute_metrics(&a, &b, w, h).unwrap();
+        assert_eq!(metrics.ssim, compute_ssim(&a, &b, w, h).unwrap());
+        assert_eq!(metrics.ms_ssim, compute_ms_ssim(&a, &b, w, h).unwrap());
+
+        // прерванный счёт не возвращает метрик
+        let mut steps = 0;
+        let interrupted = compute_metrics_with_progress(&a, &b, w, h, |_| {
+            steps += 1;
+            false
+        });
+        assert!(interrupted.unwrap().is_none());
+        assert_eq!(steps, 1);
+    }
+
+    #[test]
+    fn mse_and_psnr_known_values() {
+        let (a, b) = (flat(100, 4, 4), flat(110, 4, 4));
+        assert_eq!(compute_mse(&a, &b).unwrap(), 100.0);
+        // 10 * log10(255^2 / 100)
+        let psnr = compute_psnr(&a, &b).unwrap();
+        assert!((psnr - 28.130_803).abs() < 1e-5, "{psnr}");
+    }
+
+    #[test]
+    fn ssim_of_flat_images_is_luminance_term() {
+        // у однотонных картинок нет дисперсии, остаётся только сравнение яркости
+        let (w, h) = (32, 32);
+        let ssim = compute_ssim(&flat(100, w, h), &flat(110, w, h), w, h).unwrap();
+        let c1 = SSIM_C1 as f64;
+        let expected = (2.0 * 100.0 * 110.0 + c1) / (100.0f64.powi(2) + 110.0f64.powi(2) + c1);
+        assert!((ssim - expected).abs() < 1e-4, "{ssim} != {expected}");
+    }
+
+    #[test]
+    fn stretch_gain_reaches_full_range_for_signed_view() {
+        // разность только в красном канале, Signed выводит её треть
+        let (a, b) = ([60, 0, 0], [0, 0, 0]);
+        let gain = stretch_gain(&a, &b, DiffView::Signed).unwrap();
+        let signed = compute_difference_signed(&a, &b, gain).unwrap();
+        assert_eq!(signed, [255, 0, 0]);
+        let gain = stretch_gain(&a, &b, DiffView::Amplified).unwrap();
+        let amplified = compute_difference_amplified(&a, &b, gain).unwrap();
+        assert_eq!(amplified, [255, 0, 0]);
+    }
+
+    #[test]
+    fn metrics_reject_different_sizes() {
+        assert!(compute_mse(&flat(0, 2, 2), &flat(0, 3, 2)).is_err());
+        assert!(compute_ssim(&flat(0, 2, 2), &flat(0, 2, 2), 3, 2).is_err());
+    }
+
+    /// RGB картинка, у которой красный канал - номер пикселя, а зелёный и синий - координаты
+    fn numbered(width: usize, height: usize) -> Vec<u8> {
+        (0..width * height)
+            .flat_map(|i| [i as u8, (i % width) as u8, (i / width) as u8])
+            .collect()
+    }
+
+    /// Координаты (x, y) пикселей буфера numbered, как они записаны в зелёном и синем
+    fn coords(buf: &[u8]) -> Vec<(u8, u8)> {
+        buf.chunks_exact(3).map(|p| (p[1], p[2])).collect()
+    }
+
+    #[test]
+    fn align_crop_takes_common_top_left_part() {
+        let (a, b) = (numbered(4, 3), numbered(2, 5));
+        let (buf_1, buf_2, size) = align_images(&a, (4, 3), &b, (2, 5), SizeMatch::Crop).unwrap();
+        assert_eq!(size, (2, 3));
+        let expected = vec![(0, 0), (1, 0), (0, 1), (1, 1), (0, 2), (1, 2)];
+        assert_eq!(coords(&buf_1), expected);
+        assert_eq!(coords(&buf_2), expected);
+    }
+
+    #[test]
+    fn align_resize_stretches_second_to_first() {
+        let (a, b) = (numbered(4, 2), flat(200, 2, 1));
+        let (buf_1, buf_2, size) = align_images(&a, (4, 2), &b, (2, 1), SizeMatch::Resize).unwrap();
+        assert_eq!(size, (4, 2));
+        assert_eq!(buf_1, a);
+        assert_eq!(buf_2, flat(200, 4, 2));
+    }
+
+    #[test]
+    fn align_offset_shifts_second_image() {
+        let (a, b) = (numbered(4, 4), numbered(3, 3));
+        // вторая картинка сдвинута вправо и вниз: её (0, 0) на месте (2, 1) первой
+        let (buf_1, buf_2, size) =
+            align_images(&a, (4, 4), &b, (3, 3), SizeMatch::Offset(2, 1)).unwrap();
+        assert_eq!(size, (2, 3));
+        assert_eq!(
+            coords(&buf_1),
+            vec![(2, 1), (3, 1), (2, 2), (3, 2), (2, 3), (3, 3)]
+        );
+        assert_eq!(
+            coords(&buf_2),
+            vec![(0, 0), (1, 0), (0, 1), (1, 1), (0, 2), (1, 2)]
+        );
+    }
+
+    #[test]
+    fn align_negative_offset() {
+        let (a, b) = (numbered(3, 3), numbered(4, 4));
+        // вторая картинка сдвинута влево и вверх: её (2, 1) на месте (0, 0) первой
+        let (buf_1, buf_2, size) =
+            align_images(&a, (3, 3), &b, (4, 4), SizeMatch::Offset(-2, -1)).unwrap();
+        assert_eq!(size, (2, 3));
+        assert_eq!(
+            coords(&buf_1),
+            vec![(0, 0), (1, 0), (0, 1), (1, 1), (0, 2), (1, 2)]
+        );
+        assert_eq!(
+            coords(&buf_2),
+            vec![(2, 1), (3, 1), (2, 2), (3, 2), (2, 3), (3, 3)]
+        );
+    }
+
+    #[test]
+    fn align_without_overlap_fails() {
+        let (a, b) = (flat(0, 3, 2), flat(0, 2, 2));
+        for (dx, dy) in [(3, 0), (0, 2), (-2, 0), (0, -2), (10, -10)] {
+            let result = align_images(&a, (3, 2), &b, (2, 2), SizeMatch::Offset(dx, dy));
+            assert!(
+                matches!(result, Err(AppError::NoOverlap { dx: x, dy: y }) if (x, y) == (dx, dy)),
+                "({dx}, {dy})"
+            );
+        }
+        // буфер не того размера
+        let result = align_images(&a, (2, 2), &b, (2, 2), SizeMatch::Crop);
+        assert!(matches!(result, Err(AppError::BufferSize)));
+    }
+
+    #[test]
+    fn check_same_size_reports_pixel_counts() {
+        assert!(check_same_size(&flat(0, 2, 2), &flat(9, 2, 2)).is_ok());
+        let result = check_same_size(&flat(0, 2, 2), &flat(0, 3, 2));
+        assert!(matches!(
+            result,
+            Err(AppError::SizeMismatch {
+                first: 4,
+                second: 6
+            })
+        ));
+    }
+}
@@ -0,0 +1,188 @@
+use egui::*;
+
+use super::calculator::Notation;
+use super::color_space::{self, GamutMapping, RgbSpace};
+use super::image_op;
+
+/// Значение пикселя в оригинале и в текущем результате
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PixelSample {
+    pub x: usize,
+    pub y: usize,
+    pub original: Option<[u8; 3]>,
+    pub processed: Option<[u8; 3]>,
+}
+
+impl PixelSample {
+    /// Взять пиксель (x, y) из оригинала и результата, если он в них есть
+    pub fn new(
+        x: usize,
+        y: usize,
+        original: Option<(&[u8], (usize, usize))>,
+        processed: Option<(&[u8], (usize, usize))>,
+    ) -> Self {
+        Self {
+            x,
+            y,
+            original: original.and_then(|(buf, size)| pixel_at(buf, size, x, y)),
+            processed: processed.and_then(|(buf, size)| pixel_at(buf, size, x, y)),
+        }
+    }
+}
+
+/// Пипетка: пиксель под курсором и закреплённые щелчком пиксели
+#[derive(Default)]
+pub struct Inspector {
+    pub hovered: Option<PixelSample>,
+    /// Координаты закреплённых пикселей
+    pinned: Vec<(usize, usize)>,
+    /// Значения закреплённых пикселей в текущих оригинале и результате
+    pinned_samples: Vec<PixelSample>,
+    /// Пространства пикселей оригинала и результата
+    pub spaces: (RgbSpace, RgbSpace),
+}
+
+impl Inspector {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Закрепить пиксель, повторный щелчок по тому же пикселю ничего не добавляет
+    pub fn pin(&mut self, pixel: (usize, usize)) {
+        if !self.pinned.contains(&pixel) {
+            self.pinned.push(pixel);
+        }
+    }
+
+    /// Заново взять значения закреплённых пикселей, картинка могла измениться
+    pub fn resample(&mut self, sample: impl Fn((usize, usize)) -> PixelSample) {
+        self.pinned_samples = self.pinned.iter().copied().map(sample).collect();
+    }
+
+    /// Значения в результате, которые нужно отметить на гистограмме
+    pub fn highlighted(&self) -> Vec<[u8; 3]> {
+        self.hovered
+            .iter()
+            .chain(&self.pinned_samples)
+            .filter_map(|s| s.processed)
+            .collect()
+    }
+
+    /// Панель пипетки
+    pub fn ui(&mut self, ui: &mut Ui) {
+        ui.heading("Pixel");
+        match &self.hovered {
+            Some(sample) => sample_ui(ui, self.spaces, sample),
+            None => {
+                ui.label("Наведите курсор на картинку");
+            }
+        }
+
+        ui.separator();
+        ui.horizontal(|ui| {
+            ui.heading("Pinned");
+            if ui.button("Clear").clicked() {
+                self.pinned.clear();
+                self.pinned_samples.clear();
+            }
+        });
+
+        let mut remove = None;
+        ScrollArea::vertical().show(ui, |ui| {
+            for (i, sample) in self.pinned_samples.iter().enumerate() {
+                ui.push_id(i, |ui| {
+                    CollapsingHeader::new(format!("({}, {})", sample.x, sample.y))
+                        .default_open(true)
+                        .show(ui, |ui| {
+                            sample_ui(ui, self.spaces, sample);
+                            if ui.small_button("Unpin").clicked() {
+                                remove = Some(i);
+                            }
+                        });
+                });
+            }
+        });
+        if let Some(i) = remove {
+            self.pinned.remove(i);
+            self.pinned_samples.remove(i);
+        }
+    }
+}
+
+/// Значения пикселя во всех цветовых пространствах
+fn sample_ui(ui: &mut Ui, spaces: (RgbSpace, RgbSpace), sample: &PixelSample) {
+    ui.label(format!("x: {}, y: {}", sample.x, sample.y));
+    Grid::new(("pixel_sample", sample.x, sample.y))
+        .num_columns(3)
+        .striped(true)
+        .show(ui, |ui| {
+            ui.label("");
+            ui.label("Original");
+            ui.label("Result");
+            ui.end_row();
+
+            ui.label("");
+            swatch(ui, sample.original, spaces.0);
+            swatch(ui, sample.processed, spaces.1);
+            ui.end_row();
+
+            let original = sample.original.map(|rgb| color_values(rgb, spaces.0));
+            let processed = sample.processed.map(|rgb| color_values(rgb, spaces.1));
+            for (row, name) in color_value_names().enumerate() {
+                ui.label(name);
+                for values in [&original, &processed] {
+                    match values {
+                        Some(values) => ui.monospace(&values[row]),
+                        None => ui.label("-"),
+                    };
+                }
+                ui.end_row();
+            }
+        });
+}
+
+/// Квадрат с цветом пикселя из пространства space, выводится в sRGB
+fn swatch(ui: &mut Ui, rgb: Option<[u8; 3]>, space: RgbSpace) {
+    let (rect, _) = ui.allocate_exact_size(vec2(32.0, 16.0), Sense::hover());
+    if let Some(mut rgb) = rgb {
+        color_space::convert_buffer(&mut rgb, space, RgbSpace::Srgb, GamutMapping::Clip);
+        let [r, g, b] = rgb;
+        ui.painter()
+            .rect_filled(rect, 2.0, Color32::from_rgb(r, g, b));
+    }
+    ui.painter().rect_stroke(
+        rect,
+        2.0,
+        ui.visuals().widgets.noninteractive.bg_stroke,
+        StrokeKind::Inside,
+    );
+}
+
+/// Названия строк из color_values
+fn color_value_names() -> impl Iterator<Item = &'static str> {
+    Notation::ALL
+        .into_iter()
+        .map(Notation::name)
+        .chain(["Gray1", "Gray2"])
+}
+
+/// Цвет пикселя из RGB пространства space в виде строк для каждого пространства
+pub fn color_values(rgb: [u8; 3], space: RgbSpace) -> Vec<String> {
+    let [r, g, b] = rgb;
+    let (gray1, _, _) = image_op::rgb_to_grayscale1(r, g, b);
+    let (gray2, _, _) = image_op::rgb_to_grayscale2(r, g, b);
+    Notation::ALL
+        .into_iter()
+        .map(|notation| notation.format(rgb, space))
+        .chain([gray1.to_string(), gray2.to_string()])
+        .collect()
+}
+
+/// Пиксель (x, y) RGB буфера размера size
+fn pixel_at(buf: &[u8], size: (usize, usize), x: usize, y: usize) -> Option<[u8; 3]> {
+    if x >= size.0 || y >= size.1 {
+        return None;
+    }
+    let i = (y * size.0 + x) * 3;
+    buf.get(i..i + 3).map(|p| [p[0], p[1], p[2]])
+}
@@ -0,0 +1,182 @@
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::{Arc, mpsc};
+use std::time::Duration;
+
+use eframe::egui;
+
+use super::color_space::RgbSpace;
+
+/// Сколько пикселей обрабатывается между проверками отмены
+const CHUNK_PIXELS: usize = 1 << 16;
+
+/// Прогресс задания и флаг отмены, общие для UI и рабочего потока
+#[derive(Default)]
+pub struct Progress {
+    /// Доля выполненного, биты f32
+    done: AtomicU32,
+    cancelled: AtomicBool,
+}
+
+impl Progress {
+    pub fn set(&self, fraction: f32) {
+        let fraction = fraction.clamp(0.0, 1.0);
+        self.done.store(fraction.to_bits(), Ordering::Relaxed);
+    }
+
+    pub fn fraction(&self) -> f32 {
+        f32::from_bits(self.done.load(Ordering::Relaxed))
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+}
+
+/// Результат задания для вывода
+pub struct JobOutput {
+    pub name: String,
+    pub buf: Vec<u8>,
+    /// Точный буфер, если операция сохранила точность исходника
+    pub precise: Option<Vec<f32>>,
+    pub size: (usize, usize),
+    /// Пространство, в котором записаны пиксели результата
+    pub space: RgbSpace,
+    /// Пиксели, вышедшие за охват; None - охват не проверялся
+    pub out_of_gamut: Option<Vec<bool>>,
+}
+
+/// Выполняющееся задание
+struct Running<T> {
+    name: String,
+    progress: Arc<Progress>,
+    result: mpsc::Receiver<T>,
+}
+
+/// Задание в фоновом потоке, по умолчанию - операция над картинкой.
+/// Новое задание отменяет предыдущее, результат отменённого не выводится.
+pub struct Jobs<T = JobOutput> {
+    running: Option<Running<T>>,
+}
+
+impl<T> Default for Jobs<T> {
+    fn default() -> Self {
+        Self { running: None }
+    }
+}
+
+impl<T: Send + 'static> Jobs<T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Запустить job в фоновом потоке; job возвращает None, если его отменили
+    pub fn spawn(
+        &mut self,
+        ctx: &egui::Context,
+        name: impl Into<String>,
+        job: impl FnOnce(&Progress) -> Option<T> + Send + 'static,
+    ) {
+        self.cancel();
+        let progress = Arc::new(Progress::default());
+        let (sender, result) = mpsc::channel();
+        let worker = Arc::clone(&progress);
+        let ctx = ctx.clone();
+        std::thread::spawn(move || {
+            if let Some(output) = job(&worker)
+                && !worker.is_cancelled()
+            {
+                // получатель мог уже пропасть вместе с закрытой картинкой
+                let _ = sender.send(output);
+            }
+            ctx.request_repaint();
+        });
+        self.running = Some(Running {
+            name: name.into(),
+            progress,
+            result,
+        });
+    }
+
+    /// Результат завершившегося задания
+    pub fn poll(&mut self) -> Option<T> {
+        let running = self.running.as_ref()?;
+        match running.result.try_recv() {
+            Ok(output) => {
+                self.running = None;
+                Some(output)
+            }
+            Err(mpsc::TryRecvError::Empty) => None,
+            Err(mpsc::TryRecvError::Disconnected) => {
+                self.running = None;
+                None
+            }
+        }
+    }
+
+    /// Полоса прогресса с кнопкой отмены, пока задание выполняется
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        self.progress_ui(ui);
+        if self.running.is_some() && ui.small_button("Cancel").clicked() {
+            self.cancel();
+        }
+    }
+
+    /// Полоса прогресса без кнопки отмены, для заданий, без которых нельзя продолжить
+    pub fn progress_ui(&self, ui: &mut egui::Ui) {
+        let Some(running) = &self.running else {
+            return;
+        };
+        ui.add(
+            egui::ProgressBar::new(running.progress.fraction())
+                .desired_width(160.0)
+                .text(&running.name)
+                .animate(true),
+        );
+        ui.ctx().request_repaint_after(Duration::from_millis(100));
+    }
+}
+
+impl<T> Jobs<T> {
+    /// Отменить выполняющееся задание
+    pub fn cancel(&mut self) {
+        if let Some(running) = self.running.take() {
+            running.progress.cancel();
+        }
+    }
+
+    /// Задание запущено и ещё не выдало результат
+    pub fn is_running(&self) -> bool {
+        self.running.is_some()
+    }
+}
+
+impl<T> Drop for Jobs<T> {
+    fn drop(&mut self) {
+        self.cancel();
+    }
+}
+
+/// Применить попиксельную операцию f к буферу кусками, отмечая прогресс
+/// от span.0 до span.1. false, если задание отменили.
+pub fn for_chunks<T>(
+    buf: &mut [T],
+    channels: usize,
+    progress: &Progress,
+    span: (f32, f32),
+    mut f: impl FnMut(&mut [T]),
+) -> bool {
+    let chunk = CHUNK_PIXELS * channels;
+    let count = buf.len().div_ceil(chunk).max(1);
+    for (i, part) in buf.chunks_mut(chunk).enumerate() {
+        if progress.is_cancelled() {
+            return false;
+        }
+        f(part);
+        progress.set(span.0 + (span.1 - span.0) * (i + 1) as f32 / count as f32);
+    }
+    !progress.is_cancelled()
+}
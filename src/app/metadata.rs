@@ -0,0 +1,553 @@
+use egui::*;
+use image::DynamicImage;
+use image::metadata::Orientation;
+
+use super::color_space::RgbSpace;
+
+/// Теги EXIF, которые выводятся в панели
+const TAG_MAKE: u16 = 0x010F;
+const TAG_MODEL: u16 = 0x0110;
+const TAG_SOFTWARE: u16 = 0x0131;
+const TAG_DATE_TIME: u16 = 0x0132;
+const TAG_EXIF_IFD: u16 = 0x8769;
+const TAG_EXPOSURE_TIME: u16 = 0x829A;
+const TAG_F_NUMBER: u16 = 0x829D;
+const TAG_ISO: u16 = 0x8827;
+const TAG_DATE_TIME_ORIGINAL: u16 = 0x9003;
+const TAG_EXPOSURE_BIAS: u16 = 0x9204;
+const TAG_FLASH: u16 = 0x9209;
+const TAG_FOCAL_LENGTH: u16 = 0x920A;
+const TAG_COLOR_SPACE: u16 = 0xA001;
+const TAG_INTEROP_IFD: u16 = 0xA005;
+const TAG_FOCAL_LENGTH_35MM: u16 = 0xA405;
+const TAG_LENS_MODEL: u16 = 0xA434;
+const TAG_INTEROP_INDEX: u16 = 0x0001;
+
+/// Значения тега ColorSpace
+const COLOR_SPACE_SRGB: u16 = 1;
+const COLOR_SPACE_UNCALIBRATED: u16 = 0xFFFF;
+
+/// Чтение чисел TIFF структуры EXIF в порядке байтов блока
+struct Tiff<'a> {
+    data: &'a [u8],
+    big_endian: bool,
+}
+
+/// Запись каталога (IFD): тег, тип, число значений и где они лежат
+struct Entry {
+    tag: u16,
+    kind: u16,
+    count: usize,
+    /// Смещение значений в блоке
+    offset: usize,
+}
+
+impl<'a> Tiff<'a> {
+    fn new(data: &'a [u8]) -> Option<Self> {
+        let big_endian = match data.get(0..4)? {
+            [0x49, 0x49, 42, 0] => false,
+            [0x4D, 0x4D, 0, 42] => true,
+            _ => return None,
+        };
+        Some(Self { data, big_endian })
+    }
+
+    fn u16(&self, offset: usize) -> Option<u16> {
+        let bytes = self.data.get(offset..offset + 2)?.try_into().ok()?;
+        Some(if self.big_endian {
+            u16::from_be_bytes(bytes)
+        } else {
+            u16::from_le_bytes(bytes)
+        })
+    }
+
+    fn u32(&self, offset: usize) -> Option<u32> {
+        let bytes = self.data.get(offset..offset + 4)?.try_into().ok()?;
+        Some(if self.big_endian {
+            u32::from_be_bytes(bytes)
+        } else {
+            u32::from_le_bytes(bytes)
+        })
+    }
+
+    /// Записи каталога по смещению offset
+    fn entries(&self, offset: usize) -> Vec<Entry> {
+        let count = self.u16(offset).unwrap_or(0) as usize;
+        (0..count)
+            .map_while(|i| {
+                let at = offset + 2 + i * 12;
+                let kind = self.u16(at + 2)?;
+                let count = self.u32(at + 4)? as usize;
+                let size = type_size(kind).saturating_mul(count);
+                // значения до 4 байт лежат прямо в записи
+                let offset = if size <= 4 {
+                    at + 8
+                } else {
+                    self.u32(at + 8)? as usize
+                };
+                Some(Entry {
+                    tag: self.u16(at)?,
+                    kind,
+                    count,
+                    offset,
+                })
+            })
+            .collect()
+    }
+
+    /// Вложенный каталог, на который указывает тег tag каталога entries
+    fn sub_ifd(&self, entries: &[Entry], tag: u16) -> Vec<Entry> {
+        find(entries, tag)
+            .and_then(|e| self.u32(e.offset))
+            .map(|offset| self.entries(offset as usize))
+            .unwrap_or_default()
+    }
+
+    /// Текстовое значение записи
+    fn text(&self, entry: &Entry) -> Option<String> {
+        let bytes = self.data.get(entry.offset..entry.offset + entry.count)?;
+        let text = String::from_utf8_lossy(bytes);
+        let text = text.trim_matches(char::from(0)).trim();
+        (!text.is_empty()).then(|| text.to_string())
+    }
+
+    /// Первое числовое значение записи: целое или дробь
+    fn number(&self, entry: &Entry) -> Option<f64> {
+        match entry.kind {
+            3 => self.u16(entry.offset).map(f64::from),
+            8 => self.u16(entry.offset).map(|v| v as i16 as f64),
+            4 => self.u32(entry.offset).map(f64::from),
+            9 => self.u32(entry.offset).map(|v| v as i32 as f64),
+            5 => {
+                let (n, d) = (self.u32(entry.offset)?, self.u32(entry.offset + 4)?);
+                (d != 0).then(|| n as f64 / d as f64)
+            }
+            10 => {
+                let n = self.u32(entry.offset)? as i32;
+                let d = self.u32(entry.offset + 4)? as i32;
+                (d != 0).then(|| n as f64 / d as f64)
+            }
+            _ => None,
+        }
+    }
+}
+
+fn find(entries: &[Entry], tag: u16) -> Option<&Entry> {
+    entries.iter().find(|e| e.tag == tag)
+}
+
+/// Размер одного значения типа TIFF в байтах
+fn type_size(kind: u16) -> usize {
+    match kind {
+        1 | 2 | 6 | 7 => 1,
+        3 | 8 => 2,
+        4 | 9 | 11 => 4,
+        5 | 10 | 12 => 8,
+        _ => 0,
+    }
+}
+
+/// Метаданные загруженного файла: EXIF и применённая при загрузке ориентация
+pub struct ImageMetadata {
+    /// EXIF блок со сброшенной ориентацией: пиксели уже повёрнуты
+    exif: Option<Vec<u8>>,
+    orientation: Orientation,
+    /// Поля EXIF для панели: название и значение
+    fields: Vec<(&'static str, String)>,
+    /// Сохранять EXIF в файлы результатов
+    pub keep_on_save: bool,
+}
+
+impl Default for ImageMetadata {
+    fn default() -> Self {
+        Self {
+            exif: None,
+            orientation: Orientation::NoTransforms,
+            fields: Vec::new(),
+            keep_on_save: true,
+        }
+    }
+}
+
+impl ImageMetadata {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Запомнить EXIF нового файла и ориентацию, применённую к его пикселям
+    pub fn set(&mut self, exif: Option<Vec<u8>>, orientation: Orientation) {
+        self.fields = exif.as_deref().map(read_fields).unwrap_or_default();
+        self.exif = exif;
+        self.orientation = orientation;
+    }
+
+    /// EXIF для сохраняемого файла, пиксели которого записаны в пространстве space.
+    /// Тег ColorSpace исправляется: sRGB только для sRGB пикселей.
+    pub fn exif_for_save(&self, space: RgbSpace) -> Option<Vec<u8>> {
+        if !self.keep_on_save {
+            return None;
+        }
+        let mut exif = self.exif.clone()?;
+        let value = if space == RgbSpace::Srgb {
+            COLOR_SPACE_SRGB
+        } else {
+            COLOR_SPACE_UNCALIBRATED
+        };
+        set_color_space(&mut exif, value);
+        Some(exif)
+    }
+
+    pub fn ui(&mut self, ui: &mut Ui) {
+        ui.label(format!(
+            "Orientation: {}",
+            orientation_name(self.orientation)
+        ));
+        if self.exif.is_none() {
+            ui.label("EXIF отсутствует");
+            return;
+        }
+        if self.fields.is_empty() {
+            ui.label("В EXIF нет известных полей");
+        }
+        Grid::new("metadata_grid")
+            .num_columns(2)
+            .striped(true)
+            .show(ui, |ui| {
+                for (name, value) in &self.fields {
+                    ui.label(*name);
+                    ui.label(value);
+                    ui.end_row();
+                }
+            });
+        ui.separator();
+        ui.checkbox(&mut self.keep_on_save, "Keep EXIF on save")
+            .on_hover_text("В PNG, JPEG и WebP; ориентация сбрасывается, пиксели уже повёрнуты");
+    }
+}
+
+/// Поля EXIF для панели
+fn read_fields(exif: &[u8]) -> Vec<(&'static str, String)> {
+    let Some(tiff) = Tiff::new(exif) else {
+        return vec![("EXIF", "не разобран".to_string())];
+    };
+    let ifd0 = tiff
+        .u32(4)
+        .map(|o| tiff.entries(o as usize))
+        .unwrap_or_default();
+    let exif_ifd = tiff.sub_ifd(&ifd0, TAG_EXIF_IFD);
+    let interop = tiff.sub_ifd(&exif_ifd, TAG_INTEROP_IFD);
+    let text = |entries: &[Entry], tag| find(entries, tag).and_then(|e| tiff.text(e));
+    let number = |entries: &[Entry], tag| find(entries, tag).and_then(|e| tiff.number(e));
+
+    let camera = match (text(&ifd0, TAG_MAKE), text(&ifd0, TAG_MODEL)) {
+        // модель часто уже начинается с производителя
+        (Some(make), Some(model)) if model.starts_with(&make) => Some(model),
+        (Some(make), Some(model)) => Some(format!("{make} {model}")),
+        (make, model) => make.or(model),
+    };
+    let focal_length = number(&exif_ifd, TAG_FOCAL_LENGTH).map(|f| {
+        match number(&exif_ifd, TAG_FOCAL_LENGTH_35MM).filter(|&f35| f35 > 0.0) {
+            Some(f35) => format!("{f:.1} mm ({f35:.0} mm equiv.)"),
+            None => format!("{f:.1} mm"),
+        }
+    });
+    let color_space = number(&exif_ifd, TAG_COLOR_SPACE).map(|v| {
+        let interop = text(&interop, TAG_INTEROP_INDEX);
+        match (v as u16, interop.as_deref()) {
+            (COLOR_SPACE_SRGB, _) => "sRGB".to_string(),
+            // Adobe RGB обозначается индексом совместимости R03
+            (COLOR_SPACE_UNCALIBRATED, Some("R03")) => "Uncalibrated (Adobe RGB, R03)".to_string(),
+            (COLOR_SPACE_UNCALIBRATED, _) => "Uncalibrated".to_string(),
+            (v, _) => format!("{v}"),
+        }
+    });
+
+    [
+        ("Camera", camera),
+        ("Lens", text(&exif_ifd, TAG_LENS_MODEL)),
+        (
+            "Exposure",
+            number(&exif_ifd, TAG_EXPOSURE_TIME).map(exposure_time),
+        ),
+        (
+            "Aperture",
+            number(&exif_ifd, TAG_F_NUMBER).map(|f| format!("f/{f:.1}")),
+        ),
+        (
+            "ISO",
+            number(&exif_ifd, TAG_ISO).map(|iso| format!("{iso:.0}")),
+        ),
+        (
+            "Exposure bias",
+            number(&exif_ifd, TAG_EXPOSURE_BIAS).map(|ev| format!("{ev:+.1} EV")),
+        ),
+        ("Focal length", focal_length),
+        (
+            "Flash",
+            number(&exif_ifd, TAG_FLASH).map(|v| {
+                if v as u16 & 1 == 1 {
+                    "Fired"
+                } else {
+                    "Not fired"
+                }
+                .to_string()
+            }),
+        ),
+        ("Color space", color_space),
+        (
+            "Taken",
+            text(&exif_ifd, TAG_DATE_TIME_ORIGINAL).or_else(|| text(&ifd0, TAG_DATE_TIME)),
+        ),
+        ("Software", text(&ifd0, TAG_SOFTWARE)),
+    ]
+    .into_iter()
+    .filter_map(|(name, value)| value.map(|v| (name, v)))
+    .collect()
+}
+
+/// Выдержка: доли секунды как 1/250 s, длинные как 2.5 s
+fn exposure_time(seconds: f64) -> String {
+    if seconds > 0.0 && seconds < 0.5 {
+        format!("1/{:.0} s", 1.0 / seconds)
+    } else {
+        format!("{seconds} s")
+    }
+}
+
+/// Повернуть пиксели по ориентации и сбросить её в EXIF,
+/// чтобы сохранённый результат не повернулся второй раз
+pub fn apply_orientation(
+    image: &mut DynamicImage,
+    exif: Option<&mut [u8]>,
+    orientation: Orientation,
+) {
+    image.apply_orientation(orientation);
+    if let Some(exif) = exif {
+        let _ = Orientation::remove_from_exif_chunk(exif);
+    }
+}
+
+/// Записать значение тега ColorSpace, если он есть в EXIF
+fn set_color_space(exif: &mut [u8], value: u16) {
+    let Some(tiff) = Tiff::new(exif) else {
+        return;
+    };
+    let ifd0 = tiff
+        .u32(4)
+        .map(|o| tiff.entries(o as usize))
+        .unwrap_or_default();
+    let exif_ifd = tiff.sub_ifd(&ifd0, TAG_EXIF_IFD);
+    let Some(entry) = find(&exif_ifd, TAG_COLOR_SPACE).filter(|e| e.kind == 3) else {
+        return;
+    };
+    let bytes = if tiff.big_endian {
+        value.to_be_bytes()
+    } else {
+        value.to_le_bytes()
+    };
+    // у обрезанного EXIF запись может указывать за конец блока
+    if let Some(target) = exif.get_mut(entry.offset..entry.offset + 2) {
+        target.copy_from_slice(&bytes);
+    }
+}
+
+fn orientation_name(orientation: Orientation) -> &'static str {
+    match orientation {
+        Orientation::NoTransforms => "Normal",
+        Orientation::Rotate90 => "Rotated 90° CW (applied)",
+        Orientation::Rotate180 => "Rotated 180° (applied)",
+        Orientation::Rotate270 => "Rotated 90° CCW (applied)",
+        Orientation::FlipHorizontal => "Mirrored horizontally (applied)",
+        Orientation::FlipVertical => "Mirrored vertically (applied)",
+        Orientation::Rotate90FlipH => "Rotated 90° CW and mirrored (applied)",
+        Orientation::Rotate270FlipH => "Rotated 90° CCW and mirrored (applied)",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TAG_ORIENTATION: u16 = 0x0112;
+    /// Смещение вложенного каталога Exif в блоке exif_blob
+    const EXIF_IFD_OFFSET: u32 = 38;
+
+    /// Синтетический EXIF: IFD0 с ориентацией и ссылкой на Exif IFD с тегом ColorSpace
+    fn exif_blob(big_endian: bool, orientation: u16, color_space: u16) -> Vec<u8> {
+        let u16b = |v: u16| {
+            if big_endian {
+                v.to_be_bytes()
+            } else {
+                v.to_le_bytes()
+            }
+        };
+        let u32b = |v: u32| {
+            if big_endian {
+                v.to_be_bytes()
+            } else {
+                v.to_le_bytes()
+            }
+        };
+        let mut blob = Vec::new();
+        blob.extend(if big_endian { *b"MM\0*" } else { *b"II*\0" });
+        blob.extend(u32b(8));
+        // IFD0: ориентация и ссылка на Exif IFD
+        blob.extend(u16b(2));
+        blob.extend(u16b(TAG_ORIENTATION));
+        blob.extend(u16b(3));
+        blob.extend(u32b(1));
+        blob.extend(u16b(orientation));
+        blob.extend([0, 0]);
+        blob.extend(u16b(TAG_EXIF_IFD));
+        blob.extend(u16b(4));
+        blob.extend(u32b(1));
+        blob.extend(u32b(EXIF_IFD_OFFSET));
+        blob.extend(u32b(0));
+        assert_eq!(blob.len(), EXIF_IFD_OFFSET as usize);
+        // Exif IFD: ColorSpace
+        blob.extend(u16b(1));
+        blob.extend(u16b(TAG_COLOR_SPACE));
+        blob.extend(u16b(3));
+        blob.extend(u32b(1));
+        blob.extend(u16b(color_space));
+        blob.extend([0, 0]);
+        blob.extend(u32b(0));
+        blob
+    }
+
+    /// Значение тега ColorSpace в блоке
+    fn color_space(exif: &[u8]) -> Option<u16> {
+        let tiff = Tiff::new(exif)?;
+        let ifd0 = tiff.entries(tiff.u32(4)? as usize);
+        let exif_ifd = tiff.sub_ifd(&ifd0, TAG_EXIF_IFD);
+        tiff.u16(find(&exif_ifd, TAG_COLOR_SPACE)?.offset)
+    }
+
+    /// Куда попадает пиксель (x, y) картинки 3x2 при ориентации value
+    fn oriented_position(value: u16, x: u32, y: u32) -> (u32, u32) {
+        match value {
+            1 => (x, y),
+            2 => (2 - x, y),
+            3 => (2 - x, 1 - y),
+            4 => (x, 1 - y),
+            5 => (y, x),
+            6 => (1 - y, x),
+            7 => (1 - y, 2 - x),
+            _ => (y, 2 - x),
+        }
+    }
+
+    #[test]
+    fn orientation_is_read_from_both_byte_orders() {
+        for big_endian in [false, true] {
+            for value in 1..=8 {
+                let blob = exif_blob(big_endian, value, COLOR_SPACE_SRGB);
+                assert_eq!(
+                    Orientation::from_exif_chunk(&blob),
+                    Orientation::from_exif(value as u8),
+                    "big endian {big_endian}, orientation {value}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn orientations_transform_pixels_and_reset_exif() {
+        // картинка 3x2 с номерами пикселей: (x, y) -> y * 3 + x
+        let (w, h) = (3, 2);
+        let source = image::GrayImage::from_fn(w, h, |x, y| image::Luma([(y * w + x) as u8]));
+        for value in 1..=8 {
+            let mut blob = exif_blob(value % 2 == 0, value, COLOR_SPACE_SRGB);
+            let orientation = Orientation::from_exif_chunk(&blob).unwrap();
+            let mut image = DynamicImage::ImageLuma8(source.clone());
+            apply_orientation(&mut image, Some(&mut blob), orientation);
+
+            let image = image.to_luma8();
+            let rotated = value >= 5;
+            let size = if rotated { (h, w) } else { (w, h) };
+            assert_eq!(image.dimensions(), size, "orientation {value}");
+            for (x, y, pixel) in source.enumerate_pixels() {
+                let (tx, ty) = oriented_position(value, x, y);
+                assert_eq!(image.get_pixel(tx, ty), pixel, "orientation {value}");
+            }
+            assert_eq!(
+                Orientation::from_exif_chunk(&blob),
+                Some(Orientation::NoTransforms)
+            );
+        }
+    }
+
+    #[test]
+    fn truncated_blobs_do_not_panic() {
+        let blob = exif_blob(false, 6, COLOR_SPACE_UNCALIBRATED);
+        // конец значения ColorSpace: дальше только ссылка на следующий каталог
+        let value_end = EXIF_IFD_OFFSET as usize + 2 + 8 + 2;
+        for len in 0..blob.len() {
+            let mut truncated = blob[..len].to_vec();
+            read_fields(&truncated);
+            set_color_space(&mut truncated, COLOR_SPACE_SRGB);
+            let expected = (len >= value_end).then_some(COLOR_SPACE_SRGB);
+            assert_eq!(color_space(&truncated), expected, "length {len}");
+        }
+        assert!(Tiff::new(&blob[..3]).is_none());
+        let tiff = Tiff::new(&blob).unwrap();
+        assert_eq!(tiff.u16(blob.len() - 1), None);
+        assert_eq!(tiff.u32(blob.len() - 3), None);
+    }
+
+    #[test]
+    fn out_of_range_offsets_return_none() {
+        let mut blob = exif_blob(true, 1, COLOR_SPACE_UNCALIBRATED);
+        // ссылка на Exif IFD за концом блока
+        blob[30..34].copy_from_slice(&0xFFFF_FF00u32.to_be_bytes());
+        let tiff = Tiff::new(&blob).unwrap();
+        let ifd0 = tiff.entries(8);
+        assert_eq!(ifd0.len(), 2);
+        assert!(tiff.sub_ifd(&ifd0, TAG_EXIF_IFD).is_empty());
+        assert!(tiff.entries(blob.len() + 100).is_empty());
+
+        // значения записи за концом блока
+        let far = Entry {
+            tag: TAG_MAKE,
+            kind: 5,
+            count: 4,
+            offset: blob.len() - 2,
+        };
+        assert_eq!(tiff.text(&far), None);
+        assert_eq!(tiff.number(&far), None);
+
+        let before = blob.clone();
+        set_color_space(&mut blob, COLOR_SPACE_SRGB);
+        assert_eq!(blob, before);
+        assert!(
+            read_fields(&blob)
+                .iter()
+                .all(|(name, _)| *name != "Color space")
+        );
+    }
+
+    #[test]
+    fn color_space_tag_follows_saved_space() {
+        for big_endian in [false, true] {
+            let mut blob = exif_blob(big_endian, 6, COLOR_SPACE_UNCALIBRATED);
+            let orientation = Orientation::from_exif_chunk(&blob).unwrap();
+            let mut image = DynamicImage::new_luma8(1, 1);
+            apply_orientation(&mut image, Some(&mut blob), orientation);
+
+            let mut metadata = ImageMetadata::new();
+            metadata.set(Some(blob), orientation);
+            let srgb = metadata.exif_for_save(RgbSpace::Srgb).unwrap();
+            assert_eq!(color_space(&srgb), Some(COLOR_SPACE_SRGB));
+            // ориентация остаётся сброшенной
+            assert_eq!(
+                Orientation::from_exif_chunk(&srgb),
+                Some(Orientation::NoTransforms)
+            );
+            let p3 = metadata.exif_for_save(RgbSpace::DisplayP3).unwrap();
+            assert_eq!(color_space(&p3), Some(COLOR_SPACE_UNCALIBRATED));
+
+            metadata.keep_on_save = false;
+            assert_eq!(metadata.exif_for_save(RgbSpace::Srgb), None);
+        }
+    }
+}
@@ -0,0 +1,164 @@
+use std::time::{Duration, Instant};
+
+use egui::*;
+
+/// Сколько секунд всплывающее сообщение остаётся на экране
+const TOAST_SECONDS: f64 = 6.0;
+
+/// Сколько всплывающих сообщений видно одновременно
+const MAX_TOASTS: usize = 4;
+
+/// Важность сообщения
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Level {
+    Info,
+    Warning,
+    Error,
+}
+
+impl Level {
+    fn color(self, visuals: &Visuals) -> Color32 {
+        match self {
+            Level::Info => visuals.text_color(),
+            Level::Warning => visuals.warn_fg_color,
+            Level::Error => visuals.error_fg_color,
+        }
+    }
+}
+
+/// Запись журнала
+struct LogEntry {
+    level: Level,
+    message: String,
+    /// Время от начала сеанса
+    time: Duration,
+}
+
+/// Всплывающее сообщение: номер записи журнала и когда оно исчезнет
+struct Toast {
+    entry: usize,
+    /// None - ещё не показано, время egui
+    expires: Option<f64>,
+}
+
+/// Журнал ошибок и предупреждений сеанса и всплывающие сообщения о них
+pub struct Notifications {
+    log: Vec<LogEntry>,
+    toasts: Vec<Toast>,
+    started: Instant,
+    /// Ошибок с последнего открытия журнала
+    pub unread_errors: usize,
+}
+
+impl Default for Notifications {
+    fn default() -> Self {
+        Self {
+            log: Vec::new(),
+            toasts: Vec::new(),
+            started: Instant::now(),
+            unread_errors: 0,
+        }
+    }
+}
+
+impl Notifications {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Записать сообщение в журнал и показать его всплывающим
+    pub fn push(&mut self, level: Level, message: impl Into<String>) {
+        if level == Level::Error {
+            self.unread_errors += 1;
+        }
+        self.log.push(LogEntry {
+            level,
+            message: message.into(),
+            time: self.started.elapsed(),
+        });
+        self.toasts.push(Toast {
+            entry: self.log.len() - 1,
+            expires: None,
+        });
+    }
+
+    pub fn error(&mut self, error: &impl std::fmt::Display) {
+        self.push(Level::Error, error.to_string());
+    }
+
+    pub fn warning(&mut self, message: impl Into<String>) {
+        self.push(Level::Warning, message);
+    }
+
+    pub fn info(&mut self, message: impl Into<String>) {
+        self.push(Level::Info, message);
+    }
+
+    /// Всплывающие сообщения в правом нижнем углу; щелчок закрывает сообщение
+    pub fn show_toasts(&mut self, ctx: &Context) {
+        let now = ctx.input(|i| i.time);
+        self.toasts.retain(|t| t.expires.is_none_or(|e| e > now));
+        // старые сообщения уступают место новым
+        let hidden = self.toasts.len().saturating_sub(MAX_TOASTS);
+        self.toasts.drain(..hidden);
+        if self.toasts.is_empty() {
+            return;
+        }
+
+        let mut closed = None;
+        Area::new(Id::new("toasts"))
+            .anchor(Align2::RIGHT_BOTTOM, vec2(-12.0, -12.0))
+            .order(Order::Foreground)
+            .show(ctx, |ui| {
+                ui.set_max_width(360.0);
+                for (i, toast) in self.toasts.iter_mut().enumerate() {
+                    let expires = *toast.expires.get_or_insert(now + TOAST_SECONDS);
+                    let entry = &self.log[toast.entry];
+                    let response = Frame::popup(ui.style())
+                        .show(ui, |ui| {
+                            ui.colored_label(entry.level.color(ui.visuals()), &entry.message);
+                        })
+                        .response
+                        .interact(Sense::click())
+                        .on_hover_text("Щелчок закрывает сообщение");
+                    if response.clicked() {
+                        closed = Some(i);
+                    }
+                    ctx.request_repaint_after_secs((expires - now) as f32);
+                }
+            });
+        if let Some(i) = closed {
+            self.toasts.remove(i);
+        }
+    }
+
+    /// Журнал сообщений сеанса
+    pub fn log_ui(&mut self, ui: &mut Ui) {
+        self.unread_errors = 0;
+        ui.horizontal(|ui| {
+            ui.label(format!("{} messages", self.log.len()));
+            if ui.button("Clear").clicked() {
+                self.log.clear();
+                self.toasts.clear();
+            }
+        });
+        ui.separator();
+        ScrollArea::vertical()
+            .stick_to_bottom(true)
+            .auto_shrink([false, true])
+            .show(ui, |ui| {
+                for entry in &self.log {
+                    let secs = entry.time.as_secs();
+                    ui.horizontal_wrapped(|ui| {
+                        ui.monospace(format!(
+                            "{:02}:{:02}:{:02}",
+                            secs / 3600,
+                            secs / 60 % 60,
+                            secs % 60
+                        ));
+                        ui.colored_label(entry.level.color(ui.visuals()), &entry.message);
+                    });
+                }
+            });
+    }
+}
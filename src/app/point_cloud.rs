@@ -0,0 +1,298 @@
+use egui::*;
+
+use super::color_space::RgbSpace;
+use super::image_op;
+use super::viewer;
+
+/// Наибольшее число точек облака
+const MAX_POINTS: usize = 50_000;
+
+/// Пространство, в котором строится облако точек
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum CloudSpace {
+    #[default]
+    Rgb,
+    HsvCone,
+    Lab,
+}
+
+impl CloudSpace {
+    pub const ALL: [CloudSpace; 3] = [CloudSpace::Rgb, CloudSpace::HsvCone, CloudSpace::Lab];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            CloudSpace::Rgb => "RGB cube",
+            CloudSpace::HsvCone => "HSV cone",
+            CloudSpace::Lab => "Lab",
+        }
+    }
+
+    /// Координаты цвета в кубе [-0.5, 0.5], ось y вверх.
+    /// rgb_space - пространство пикселей, нужно для Lab.
+    fn position(self, r: u8, g: u8, b: u8, rgb_space: RgbSpace) -> [f32; 3] {
+        match self {
+            CloudSpace::Rgb => [
+                r as f32 / 255.0 - 0.5,
+                g as f32 / 255.0 - 0.5,
+                b as f32 / 255.0 - 0.5,
+            ],
+            CloudSpace::HsvCone => {
+                let (h, s, v) = image_op::rgb_to_hsv(r, g, b);
+                let radius = s as f32 / 100.0 * v as f32 / 100.0 * 0.5;
+                let angle = (h as f32).to_radians();
+                [
+                    radius * angle.cos(),
+                    v as f32 / 100.0 - 0.5,
+                    radius * angle.sin(),
+                ]
+            }
+            CloudSpace::Lab => {
+                let (l, a, b) = image_op::rgb_to_lab(r, g, b, rgb_space);
+                [a / 256.0, l / 100.0 - 0.5, b / 256.0]
+            }
+        }
+    }
+}
+
+/// Трёхмерное облако пикселей картинки, рисуется на CPU через painter.
+/// Перетаскивание мышью вращает, колесо меняет масштаб.
+pub struct PointCloud {
+    pub space: CloudSpace,
+    /// Рабочее RGB пространство пикселей
+    pub rgb_space: RgbSpace,
+    /// Сколько точек рисовать
+    pub max_points: usize,
+    pub point_size: f32,
+    /// Поворот вокруг вертикальной оси и наклон, радианы
+    yaw: f32,
+    pitch: f32,
+    zoom: f32,
+    /// Подвыборка пикселей картинки, RGB буфер
+    samples: Vec<u8>,
+    /// Точки в пространстве и их цвета
+    points: Vec<([f32; 3], Color32)>,
+    /// Пространства и число точек, для которых построено облако
+    built_for: Option<(CloudSpace, RgbSpace, usize)>,
+}
+
+impl Default for PointCloud {
+    fn default() -> Self {
+        Self {
+            space: CloudSpace::default(),
+            rgb_space: RgbSpace::default(),
+            max_points: 20_000,
+            point_size: 2.0,
+            yaw: -0.6,
+            pitch: 0.4,
+            zoom: 1.0,
+            samples: Vec::new(),
+            points: Vec::new(),
+            built_for: None,
+        }
+    }
+}
+
+impl PointCloud {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Взять пиксели новой картинки; облако перестраивается, только если они изменились
+    pub fn set_image(&mut self, buf: &[u8]) {
+        let step = (buf.len() / 3).div_ceil(MAX_POINTS).max(1);
+        let samples: Vec<u8> = buf
+            .chunks_exact(3)
+            .step_by(step)
+            .flatten()
+            .copied()
+            .collect();
+        if samples != self.samples {
+            self.samples = samples;
+            self.built_for = None;
+        }
+    }
+
+    /// Окно облака точек
+    pub fn ui(&mut self, ui: &mut Ui) {
+        ui.horizontal(|ui| {
+            ComboBox::from_id_salt("cloud_space")
+                .selected_text(self.space.name())
+                .show_ui(ui, |ui| {
+                    for space in CloudSpace::ALL {
+                        ui.selectable_value(&mut self.space, space, space.name());
+                    }
+                });
+            ui.add(
+                Slider::new(&mut self.max_points, 1_000..=MAX_POINTS)
+                    .logarithmic(true)
+                    .text("points"),
+            );
+            ui.add(Slider::new(&mut self.point_size, 1.0..=6.0).text("size"));
+            if ui.button("Reset").clicked() {
+                let defaults = Self::default();
+                (self.yaw, self.pitch, self.zoom) = (defaults.yaw, defaults.pitch, defaults.zoom);
+            }
+        });
+
+        if self.samples.is_empty() {
+            ui.label("Необходимо загрузить картинку.");
+            return;
+        }
+        if self.built_for != Some((self.space, self.rgb_space, self.max_points)) {
+            self.build();
+        }
+
+        let size = ui.available_size().max(vec2(240.0, 240.0));
+        let (rect, response) = ui.allocate_exact_size(size, Sense::drag());
+        if response.dragged() {
+            let delta = response.drag_delta() * 0.01;
+            self.yaw += delta.x;
+            self.pitch = (self.pitch + delta.y).clamp(-1.5, 1.5);
+        }
+        if response.hovered() {
+            let scroll = ui.input(|i| i.smooth_scroll_delta.y);
+            self.zoom = (self.zoom * (scroll * 0.002).exp()).clamp(0.3, 5.0);
+        }
+
+        let painter = ui.painter_at(rect);
+        painter.rect_filled(rect, 0.0, Color32::from_gray(32));
+        let projection = Projection::new(rect, self.yaw, self.pitch, self.zoom);
+        self.paint_frame(&painter, &projection);
+        self.paint_points(&painter, &projection);
+    }
+
+    /// Пересчитать координаты точек; цвета точек переводятся в sRGB, как картинка
+    fn build(&mut self) {
+        let step = (self.samples.len() / 3).div_ceil(self.max_points).max(1);
+        let display = viewer::display_rgb(&self.samples, self.rgb_space);
+        self.points = self
+            .samples
+            .chunks_exact(3)
+            .zip(display.chunks_exact(3))
+            .step_by(step)
+            .map(|(p, c)| {
+                (
+                    self.space.position(p[0], p[1], p[2], self.rgb_space),
+                    Color32::from_rgb(c[0], c[1], c[2]),
+                )
+            })
+            .collect();
+        self.built_for = Some((self.space, self.rgb_space, self.max_points));
+    }
+
+    /// Точки от дальних к ближним, одним мешем
+    fn paint_points(&self, painter: &Painter, projection: &Projection) {
+        let mut projected: Vec<(Pos2, f32, Color32)> = self
+            .points
+            .iter()
+            .map(|&(p, color)| {
+                let (pos, depth) = projection.project(p);
+                (pos, depth, color)
+            })
+            .collect();
+        projected.sort_by(|a, b| a.1.total_cmp(&b.1));
+
+        let mut mesh = Mesh::default();
+        let half = Vec2::splat(self.point_size / 2.0);
+        for (pos, _, color) in projected {
+            mesh.add_colored_rect(Rect::from_min_max(pos - half, pos + half), color);
+        }
+        painter.add(mesh);
+    }
+
+    /// Каркас пространства: рёбра куба, конус или оси Lab
+    fn paint_frame(&self, painter: &Painter, projection: &Projection) {
+        let stroke = Stroke::new(1.0, Color32::from_gray(140));
+        let line = |a: [f32; 3], b: [f32; 3]| {
+            painter.line_segment([projection.project(a).0, projection.project(b).0], stroke);
+        };
+        let label = |p: [f32; 3], text: &str| {
+            painter.text(
+                projection.project(p).0,
+                Align2::CENTER_CENTER,
+                text,
+                FontId::proportional(12.0),
+                Color32::from_gray(220),
+            );
+        };
+
+        match self.space {
+            CloudSpace::Rgb => {
+                for a in 0..8u8 {
+                    for axis in 0..3 {
+                        let b = a | (1 << axis);
+                        if b != a {
+                            line(cube_corner(a), cube_corner(b));
+                        }
+                    }
+                }
+                label([0.6, -0.5, -0.5], "R");
+                label([-0.5, 0.6, -0.5], "G");
+                label([-0.5, -0.5, 0.6], "B");
+            }
+            CloudSpace::HsvCone => {
+                let circle: Vec<[f32; 3]> = (0..=48)
+                    .map(|i| {
+                        let angle = i as f32 / 48.0 * std::f32::consts::TAU;
+                        [0.5 * angle.cos(), 0.5, 0.5 * angle.sin()]
+                    })
+                    .collect();
+                for pair in circle.windows(2) {
+                    line(pair[0], pair[1]);
+                }
+                for p in circle.iter().step_by(12) {
+                    line([0.0, -0.5, 0.0], *p);
+                }
+                line([0.0, -0.5, 0.0], [0.0, 0.5, 0.0]);
+                label([0.6, 0.5, 0.0], "H 0°");
+                label([0.0, 0.6, 0.0], "V");
+            }
+            CloudSpace::Lab => {
+                line([-0.5, 0.0, 0.0], [0.5, 0.0, 0.0]);
+                line([0.0, 0.0, -0.5], [0.0, 0.0, 0.5]);
+                line([0.0, -0.5, 0.0], [0.0, 0.5, 0.0]);
+                label([0.6, 0.0, 0.0], "+a");
+                label([-0.6, 0.0, 0.0], "−a");
+                label([0.0, 0.0, 0.6], "+b");
+                label([0.0, 0.0, -0.6], "−b");
+                label([0.0, 0.6, 0.0], "L");
+            }
+        }
+    }
+}
+
+/// Вершина куба [-0.5, 0.5] по битам номера: 1 - x, 2 - y, 4 - z
+fn cube_corner(i: u8) -> [f32; 3] {
+    let c = |bit: u8| if i & bit != 0 { 0.5 } else { -0.5 };
+    [c(1), c(2), c(4)]
+}
+
+/// Ортографическая проекция с поворотом на экран
+struct Projection {
+    center: Pos2,
+    scale: f32,
+    yaw: (f32, f32),
+    pitch: (f32, f32),
+}
+
+impl Projection {
+    fn new(rect: Rect, yaw: f32, pitch: f32, zoom: f32) -> Self {
+        Self {
+            center: rect.center(),
+            scale: rect.width().min(rect.height()) * 0.6 * zoom,
+            yaw: yaw.sin_cos(),
+            pitch: pitch.sin_cos(),
+        }
+    }
+
+    /// Точка на экране и её глубина (больше - ближе к зрителю)
+    fn project(&self, [x, y, z]: [f32; 3]) -> (Pos2, f32) {
+        let (sin_yaw, cos_yaw) = self.yaw;
+        let (sin_pitch, cos_pitch) = self.pitch;
+        let x1 = x * cos_yaw + z * sin_yaw;
+        let z1 = -x * sin_yaw + z * cos_yaw;
+        let y2 = y * cos_pitch - z1 * sin_pitch;
+        let z2 = y * sin_pitch + z1 * cos_pitch;
+        (self.center + vec2(x1, -y2) * self.scale, z2)
+    }
+}
@@ -0,0 +1,73 @@
+use std::path::{Path, PathBuf};
+
+use eframe::egui;
+
+/// Сколько последних файлов помнит меню
+const MAX_RECENT: usize = 10;
+
+/// Ключ списка в хранилище eframe
+const STORAGE_KEY: &str = "recent_files";
+
+/// Недавно открытые файлы, сохраняются между запусками
+#[derive(Default)]
+pub struct RecentFiles {
+    /// Последний открытый первым
+    paths: Vec<PathBuf>,
+}
+
+impl RecentFiles {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Список из хранилища eframe, пустой при первом запуске
+    pub fn load(storage: Option<&dyn eframe::Storage>) -> Self {
+        Self {
+            paths: storage
+                .and_then(|s| eframe::get_value(s, STORAGE_KEY))
+                .unwrap_or_default(),
+        }
+    }
+
+    pub fn save(&self, storage: &mut dyn eframe::Storage) {
+        eframe::set_value(storage, STORAGE_KEY, &self.paths);
+    }
+
+    /// Поднять файл в начало списка
+    pub fn add(&mut self, path: &Path) {
+        self.remove(path);
+        self.paths.insert(0, path.to_path_buf());
+        self.paths.truncate(MAX_RECENT);
+    }
+
+    pub fn remove(&mut self, path: &Path) {
+        self.paths.retain(|p| p != path);
+    }
+
+    /// Подменю со списком, возвращает выбранный файл
+    pub fn ui(&mut self, ui: &mut egui::Ui) -> Option<PathBuf> {
+        if self.paths.is_empty() {
+            ui.label("No recent files");
+            return None;
+        }
+        let mut picked = None;
+        for path in &self.paths {
+            let name = path.file_name().map_or_else(
+                || path.display().to_string(),
+                |n| n.to_string_lossy().into(),
+            );
+            if ui
+                .button(name)
+                .on_hover_text(path.display().to_string())
+                .clicked()
+            {
+                picked = Some(path.clone());
+            }
+        }
+        ui.separator();
+        if ui.button("Clear list").clicked() {
+            self.paths.clear();
+        }
+        picked
+    }
+}
@@ -0,0 +1,318 @@
+use egui::*;
+
+/// Наибольшее число столбцов осциллограммы
+const MAX_WAVEFORM_COLUMNS: usize = 512;
+
+/// Угол линии телесных тонов на векторскопе, градусы от оси +Cb
+const SKIN_TONE_ANGLE: f32 = 123.0;
+
+/// Амплитуда меток цветов на векторскопе, как у полос 75%
+const TARGET_AMPLITUDE: f32 = 0.75;
+
+/// Что выводится в нижней панели; гистограммы рисует RGBHistogram
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum ScopeKind {
+    #[default]
+    Histogram,
+    Joint,
+    Vectorscope,
+    Waveform,
+    Parade,
+}
+
+impl ScopeKind {
+    pub const ALL: [ScopeKind; 5] = [
+        ScopeKind::Histogram,
+        ScopeKind::Joint,
+        ScopeKind::Vectorscope,
+        ScopeKind::Waveform,
+        ScopeKind::Parade,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ScopeKind::Histogram => "Histogram",
+            ScopeKind::Joint => "2D histogram",
+            ScopeKind::Vectorscope => "Vectorscope",
+            ScopeKind::Waveform => "Waveform",
+            ScopeKind::Parade => "RGB parade",
+        }
+    }
+}
+
+/// Мониторы как в видеомонтаже: векторскоп, осциллограмма яркости и RGB парад.
+/// Строятся по требованию, как гистограмма.
+#[derive(Default)]
+pub struct Scopes {
+    data: Vec<u8>, // RGB buffer
+    size: (usize, usize),
+    /// Построенные текстуры, сбрасываются при смене картинки
+    vectorscope: Option<TextureHandle>,
+    waveform: Option<TextureHandle>,
+    parade: Option<TextureHandle>,
+}
+
+impl Scopes {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Обновить мониторы под новую картинку
+    pub fn update_data(&mut self, data: Vec<u8>, size: (usize, usize)) {
+        self.data = data;
+        self.size = size;
+        self.vectorscope = None;
+        self.waveform = None;
+        self.parade = None;
+    }
+
+    /// Нарисовать монитор kind на всё доступное место
+    pub fn show(&mut self, ui: &mut Ui, kind: ScopeKind) {
+        let (rect, _) = ui.allocate_exact_size(ui.available_size(), Sense::hover());
+        let painter = ui.painter_at(rect);
+        painter.rect_filled(rect, 0.0, Color32::from_gray(16));
+        if self.data.is_empty() || self.size.0 == 0 {
+            return;
+        }
+
+        match kind {
+            ScopeKind::Histogram | ScopeKind::Joint => {}
+            ScopeKind::Vectorscope => {
+                let texture = self
+                    .vectorscope
+                    .get_or_insert_with(|| vectorscope_texture(ui.ctx(), &self.data));
+                let side = rect.width().min(rect.height());
+                let square = Rect::from_center_size(rect.center(), vec2(side, side));
+                paint_texture(&painter, texture, square);
+                paint_vectorscope_graticule(&painter, square);
+            }
+            ScopeKind::Waveform => {
+                let texture = self
+                    .waveform
+                    .get_or_insert_with(|| waveform_texture(ui.ctx(), &self.data, self.size));
+                paint_texture(&painter, texture, rect);
+                paint_level_graticule(&painter, rect);
+            }
+            ScopeKind::Parade => {
+                let texture = self
+                    .parade
+                    .get_or_insert_with(|| parade_texture(ui.ctx(), &self.data, self.size));
+                paint_texture(&painter, texture, rect);
+                paint_level_graticule(&painter, rect);
+                for i in 1..3 {
+                    let x = rect.left() + rect.width() * i as f32 / 3.0;
+                    painter.vline(x, rect.y_range(), Stroke::new(1.0, Color32::from_gray(90)));
+                }
+            }
+        }
+    }
+}
+
+/// Цветоразностные составляющие BT.601, [-0.5, 0.5]
+fn rgb_to_cb_cr(r: f32, g: f32, b: f32) -> (f32, f32) {
+    let cb = -0.168736 * r - 0.331264 * g + 0.5 * b;
+    let cr = 0.5 * r - 0.418688 * g - 0.081312 * b;
+    (cb, cr)
+}
+
+/// Яркость BT.601 [0, 255]
+fn luma(r: u8, g: u8, b: u8) -> u8 {
+    (0.299 * r as f32 + 0.587 * g as f32 + 0.114 * b as f32).round() as u8
+}
+
+/// Векторскоп: по горизонтали Cb, по вертикали Cr; точки окрашены цветом пикселя
+fn vectorscope_texture(ctx: &Context, data: &[u8]) -> TextureHandle {
+    const SIDE: usize = 256;
+    let mut counts = vec![0u32; SIDE * SIDE];
+    let mut colors = vec![[0u64; 3]; SIDE * SIDE];
+    for p in data.chunks_exact(3) {
+        let (cb, cr) = rgb_to_cb_cr(
+            p[0] as f32 / 255.0,
+            p[1] as f32 / 255.0,
+            p[2] as f32 / 255.0,
+        );
+        let col = (((cb + 0.5) * SIDE as f32) as usize).min(SIDE - 1);
+        let row = (((0.5 - cr) * SIDE as f32) as usize).min(SIDE - 1);
+        let i = row * SIDE + col;
+        counts[i] += 1;
+        for c in 0..3 {
+            colors[i][c] += p[c] as u64;
+        }
+    }
+
+    let max = counts.iter().copied().max().unwrap_or(0).max(1) as f32;
+    let pixels = counts
+        .iter()
+        .zip(&colors)
+        .map(|(&count, sum)| {
+            if count == 0 {
+                return Color32::TRANSPARENT;
+            }
+            // средний цвет ячейки, яркость по плотности
+            let t = 0.3 + 0.7 * log_density(count, max);
+            let mean = sum.map(|s| s as f32 / count as f32);
+            let peak = mean.iter().copied().fold(1.0, f32::max);
+            let [r, g, b] = mean.map(|c| (c / peak * 255.0 * t) as u8);
+            Color32::from_rgb(r, g, b)
+        })
+        .collect();
+    ctx.load_texture(
+        "vectorscope",
+        ColorImage::new([SIDE, SIDE], pixels),
+        TextureOptions::LINEAR,
+    )
+}
+
+/// Осциллограмма яркости: по горизонтали столбцы картинки, по вертикали яркость
+fn waveform_texture(ctx: &Context, data: &[u8], size: (usize, usize)) -> TextureHandle {
+    let columns = size.0.min(MAX_WAVEFORM_COLUMNS);
+    let counts = level_counts(data, size, columns, |p| luma(p[0], p[1], p[2]));
+    let pixels = level_pixels(&counts, columns, Color32::from_rgb(140, 255, 140));
+    ctx.load_texture(
+        "waveform",
+        ColorImage::new([columns, 256], pixels),
+        TextureOptions::LINEAR,
+    )
+}
+
+/// RGB парад: осциллограммы каналов R, G, B рядом
+fn parade_texture(ctx: &Context, data: &[u8], size: (usize, usize)) -> TextureHandle {
+    let columns = size.0.min(MAX_WAVEFORM_COLUMNS / 2);
+    let channels = [
+        (0, Color32::from_rgb(255, 80, 80)),
+        (1, Color32::from_rgb(80, 255, 80)),
+        (2, Color32::from_rgb(100, 140, 255)),
+    ]
+    .map(|(c, color)| {
+        let counts = level_counts(data, size, columns, |p| p[c]);
+        level_pixels(&counts, columns, color)
+    });
+
+    // склеить три осциллограммы построчно
+    let mut pixels = Vec::with_capacity(columns * 3 * 256);
+    for row in 0..256 {
+        for channel in &channels {
+            pixels.extend_from_slice(&channel[row * columns..(row + 1) * columns]);
+        }
+    }
+    ctx.load_texture(
+        "rgb_parade",
+        ColorImage::new([columns * 3, 256], pixels),
+        TextureOptions::LINEAR,
+    )
+}
+
+/// Сколько пикселей каждого уровня в каждом столбце; первая строка - уровень 255
+fn level_counts(
+    data: &[u8],
+    size: (usize, usize),
+    columns: usize,
+    level: impl Fn(&[u8]) -> u8,
+) -> Vec<u32> {
+    let mut counts = vec![0u32; columns * 256];
+    for (i, p) in data.chunks_exact(3).enumerate() {
+        let col = (i % size.0) * columns / size.0;
+        let row = 255 - level(p) as usize;
+        counts[row * columns + col] += 1;
+    }
+    counts
+}
+
+/// Текстура осциллограммы из количеств, цвет color с яркостью по плотности
+fn level_pixels(counts: &[u32], columns: usize, color: Color32) -> Vec<Color32> {
+    // нормировка по столбцу, чтобы узкие картинки не выглядели тусклыми
+    let mut column_max = vec![1u32; columns];
+    for (i, &count) in counts.iter().enumerate() {
+        let col = i % columns;
+        column_max[col] = column_max[col].max(count);
+    }
+    counts
+        .iter()
+        .enumerate()
+        .map(|(i, &count)| {
+            if count == 0 {
+                return Color32::TRANSPARENT;
+            }
+            let t = 0.25 + 0.75 * log_density(count, column_max[i % columns] as f32);
+            color.gamma_multiply(t)
+        })
+        .collect()
+}
+
+/// Плотность в [0, 1] по логарифмической шкале
+fn log_density(count: u32, max: f32) -> f32 {
+    (1.0 + count as f32).ln() / (1.0 + max).ln()
+}
+
+fn paint_texture(painter: &Painter, texture: &TextureHandle, rect: Rect) {
+    painter.image(
+        texture.id(),
+        rect,
+        Rect::from_min_max(pos2(0.0, 0.0), pos2(1.0, 1.0)),
+        Color32::WHITE,
+    );
+}
+
+/// Горизонтальные линии уровней 0, 25, 50, 75, 100%
+fn paint_level_graticule(painter: &Painter, rect: Rect) {
+    let stroke = Stroke::new(1.0, Color32::from_gray(70));
+    for percent in [0, 25, 50, 75, 100] {
+        let y = rect.bottom() - rect.height() * percent as f32 / 100.0;
+        painter.hline(rect.x_range(), y, stroke);
+        painter.text(
+            pos2(rect.left() + 2.0, y),
+            if percent == 100 {
+                Align2::LEFT_TOP
+            } else {
+                Align2::LEFT_BOTTOM
+            },
+            format!("{percent}"),
+            FontId::proportional(10.0),
+            Color32::from_gray(140),
+        );
+    }
+}
+
+/// Сетка векторскопа: окружность, оси, метки цветов и линия телесных тонов
+fn paint_vectorscope_graticule(painter: &Painter, square: Rect) {
+    let center = square.center();
+    let scale = square.width();
+    let stroke = Stroke::new(1.0, Color32::from_gray(80));
+    let to_screen = |cb: f32, cr: f32| center + vec2(cb, -cr) * scale;
+
+    painter.circle_stroke(center, scale / 2.0, stroke);
+    painter.hline(square.x_range(), center.y, stroke);
+    painter.vline(center.x, square.y_range(), stroke);
+
+    let angle = SKIN_TONE_ANGLE.to_radians();
+    painter.line_segment(
+        [center, to_screen(angle.cos() * 0.5, angle.sin() * 0.5)],
+        Stroke::new(1.0, Color32::from_rgb(230, 170, 130)),
+    );
+
+    for (name, [r, g, b]) in [
+        ("R", [1.0, 0.0, 0.0]),
+        ("Yl", [1.0, 1.0, 0.0]),
+        ("G", [0.0, 1.0, 0.0]),
+        ("Cy", [0.0, 1.0, 1.0]),
+        ("B", [0.0, 0.0, 1.0]),
+        ("Mg", [1.0, 0.0, 1.0]),
+    ] {
+        let (cb, cr) = rgb_to_cb_cr(r, g, b);
+        let pos = to_screen(cb * TARGET_AMPLITUDE, cr * TARGET_AMPLITUDE);
+        let color = Color32::from_rgb((r * 200.0) as u8, (g * 200.0) as u8, (b * 200.0) as u8);
+        painter.rect_stroke(
+            Rect::from_center_size(pos, vec2(10.0, 10.0)),
+            0.0,
+            Stroke::new(1.0, color),
+            StrokeKind::Middle,
+        );
+        painter.text(
+            pos + vec2(8.0, -8.0),
+            Align2::LEFT_BOTTOM,
+            name,
+            FontId::proportional(11.0),
+            color,
+        );
+    }
+}
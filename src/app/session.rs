@@ -0,0 +1,123 @@
+use std::path::{Path, PathBuf};
+
+use super::color_space::RgbSpace;
+use super::error::AppError;
+use super::history::History;
+use super::image_op;
+
+/// Ключ сеанса в хранилище eframe
+const STORAGE_KEY: &str = "session";
+
+/// Запись истории, пиксели лежат в PNG в папке сеанса
+#[derive(serde::Serialize, serde::Deserialize)]
+struct SavedEntry {
+    name: String,
+    space: RgbSpace,
+    file: String,
+}
+
+/// Открытая картинка и история операций прошлого сеанса
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct Session {
+    pub image: PathBuf,
+    entries: Vec<SavedEntry>,
+}
+
+/// Папка с пикселями истории рядом с хранилищем eframe
+fn session_dir() -> Option<PathBuf> {
+    eframe::storage_dir(super::APP_NAME).map(|dir| dir.join("session"))
+}
+
+impl Session {
+    /// Сеанс из хранилища eframe, None - сохранять было нечего
+    pub fn load(storage: Option<&dyn eframe::Storage>) -> Option<Self> {
+        storage.and_then(|s| eframe::get_value::<Option<Self>>(s, STORAGE_KEY))?
+    }
+
+    /// Записать сеанс в хранилище, None забывает прошлый сеанс
+    pub fn store(session: Option<&Self>, storage: &mut dyn eframe::Storage) {
+        eframe::set_value(storage, STORAGE_KEY, &session);
+    }
+
+    /// Записать пиксели истории в папку сеанса.
+    /// None, если у хранилища нет папки.
+    pub fn capture(image: &Path, history: &History) -> Result<Option<Self>, AppError> {
+        let Some(dir) = session_dir() else {
+            return Ok(None);
+        };
+        // файлы прошлого сеанса не нужны
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).map_err(|e| AppError::write(&dir, e))?;
+
+        let mut entries = Vec::with_capacity(history.len());
+        for index in 0..history.len() {
+            let Some(entry) = history.get(index) else {
+                continue;
+            };
+            let file = format!("{index}.png");
+            let path = dir.join(&file);
+            let (w, h) = (entry.size.0 as u32, entry.size.1 as u32);
+            let result = match &entry.alpha {
+                Some(alpha) => {
+                    let rgba = image_op::merge_alpha(&entry.image, alpha)?;
+                    image::save_buffer(&path, &rgba, w, h, image::ExtendedColorType::Rgba8)
+                }
+                None => {
+                    image::save_buffer(&path, &entry.image, w, h, image::ExtendedColorType::Rgb8)
+                }
+            };
+            result.map_err(|e| match e {
+                image::ImageError::IoError(e) => AppError::write(&path, e),
+                e => AppError::encode("PNG", e),
+            })?;
+            entries.push(SavedEntry {
+                name: entry.name.clone(),
+                space: entry.space,
+                file,
+            });
+        }
+        Ok(Some(Self {
+            image: image.to_path_buf(),
+            entries,
+        }))
+    }
+
+    /// Вернуть записи в историю заново открытой картинки размера size.
+    /// Результаты операций могут быть другого размера, например разность после совмещения.
+    pub fn restore_into(
+        &self,
+        history: &mut History,
+        size: (usize, usize),
+    ) -> Result<(), AppError> {
+        let dir = session_dir().unwrap_or_default();
+        let mut restored = Vec::with_capacity(self.entries.len());
+        for (index, entry) in self.entries.iter().enumerate() {
+            let path = dir.join(&entry.file);
+            let image = image::open(&path).map_err(|e| AppError::open(&path, e))?;
+            let entry_size = (image.width() as usize, image.height() as usize);
+            // файл картинки могли изменить после прошлого сеанса
+            if index == 0 && entry_size != size {
+                return Err(AppError::SizeMismatch {
+                    first: size.0 * size.1,
+                    second: entry_size.0 * entry_size.1,
+                });
+            }
+            let (rgb, alpha) = if image.color().has_alpha() {
+                let (rgb, alpha) = image_op::split_alpha(image.to_rgba8().as_raw());
+                (rgb, Some(alpha))
+            } else {
+                (image.to_rgb8().into_raw(), None)
+            };
+            restored.push((entry, rgb, alpha, entry_size));
+        }
+
+        for (index, (entry, rgb, alpha, size)) in restored.into_iter().enumerate() {
+            if index == 0 {
+                history.reset(rgb, alpha, size, entry.space);
+            } else {
+                history.push(&entry.name, rgb, alpha, size, entry.space);
+            }
+        }
+        Ok(())
+    }
+}
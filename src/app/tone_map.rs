@@ -0,0 +1,433 @@
+use egui::*;
+use egui_plot::{Bar, BarChart, Plot, VLine};
+
+use super::color_space::{GamutMapping, RgbSpace, map_linear_gamut};
+use super::image_op;
+
+/// Пределы гистограммы яркости, ступени экспозиции (log2)
+const MIN_STOP: f32 = -16.0;
+const MAX_STOP: f32 = 16.0;
+/// Столбцов гистограммы на одну ступень
+const BINS_PER_STOP: f32 = 4.0;
+
+/// Наибольшая сторона карты яркости для локального оператора Рейнхарда
+const LOCAL_MAP_SIZE: usize = 256;
+
+/// Оператор тональной компрессии
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ToneOperator {
+    /// Только экспозиция и гамма
+    ExposureGamma,
+    #[default]
+    ReinhardGlobal,
+    ReinhardLocal,
+    /// Приближение ACES filmic (Narkowicz)
+    Aces,
+    /// Hable / Uncharted 2
+    Hable,
+}
+
+impl ToneOperator {
+    pub const ALL: [ToneOperator; 5] = [
+        ToneOperator::ExposureGamma,
+        ToneOperator::ReinhardGlobal,
+        ToneOperator::ReinhardLocal,
+        ToneOperator::Aces,
+        ToneOperator::Hable,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ToneOperator::ExposureGamma => "Exposure + gamma",
+            ToneOperator::ReinhardGlobal => "Reinhard global",
+            ToneOperator::ReinhardLocal => "Reinhard local",
+            ToneOperator::Aces => "ACES filmic",
+            ToneOperator::Hable => "Hable",
+        }
+    }
+}
+
+/// Параметры тональной компрессии
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ToneSettings {
+    pub operator: ToneOperator,
+    /// Экспозиция, ступени
+    pub exposure: f32,
+    /// Линейная яркость, которая становится белой
+    pub white: f32,
+    /// Гамма для оператора ExposureGamma
+    pub gamma: f32,
+    /// Радиус адаптации локального оператора, доля большей стороны картинки
+    pub local_radius: f32,
+}
+
+impl Default for ToneSettings {
+    fn default() -> Self {
+        Self {
+            operator: ToneOperator::default(),
+            exposure: 0.0,
+            white: 4.0,
+            gamma: 2.2,
+            local_radius: 0.02,
+        }
+    }
+}
+
+/// Задание тональной компрессии: параметры и гистограмма яркости исходника
+#[derive(Default, Clone)]
+pub struct ToneMapper {
+    pub settings: ToneSettings,
+    /// Рабочее пространство: яркость и передаточная функция результата
+    pub space: RgbSpace,
+    /// Гистограмма log2 яркости: (ступень, количество)
+    histogram: Vec<(f32, u32)>,
+    /// Доля пикселей с нулевой яркостью
+    black_fraction: f32,
+}
+
+impl ToneMapper {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Посчитать гистограмму яркости линейного RGB буфера
+    pub fn set_image(&mut self, linear: &[f32]) {
+        let bins = ((MAX_STOP - MIN_STOP) * BINS_PER_STOP) as usize;
+        let mut counts = vec![0u32; bins];
+        let mut black = 0;
+        for p in linear.chunks_exact(3) {
+            let l = self.space.luminance([p[0], p[1], p[2]]);
+            if l <= 0.0 {
+                black += 1;
+                continue;
+            }
+            let bin = ((l.log2() - MIN_STOP) * BINS_PER_STOP).floor();
+            counts[(bin.max(0.0) as usize).min(bins - 1)] += 1;
+        }
+        self.histogram = counts
+            .into_iter()
+            .enumerate()
+            .map(|(i, count)| (MIN_STOP + (i as f32 + 0.5) / BINS_PER_STOP, count))
+            .collect();
+        self.black_fraction = black as f32 / (linear.len() / 3).max(1) as f32;
+    }
+
+    /// Элементы управления; true, если параметры изменились
+    pub fn ui(&mut self, ui: &mut Ui) -> bool {
+        let before = self.settings;
+        let settings = &mut self.settings;
+
+        ComboBox::from_id_salt("tone_operator")
+            .selected_text(settings.operator.name())
+            .show_ui(ui, |ui| {
+                for operator in ToneOperator::ALL {
+                    ui.selectable_value(&mut settings.operator, operator, operator.name());
+                }
+            });
+        ui.add(Slider::new(&mut settings.exposure, -10.0..=10.0).text("exposure, EV"));
+        match settings.operator {
+            ToneOperator::ExposureGamma => {
+                ui.add(Slider::new(&mut settings.gamma, 1.0..=3.0).text("gamma"));
+            }
+            ToneOperator::Aces => {}
+            _ => {
+                ui.add(
+                    Slider::new(&mut settings.white, 1.0..=64.0)
+                        .logarithmic(true)
+                        .text("white"),
+                );
+            }
+        }
+        if settings.operator == ToneOperator::ReinhardLocal {
+            ui.add(
+                Slider::new(&mut settings.local_radius, 0.005..=0.1)
+                    .logarithmic(true)
+                    .text("radius"),
+            );
+        }
+        if ui.button("Reset").clicked() {
+            *settings = ToneSettings {
+                operator: settings.operator,
+                ..ToneSettings::default()
+            };
+        }
+
+        ui.separator();
+        ui.label("Яркость, ступени (log2)");
+        self.histogram_ui(ui);
+        if self.black_fraction > 0.0 {
+            ui.label(format!(
+                "Чёрных пикселей: {:.1}%",
+                self.black_fraction * 100.0
+            ));
+        }
+
+        self.settings != before
+    }
+
+    /// Гистограмма log2 яркости с отметками средне-серого и белой точки
+    fn histogram_ui(&self, ui: &mut Ui) {
+        let bars: Vec<Bar> = self
+            .histogram
+            .iter()
+            .map(|&(stop, count)| {
+                Bar::new(stop as f64, count as f64)
+                    .width(1.0 / BINS_PER_STOP as f64)
+                    .name(format!("{stop:+.2} EV"))
+            })
+            .collect();
+        let exposure = self.settings.exposure as f64;
+
+        Plot::new("tone_histogram")
+            .height(140.0)
+            .show_axes([true, false])
+            .show_grid([true, false])
+            .allow_drag(false)
+            .allow_zoom(false)
+            .allow_scroll(false)
+            .show(ui, |plot_ui| {
+                plot_ui.bar_chart(BarChart::new("luminance", bars).color(Color32::GRAY));
+                // яркость, которая после экспозиции станет средне-серой (18%)
+                plot_ui.vline(
+                    VLine::new("middle gray", 0.18f64.log2() - exposure)
+                        .color(Color32::from_rgb(60, 140, 60)),
+                );
+                if !matches!(
+                    self.settings.operator,
+                    ToneOperator::ExposureGamma | ToneOperator::Aces
+                ) {
+                    plot_ui.vline(
+                        VLine::new("white", (self.settings.white as f64).log2() - exposure)
+                            .color(Color32::from_rgb(200, 60, 60)),
+                    );
+                }
+            });
+    }
+
+    /// Яркость окружения каждого пикселя для локального оператора, для остальных пусто.
+    /// Считается по всей картинке один раз перед [`Self::map_chunk`].
+    pub fn adaptation(&self, linear: &[f32], size: (usize, usize)) -> Vec<f32> {
+        let s = &self.settings;
+        if s.operator != ToneOperator::ReinhardLocal {
+            return Vec::new();
+        }
+        local_adaptation(linear, size, self.space, s.exposure.exp2(), s.local_radius)
+    }
+
+    /// Тональная компрессия куска линейного RGB буфера на месте, в кодированные значения
+    /// рабочего пространства. first - номер первого пикселя куска в картинке,
+    /// adaptation - карта из [`Self::adaptation`].
+    /// Цвета вне охвата приводятся способом mapping, возвращается маска таких пикселей.
+    pub fn map_chunk(
+        &self,
+        chunk: &mut [f32],
+        first: usize,
+        adaptation: &[f32],
+        mapping: GamutMapping,
+    ) -> Vec<bool> {
+        let s = &self.settings;
+        let scale = s.exposure.exp2();
+        match s.operator {
+            ToneOperator::ExposureGamma => chunk.iter_mut().for_each(|c| *c *= scale),
+            ToneOperator::ReinhardGlobal => {
+                let white2 = s.white * s.white;
+                map_luminance(chunk, self.space, scale, |l, _| {
+                    l * (1.0 + l / white2) / (1.0 + l)
+                });
+            }
+            ToneOperator::ReinhardLocal => {
+                let white2 = s.white * s.white;
+                map_luminance(chunk, self.space, scale, |l, i| {
+                    l * (1.0 + l / white2) / (1.0 + adaptation[first + i])
+                });
+            }
+            ToneOperator::Aces => chunk.iter_mut().for_each(|c| *c = aces(*c * scale)),
+            ToneOperator::Hable => {
+                let norm = hable(s.white);
+                chunk.iter_mut().for_each(|c| *c = hable(*c * scale) / norm);
+            }
+        }
+        let out_of_gamut = map_linear_gamut(chunk, self.space, mapping);
+        if s.operator == ToneOperator::ExposureGamma {
+            // гамма применяется вместо кодирования пространства
+            let inv_gamma = 1.0 / s.gamma;
+            chunk.iter_mut().for_each(|c| *c = c.powf(inv_gamma));
+        } else {
+            chunk
+                .iter_mut()
+                .for_each(|c| *c = self.space.from_linear(*c));
+        }
+        out_of_gamut
+    }
+}
+
+/// Линейный RGB буфер для тональной компрессии.
+/// Целочисленные картинки хранятся гамма-кодированными в рабочем пространстве
+/// и переводятся в линейные, картинки с плавающей точкой (EXR, HDR) уже линейны.
+pub fn linear_buffer(buf: &[f32], already_linear: bool, space: RgbSpace) -> Vec<f32> {
+    if already_linear {
+        buf.to_vec()
+    } else {
+        buf.iter().map(|&c| space.to_linear(c)).collect()
+    }
+}
+
+/// Сжать яркость пикселей на месте функцией f(яркость, номер пикселя), сохранив цветность
+fn map_luminance(linear: &mut [f32], space: RgbSpace, scale: f32, f: impl Fn(f32, usize) -> f32) {
+    for (i, p) in linear.chunks_exact_mut(3).enumerate() {
+        let l = space.luminance([p[0], p[1], p[2]]) * scale;
+        let ratio = if l > 0.0 { f(l, i) / l * scale } else { 0.0 };
+        p.iter_mut().for_each(|c| *c *= ratio);
+    }
+}
+
+/// Яркость окружения каждого пикселя: размытая яркость на уменьшенной карте.
+/// Размытие в логарифмах, чтобы яркие источники не засвечивали соседей.
+fn local_adaptation(
+    linear: &[f32],
+    size: (usize, usize),
+    space: RgbSpace,
+    scale: f32,
+    radius: f32,
+) -> Vec<f32> {
+    let (w, h) = size;
+    let factor = w.max(h).div_ceil(LOCAL_MAP_SIZE).max(1);
+    let (mw, mh) = (w.div_ceil(factor), h.div_ceil(factor));
+
+    // средний логарифм яркости в блоках factor x factor
+    let mut sums = vec![0.0f32; mw * mh];
+    let mut counts = vec![0u32; mw * mh];
+    for (i, p) in linear.chunks_exact(3).enumerate() {
+        let (x, y) = (i % w / factor, i / w / factor);
+        let l = space.luminance([p[0], p[1], p[2]]) * scale;
+        sums[y * mw + x] += (l.max(1e-6)).ln();
+        counts[y * mw + x] += 1;
+    }
+    let map: Vec<f32> = sums
+        .iter()
+        .zip(&counts)
+        .map(|(s, &c)| s / c.max(1) as f32)
+        .collect();
+
+    let sigma = (radius * w.max(h) as f32 / factor as f32).max(0.5);
+    let blurred = image_op::blur_plane(&map, mw, mh, sigma);
+    (0..w * h)
+        .map(|i| blurred[(i / w / factor) * mw + i % w / factor].exp())
+        .collect()
+}
+
+/// Приближение ACES filmic, Narkowicz 2015
+fn aces(x: f32) -> f32 {
+    let x = x.max(0.0);
+    (x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14)
+}
+
+/// Кривая Hable (Uncharted 2)
+fn hable(x: f32) -> f32 {
+    const A: f32 = 0.15;
+    const B: f32 = 0.50;
+    const C: f32 = 0.10;
+    const D: f32 = 0.20;
+    const E: f32 = 0.02;
+    const F: f32 = 0.30;
+    let x = x.max(0.0);
+    ((x * (A * x + C * B) + D * E) / (x * (A * x + B) + D * F)) - E / F
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Серые пиксели с линейной яркостью values
+    fn gray(values: &[f32]) -> Vec<f32> {
+        values.iter().flat_map(|&v| [v, v, v]).collect()
+    }
+
+    /// Тональная компрессия всего буфера оператором operator со значениями по умолчанию
+    fn map(operator: ToneOperator, linear: &[f32]) -> Vec<f32> {
+        let mut mapper = ToneMapper::new();
+        mapper.settings.operator = operator;
+        let size = (linear.len() / 3, 1);
+        let adaptation = mapper.adaptation(linear, size);
+        let mut buf = linear.to_vec();
+        mapper.map_chunk(&mut buf, 0, &adaptation, GamutMapping::Clip);
+        buf
+    }
+
+    #[test]
+    fn black_stays_black() {
+        for operator in ToneOperator::ALL {
+            let out = map(operator, &gray(&[0.0, 0.0]));
+            assert!(out.iter().all(|&c| c.abs() < 1e-6), "{}", operator.name());
+        }
+    }
+
+    #[test]
+    fn operators_are_monotonic() {
+        let values: Vec<f32> = (0..=200).map(|i| i as f32 * 0.05).collect();
+        for operator in ToneOperator::ALL {
+            // у локального оператора окружение зависит от соседей, поэтому по одному пикселю
+            let out: Vec<f32> = values
+                .iter()
+                .map(|&v| map(operator, &gray(&[v]))[0])
+                .collect();
+            for (i, pair) in out.windows(2).enumerate() {
+                assert!(
+                    pair[1] >= pair[0],
+                    "{}: {} -> {}, {} -> {}",
+                    operator.name(),
+                    values[i],
+                    pair[0],
+                    values[i + 1],
+                    pair[1]
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn white_point_maps_to_one() {
+        let white = ToneSettings::default().white;
+        for operator in [ToneOperator::ReinhardGlobal, ToneOperator::Hable] {
+            let out = map(operator, &gray(&[white]));
+            for c in out {
+                assert!((c - 1.0).abs() < 1e-3, "{}: {c}", operator.name());
+            }
+        }
+    }
+
+    #[test]
+    fn histogram_bins_log2_stops() {
+        // яркости посередине столбцов ступеней -2, 0 и 1, чёрный и две за пределами
+        let mid = 0.5 / BINS_PER_STOP;
+        let values = [
+            (-2.0 + mid).exp2(),
+            mid.exp2(),
+            mid.exp2(),
+            (1.0 + mid).exp2(),
+            0.0,
+            1e-9,
+            1e9,
+        ];
+        let mut mapper = ToneMapper::new();
+        mapper.set_image(&gray(&values));
+
+        let bins = ((MAX_STOP - MIN_STOP) * BINS_PER_STOP) as usize;
+        assert_eq!(mapper.histogram.len(), bins);
+        let count_at = |stop: f32| {
+            let bin = ((stop - MIN_STOP) * BINS_PER_STOP) as usize;
+            let (center, count) = mapper.histogram[bin];
+            assert!((center - (stop + mid)).abs() < 1e-6, "{center}");
+            count
+        };
+        assert_eq!(count_at(-2.0), 1);
+        assert_eq!(count_at(0.0), 2);
+        assert_eq!(count_at(1.0), 1);
+        // слишком тёмные и слишком яркие попадают в крайние столбцы
+        assert_eq!(mapper.histogram[0].1, 1);
+        assert_eq!(mapper.histogram[bins - 1].1, 1);
+        let total: u32 = mapper.histogram.iter().map(|(_, count)| count).sum();
+        assert_eq!(total, 6);
+        assert!((mapper.black_fraction - 1.0 / 7.0).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,298 @@
+use egui::emath::GuiRounding as _;
+use egui::*;
+
+use super::color_space::{GamutMapping, RgbSpace, convert_buffer};
+
+/// Пределы масштаба (точек экрана на пиксель картинки)
+const MIN_ZOOM: f32 = 0.02;
+const MAX_ZOOM: f32 = 64.0;
+
+/// С какого увеличения (физических пикселей на пиксель картинки) рисуется сетка
+const GRID_MIN_ZOOM: f32 = 8.0;
+
+/// Настройки текстур для просмотра: при увеличении пиксели не размываются,
+/// при уменьшении сглаживаются.
+pub const TEXTURE_OPTIONS: TextureOptions = TextureOptions {
+    magnification: TextureFilter::Nearest,
+    minification: TextureFilter::Linear,
+    wrap_mode: TextureWrapMode::ClampToEdge,
+    mipmap_mode: Some(TextureFilter::Linear),
+};
+
+/// Размер клетки шахматного фона под прозрачными пикселями, точки экрана
+const CHECKER_SIZE: f32 = 8.0;
+
+/// Состояние просмотра картинки: масштаб и сдвиг.
+/// Одно состояние на все панели сравнения, поэтому они двигаются синхронно.
+#[derive(Default)]
+pub struct ImageView {
+    /// Масштаб, None - вписать картинку в область вывода
+    zoom: Option<f32>,
+    /// Сдвиг центра картинки относительно центра области вывода
+    pan: Vec2,
+    /// Рисовать сетку пикселей при большом увеличении
+    pub show_grid: bool,
+    /// Область вывода и размер картинки с прошлого кадра, для кнопок масштаба
+    last_layout: Option<(Rect, Vec2)>,
+    /// Пиксель картинки под курсором
+    hovered_pixel: Option<(usize, usize)>,
+    /// Пиксель, по которому щёлкнули в этом кадре
+    clicked_pixel: Option<(usize, usize)>,
+}
+
+impl ImageView {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Вписать картинку в область вывода
+    pub fn fit(&mut self) {
+        self.zoom = None;
+        self.pan = Vec2::ZERO;
+    }
+
+    /// Один пиксель картинки на один пиксель экрана
+    pub fn actual_size(&mut self, ctx: &Context) {
+        self.zoom = Some(1.0 / ctx.pixels_per_point());
+        self.pan = Vec2::ZERO;
+    }
+
+    /// Текущий масштаб для области rect и картинки размера image_size
+    pub fn zoom(&self, rect: Rect, image_size: Vec2) -> f32 {
+        self.zoom.unwrap_or_else(|| fit_zoom(rect, image_size))
+    }
+
+    /// Прямоугольник картинки на экране
+    pub fn image_rect(&self, rect: Rect, image_size: Vec2) -> Rect {
+        let zoom = self.zoom(rect, image_size);
+        Rect::from_center_size(rect.center() + self.pan, image_size * zoom)
+    }
+
+    /// Пиксель картинки под точкой экрана pos
+    pub fn pixel_at(&self, rect: Rect, image_size: Vec2, pos: Pos2) -> Option<(usize, usize)> {
+        let image_rect = self.image_rect(rect, image_size);
+        if !rect.contains(pos) || !image_rect.contains(pos) {
+            return None;
+        }
+        let p = (pos - image_rect.min) / self.zoom(rect, image_size);
+        let (x, y) = (p.x.floor() as usize, p.y.floor() as usize);
+        (x < image_size.x as usize && y < image_size.y as usize).then_some((x, y))
+    }
+
+    /// Пиксель картинки под курсором
+    pub fn hovered_pixel(&self) -> Option<(usize, usize)> {
+        self.hovered_pixel
+    }
+
+    /// Пиксель, по которому щёлкнули в этом кадре
+    pub fn clicked_pixel(&self) -> Option<(usize, usize)> {
+        self.clicked_pixel
+    }
+
+    /// Кнопки масштаба
+    pub fn toolbar_ui(&mut self, ui: &mut Ui) {
+        let ppp = ui.ctx().pixels_per_point();
+
+        if ui.button("Fit").on_hover_text("Вписать в окно").clicked() {
+            self.fit();
+        }
+        if ui
+            .button("1:1")
+            .on_hover_text("Пиксель в пиксель")
+            .clicked()
+        {
+            self.actual_size(ui.ctx());
+        }
+        if let Some((rect, image_size)) = self.last_layout {
+            if ui.button("−").clicked() {
+                self.zoom_around(rect, image_size, rect.center(), 0.5);
+            }
+            ui.label(format!("{:.0}%", self.zoom(rect, image_size) * ppp * 100.0));
+            if ui.button("+").clicked() {
+                self.zoom_around(rect, image_size, rect.center(), 2.0);
+            }
+        }
+        ui.checkbox(&mut self.show_grid, "grid");
+    }
+
+    /// Вывести текстуру на всё оставшееся место с масштабом и сдвигом.
+    /// overlay - полупрозрачная текстура того же размера поверх картинки.
+    pub fn show(
+        &mut self,
+        ui: &mut Ui,
+        texture: &TextureHandle,
+        overlay: Option<&TextureHandle>,
+    ) -> Response {
+        let (rect, response) = ui.allocate_exact_size(ui.available_size(), Sense::click_and_drag());
+        self.handle_input(ui, &response, rect, texture.size_vec2());
+        let painter = ui.painter_at(rect);
+        self.paint(&painter, rect, rect, texture);
+        if let Some(overlay) = overlay {
+            let image_rect = self.pixel_aligned_rect(&painter, rect, overlay.size_vec2());
+            painter.image(overlay.id(), image_rect, UV_FULL, Color32::WHITE);
+        }
+        response
+    }
+
+    /// Обработать колесо мыши (масштаб вокруг курсора), перетаскивание (сдвиг)
+    /// и двойной щелчок (вписать в окно)
+    pub fn handle_input(&mut self, ui: &Ui, response: &Response, rect: Rect, image_size: Vec2) {
+        self.last_layout = Some((rect, image_size));
+        self.hovered_pixel = response
+            .hover_pos()
+            .and_then(|pos| self.pixel_at(rect, image_size, pos));
+        self.clicked_pixel = self.hovered_pixel.filter(|_| response.clicked());
+
+        if response.double_clicked() {
+            self.fit();
+            return;
+        }
+
+        if response.dragged_by(PointerButton::Primary) || response.dragged_by(PointerButton::Middle)
+        {
+            self.pan += response.drag_delta();
+        }
+
+        if let Some(pointer) = response.hover_pos() {
+            let (scroll, zoom_delta) = ui.input(|i| (i.smooth_scroll_delta.y, i.zoom_delta()));
+            let factor = zoom_delta * (scroll * 0.002).exp();
+            if factor != 1.0 {
+                self.zoom_around(rect, image_size, pointer, factor);
+            }
+        }
+    }
+
+    /// Изменить масштаб в factor раз, оставив точку экрана anchor на месте
+    pub fn zoom_around(&mut self, rect: Rect, image_size: Vec2, anchor: Pos2, factor: f32) {
+        let zoom = self.zoom(rect, image_size);
+        let new_zoom = (zoom * factor).clamp(MIN_ZOOM, MAX_ZOOM);
+        let offset = anchor - rect.center() - self.pan;
+        self.pan = anchor - rect.center() - offset * (new_zoom / zoom);
+        self.zoom = Some(new_zoom);
+    }
+
+    /// Место картинки на экране; угол на границе физического пикселя,
+    /// чтобы 1:1 было без полос
+    fn pixel_aligned_rect(&self, painter: &Painter, rect: Rect, image_size: Vec2) -> Rect {
+        let image_rect = self.image_rect(rect, image_size);
+        Rect::from_min_size(
+            image_rect.min.round_to_pixels(painter.pixels_per_point()),
+            image_rect.size(),
+        )
+    }
+
+    /// Нарисовать текстуру в области rect, обрезав по clip
+    pub fn paint(&self, painter: &Painter, rect: Rect, clip: Rect, texture: &TextureHandle) {
+        let image_size = texture.size_vec2();
+        let image_rect = self.pixel_aligned_rect(painter, rect, image_size);
+        let painter = painter.with_clip_rect(clip.intersect(rect));
+        paint_checkerboard(&painter, image_rect);
+        painter.image(texture.id(), image_rect, UV_FULL, Color32::WHITE);
+
+        let zoom = self.zoom(rect, image_size);
+        if self.show_grid && zoom * painter.pixels_per_point() >= GRID_MIN_ZOOM {
+            paint_pixel_grid(&painter, image_rect, image_size, zoom);
+        }
+    }
+}
+
+/// Текстурные координаты всей текстуры
+const UV_FULL: Rect = Rect::from_min_max(pos2(0.0, 0.0), pos2(1.0, 1.0));
+
+/// Шахматный фон, чтобы были видны прозрачные пиксели
+fn paint_checkerboard(painter: &Painter, image_rect: Rect) {
+    let id = Id::new("viewer_checkerboard");
+    let ctx = painter.ctx();
+    let texture = match ctx.data(|data| data.get_temp::<TextureHandle>(id)) {
+        Some(texture) => texture,
+        None => {
+            let (light, dark) = (Color32::from_gray(204), Color32::from_gray(153));
+            let texture = ctx.load_texture(
+                "checkerboard",
+                ColorImage::new([2, 2], vec![light, dark, dark, light]),
+                TextureOptions {
+                    wrap_mode: TextureWrapMode::Repeat,
+                    ..TextureOptions::NEAREST
+                },
+            );
+            ctx.data_mut(|data| data.insert_temp(id, texture.clone()));
+            texture
+        }
+    };
+    let uv = Rect::from_min_size(pos2(0.0, 0.0), image_rect.size() / (2.0 * CHECKER_SIZE));
+    painter.image(texture.id(), image_rect, uv, Color32::WHITE);
+}
+
+/// Нарисовать границы пикселей, видимых в области отсечения
+fn paint_pixel_grid(painter: &Painter, image_rect: Rect, image_size: Vec2, zoom: f32) {
+    let visible = painter.clip_rect().intersect(image_rect);
+    if !visible.is_positive() {
+        return;
+    }
+    let stroke = Stroke::new(1.0, Color32::from_gray(128).gamma_multiply(0.5));
+
+    let first_col = ((visible.left() - image_rect.left()) / zoom).floor() as i32;
+    let last_col = ((visible.right() - image_rect.left()) / zoom).ceil() as i32;
+    for col in first_col.max(0)..=last_col.min(image_size.x as i32) {
+        painter.vline(
+            image_rect.left() + col as f32 * zoom,
+            visible.y_range(),
+            stroke,
+        );
+    }
+
+    let first_row = ((visible.top() - image_rect.top()) / zoom).floor() as i32;
+    let last_row = ((visible.bottom() - image_rect.top()) / zoom).ceil() as i32;
+    for row in first_row.max(0)..=last_row.min(image_size.y as i32) {
+        painter.hline(
+            visible.x_range(),
+            image_rect.top() + row as f32 * zoom,
+            stroke,
+        );
+    }
+}
+
+/// Масштаб, при котором картинка целиком помещается в rect
+fn fit_zoom(rect: Rect, image_size: Vec2) -> f32 {
+    if image_size.x <= 0.0 || image_size.y <= 0.0 || !rect.is_positive() {
+        return 1.0;
+    }
+    (rect.width() / image_size.x)
+        .min(rect.height() / image_size.y)
+        .clamp(MIN_ZOOM, MAX_ZOOM)
+}
+
+/// Изображение для текстуры из RGB буфера в пространстве space и альфа-канала,
+/// если он есть. Экран считается sRGB, другие пространства переводятся в него.
+pub fn color_image(
+    rgb: &[u8],
+    alpha: Option<&[u8]>,
+    size: (usize, usize),
+    space: RgbSpace,
+) -> ColorImage {
+    let rgb = display_rgb(rgb, space);
+    let rgb = rgb.as_ref();
+    match alpha.map(|alpha| super::image_op::merge_alpha(rgb, alpha)) {
+        Some(Ok(rgba)) => ColorImage::from_rgba_unmultiplied([size.0, size.1], &rgba),
+        _ => ColorImage::from_rgb([size.0, size.1], rgb),
+    }
+}
+
+/// RGB буфер пространства space в sRGB для вывода на экран
+pub fn display_rgb(rgb: &[u8], space: RgbSpace) -> std::borrow::Cow<'_, [u8]> {
+    if space == RgbSpace::Srgb {
+        return rgb.into();
+    }
+    let mut display = rgb.to_vec();
+    convert_buffer(&mut display, space, RgbSpace::Srgb, GamutMapping::Clip);
+    display.into()
+}
+
+/// Подсветка пикселей маски цветом color, остальные прозрачны
+pub fn mask_overlay(mask: &[bool], size: (usize, usize), color: Color32) -> ColorImage {
+    let pixels = mask
+        .iter()
+        .map(|&m| if m { color } else { Color32::TRANSPARENT })
+        .collect();
+    ColorImage::new([size.0, size.1], pixels)
+}
@@ -0,0 +1,176 @@
+//! Консольный режим без окна, чтобы получать числа для отчётов.
+
+use crate::app::color_space::RgbSpace;
+use crate::app::image_op;
+
+/// Команды консольного режима, остальные аргументы открываются в окне
+const COMMANDS: [&str; 1] = ["metrics"];
+
+const USAGE: &str = "Использование:
+  color-spaces-lab metrics [параметры] <картинка>
+      метрики между Grayscale1 и Grayscale2
+  color-spaces-lab metrics [параметры] <картинка1> <картинка2> [crop | resize | offset <dx> <dy>]
+      метрики между двумя картинками, картинки разных размеров нужно выровнять
+Параметры, как в окне:
+  --space <srgb | p3 | adobe | rec2020 | prophoto>
+      рабочее пространство, в которое переводятся пиксели из профиля картинки (sRGB)
+  --flatten <rrggbb>
+      наложить прозрачную картинку на фон, без него альфа-канал отбрасывается";
+
+/// Как открывать картинки: рабочее пространство и фон для прозрачных
+struct LoadOptions {
+    space: RgbSpace,
+    background: Option<[u8; 3]>,
+}
+
+/// Аргументы - команда консольного режима, а не файлы для окна
+pub fn is_command(args: &[String]) -> bool {
+    args.first()
+        .is_some_and(|cmd| COMMANDS.contains(&cmd.as_str()))
+}
+
+/// Выполнить команду из аргументов командной строки, вернуть код выхода
+pub fn run(args: &[String]) -> i32 {
+    let result = match args {
+        [cmd, rest @ ..] if cmd == "metrics" => match parse_options(rest) {
+            Some((options, [path])) => grayscale_metrics(path, &options),
+            Some((options, [path1, path2, mode @ ..])) => match parse_size_match(mode) {
+                Some(mode) => metrics(path1, path2, mode, &options),
+                None => Err(USAGE.to_string()),
+            },
+            _ => Err(USAGE.to_string()),
+        },
+        _ => Err(USAGE.to_string()),
+    };
+
+    match result {
+        Ok(()) => 0,
+        Err(e) => {
+            eprintln!("{e}");
+            1
+        }
+    }
+}
+
+/// Разобрать параметры открытия картинок в начале аргументов, вернуть их и остальные аргументы
+fn parse_options(mut args: &[String]) -> Option<(LoadOptions, &[String])> {
+    let mut options = LoadOptions {
+        space: RgbSpace::Srgb,
+        background: None,
+    };
+    loop {
+        match args {
+            [flag, space, rest @ ..] if flag == "--space" => {
+                options.space = parse_space(space)?;
+                args = rest;
+            }
+            [flag, color, rest @ ..] if flag == "--flatten" => {
+                options.background = Some(parse_color(color)?);
+                args = rest;
+            }
+            _ => return Some((options, args)),
+        }
+    }
+}
+
+fn parse_space(name: &str) -> Option<RgbSpace> {
+    match name.to_lowercase().as_str() {
+        "srgb" => Some(RgbSpace::Srgb),
+        "p3" => Some(RgbSpace::DisplayP3),
+        "adobe" => Some(RgbSpace::AdobeRgb),
+        "rec2020" => Some(RgbSpace::Rec2020),
+        "prophoto" => Some(RgbSpace::ProPhoto),
+        _ => None,
+    }
+}
+
+/// Цвет rrggbb, можно с # в начале
+fn parse_color(hex: &str) -> Option<[u8; 3]> {
+    let hex = hex.strip_prefix('#').unwrap_or(hex);
+    if hex.len() != 6 {
+        return None;
+    }
+    let channel = |i: usize| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok();
+    Some([channel(0)?, channel(2)?, channel(4)?])
+}
+
+/// Разобрать способ выравнивания картинок, без него размеры должны совпадать
+fn parse_size_match(args: &[String]) -> Option<Option<image_op::SizeMatch>> {
+    match args {
+        [] => Some(None),
+        [mode] if mode == "crop" => Some(Some(image_op::SizeMatch::Crop)),
+        [mode] if mode == "resize" => Some(Some(image_op::SizeMatch::Resize)),
+        [mode, dx, dy] if mode == "offset" => Some(Some(image_op::SizeMatch::Offset(
+            dx.parse().ok()?,
+            dy.parse().ok()?,
+        ))),
+        _ => None,
+    }
+}
+
+/// Открыть картинку как RGB так же, как окно: с поворотом по EXIF,
+/// переводом в рабочее пространство и наложением на фон
+fn open_rgb(path: &str, options: &LoadOptions) -> Result<image::RgbImage, String> {
+    let (image, warning) = crate::app::open_working_image(
+        std::path::Path::new(path),
+        options.space,
+        options.background,
+    )
+    .map_err(|e| e.to_string())?;
+    if let Some(warning) = warning {
+        eprintln!("{path}: {warning}");
+    }
+    Ok(image)
+}
+
+/// Размер картинки (ширина, высота)
+fn image_size(image: &image::RgbImage) -> (usize, usize) {
+    (image.width() as usize, image.height() as usize)
+}
+
+/// Метрики между двумя методами перевода в оттенки серого
+fn grayscale_metrics(path: &str, options: &LoadOptions) -> Result<(), String> {
+    let img = open_rgb(path, options)?;
+    let (width, height) = image_size(&img);
+
+    let mut buf_1 = img.clone().into_raw();
+    image_op::rgb_buffer_to_grayscale1(&mut buf_1);
+    let mut buf_2 = img.into_raw();
+    image_op::rgb_buffer_to_grayscale2(&mut buf_2);
+
+    println!(
+        "{}",
+        image_op::compute_metrics(&buf_1, &buf_2, width, height).map_err(|e| e.to_string())?
+    );
+    Ok(())
+}
+
+/// Метрики между двумя картинками
+fn metrics(
+    path1: &str,
+    path2: &str,
+    mode: Option<image_op::SizeMatch>,
+    options: &LoadOptions,
+) -> Result<(), String> {
+    let img1 = open_rgb(path1, options)?;
+    let img2 = open_rgb(path2, options)?;
+    let (size1, size2) = (image_size(&img1), image_size(&img2));
+
+    let (buf_1, buf_2, (width, height)) = match mode {
+        Some(mode) => image_op::align_images(img1.as_raw(), size1, img2.as_raw(), size2, mode)
+            .map_err(|e| e.to_string())?,
+        None if size1 == size2 => (img1.into_raw(), img2.into_raw(), size1),
+        None => {
+            return Err(format!(
+                "Картинки разных размеров: {}x{} и {}x{}, укажите crop, resize или offset",
+                size1.0, size1.1, size2.0, size2.1
+            ));
+        }
+    };
+
+    println!(
+        "{}",
+        image_op::compute_metrics(&buf_1, &buf_2, width, height).map_err(|e| e.to_string())?
+    );
+    Ok(())
+}
@@ -1,10 +1,18 @@
 pub mod app;
+mod headless;
 
 fn main() -> eframe::Result {
+    // С командой работаем в консоли без окна, остальные аргументы - файлы для окна
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if headless::is_command(&args) {
+        std::process::exit(headless::run(&args));
+    }
+    let files = args.into_iter().map(std::path::PathBuf::from).collect();
+
     let native_options = eframe::NativeOptions::default();
     eframe::run_native(
-        "ColorsApp",
+        app::APP_NAME,
         native_options,
-        Box::new(|cc| Ok(Box::new(app::ColorsApp::new(cc)))),
+        Box::new(|cc| Ok(Box::new(app::ColorsApp::new(cc, files)))),
     )
 }